@@ -0,0 +1,43 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use payments_engine::PaymentsEngine;
+use std::fs;
+
+// Compares the byte-level fast path with deserializing every record by serde.
+fn csv_parsing(c: &mut Criterion) {
+    let transactions_csv = fs::read("deposits-withdrawals-100k.csv").unwrap();
+    let rows = transactions_csv
+        .iter()
+        .filter(|&&byte| byte == b'\n')
+        .count();
+
+    let mut group = c.benchmark_group("process_csv");
+    group.throughput(Throughput::Elements(rows as u64));
+    group.sample_size(20);
+    group.bench_function(BenchmarkId::new("fast path", rows), |b| {
+        b.iter(|| {
+            let mut payments_engine = PaymentsEngine::new();
+            payments_engine::process_csv(transactions_csv.as_slice(), &mut payments_engine)
+                .unwrap();
+            payments_engine
+        })
+    });
+    group.bench_function(BenchmarkId::new("serde", rows), |b| {
+        b.iter(|| {
+            let mut payments_engine = PaymentsEngine::new();
+            payments_engine::process_csv_with_serde(
+                transactions_csv.as_slice(),
+                &mut payments_engine,
+            )
+            .unwrap();
+            payments_engine
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, csv_parsing);
+criterion_main!(benches);
@@ -0,0 +1,75 @@
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::BenchmarkId;
+use criterion::Criterion;
+use criterion::Throughput;
+use payments_engine::ClientId;
+use payments_engine::Generator;
+use payments_engine::PaymentsEngine;
+
+const ROWS: u64 = 100_000;
+const SEED: u64 = 1;
+
+fn generate(generator: Generator) -> Vec<u8> {
+    let mut transactions_csv = Vec::new();
+    generator.write_csv(&mut transactions_csv).unwrap();
+    transactions_csv
+}
+
+fn process_csv(c: &mut Criterion) {
+    let data_sets = [
+        (
+            "deposits-withdrawals",
+            generate(Generator::new(ROWS, SEED).with_clients(1000)),
+        ),
+        (
+            "disputes-errors",
+            generate(
+                Generator::new(ROWS, SEED)
+                    .with_clients(1000)
+                    .with_disputes(0.01)
+                    .with_chargebacks(0.1)
+                    .with_errors(0.01),
+            ),
+        ),
+    ];
+
+    let mut group = c.benchmark_group("processing");
+    group.throughput(Throughput::Elements(ROWS));
+    group.sample_size(20);
+    for (name, transactions_csv) in &data_sets {
+        group.bench_function(BenchmarkId::new(*name, ROWS), |b| {
+            b.iter(|| {
+                let mut payments_engine = PaymentsEngine::new();
+                payments_engine::process_csv(transactions_csv.as_slice(), &mut payments_engine)
+                    .unwrap();
+                payments_engine
+            })
+        });
+    }
+    group.finish();
+}
+
+fn write_account_states_to_csv(c: &mut Criterion) {
+    let mut group = c.benchmark_group("write_account_states_to_csv");
+    let client_counts: [ClientId; 2] = [100, 10_000];
+    for clients in client_counts {
+        let transactions_csv = generate(Generator::new(ROWS, SEED).with_clients(clients));
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(transactions_csv.as_slice(), &mut payments_engine).unwrap();
+
+        group.throughput(Throughput::Elements(u64::from(clients)));
+        group.bench_function(BenchmarkId::new("clients", clients), |b| {
+            b.iter(|| {
+                let mut output = Vec::new();
+                payments_engine::write_account_states_to_csv(&payments_engine, &mut output)
+                    .unwrap();
+                output
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, process_csv, write_account_states_to_csv);
+criterion_main!(benches);
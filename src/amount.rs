@@ -0,0 +1,428 @@
+#[cfg(feature = "decimal")]
+pub use decimal::Amount;
+#[cfg(not(feature = "decimal"))]
+pub use fixed::Amount;
+#[cfg(not(feature = "decimal"))]
+pub use fixed::ParseAmountError;
+
+use crate::Rejection;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serializer;
+
+/// Four decimal places stored as a number of ten-thousandths. All the arithmetic is checked: there
+/// are no operators, the `checked_*` methods return None on overflow and the `saturating_*` ones
+/// stop at the bounds. Amounts are written with their four decimal places, e.g. `1.5000`.
+#[cfg(not(feature = "decimal"))]
+mod fixed {
+    use serde::de;
+    use serde::Deserialize;
+    use serde::Deserializer;
+    use serde::Serialize;
+    use serde::Serializer;
+    use std::error::Error;
+    use std::fmt;
+    use std::str::FromStr;
+
+    const SCALE: u32 = 4;
+    const UNIT: i64 = 10_i64.pow(SCALE);
+
+    #[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct Amount {
+        ten_thousandths: i64,
+    }
+
+    impl Amount {
+        pub const ZERO: Amount = Amount::from_ten_thousandths(0);
+        pub const MAX: Amount = Amount::from_ten_thousandths(i64::MAX);
+        pub const MIN: Amount = Amount::from_ten_thousandths(i64::MIN);
+
+        /// The amount of `ten_thousandths` / 10^4.
+        pub const fn from_ten_thousandths(ten_thousandths: i64) -> Self {
+            Amount { ten_thousandths }
+        }
+
+        pub const fn ten_thousandths(self) -> i64 {
+            self.ten_thousandths
+        }
+
+        pub fn is_zero(self) -> bool {
+            self.ten_thousandths == 0
+        }
+
+        // Zero counts as positive, as it does for `Decimal`.
+        pub fn is_sign_positive(self) -> bool {
+            self.ten_thousandths >= 0
+        }
+
+        pub fn is_sign_negative(self) -> bool {
+            self.ten_thousandths < 0
+        }
+
+        pub fn checked_abs(self) -> Option<Amount> {
+            self.map(i64::checked_abs)
+        }
+
+        pub fn checked_add(self, other: Amount) -> Option<Amount> {
+            self.map(|units| units.checked_add(other.ten_thousandths))
+        }
+
+        pub fn checked_sub(self, other: Amount) -> Option<Amount> {
+            self.map(|units| units.checked_sub(other.ten_thousandths))
+        }
+
+        pub fn checked_neg(self) -> Option<Amount> {
+            self.map(i64::checked_neg)
+        }
+
+        pub fn saturating_add(self, other: Amount) -> Amount {
+            Amount::from_ten_thousandths(self.ten_thousandths.saturating_add(other.ten_thousandths))
+        }
+
+        pub fn saturating_sub(self, other: Amount) -> Amount {
+            Amount::from_ten_thousandths(self.ten_thousandths.saturating_sub(other.ten_thousandths))
+        }
+
+        fn map(self, f: impl FnOnce(i64) -> Option<i64>) -> Option<Amount> {
+            f(self.ten_thousandths).map(Amount::from_ten_thousandths)
+        }
+
+        /// `percentage`% of the amount, rounded half to even to four decimal places.
+        pub fn checked_percentage(self, percentage: Amount) -> Option<Amount> {
+            let divisor = 100 * i128::from(UNIT);
+            let product = i128::from(self.ten_thousandths) * i128::from(percentage.ten_thousandths);
+            let quotient = product.div_euclid(divisor);
+            let remainder = product.rem_euclid(divisor);
+            let round_up = match (2 * remainder).cmp(&divisor) {
+                std::cmp::Ordering::Less => false,
+                std::cmp::Ordering::Equal => quotient % 2 != 0,
+                std::cmp::Ordering::Greater => true,
+            };
+            let rounded = quotient + i128::from(round_up);
+            i64::try_from(rounded)
+                .ok()
+                .map(Amount::from_ten_thousandths)
+        }
+
+        // Accepts an optional sign, digits with an optional fractional part and an optional
+        // exponent - as long as the result has no more than four decimal places.
+        fn parse(s: &[u8]) -> Result<Self, ParseAmountError> {
+            let (negative, s) = match s {
+                [b'-', rest @ ..] => (true, rest),
+                [b'+', rest @ ..] => (false, rest),
+                _ => (false, s),
+            };
+            let (number, exponent) = match s.iter().position(|&byte| matches!(byte, b'e' | b'E')) {
+                Some(e) => (&s[..e], Some(&s[e + 1..])),
+                None => (s, None),
+            };
+            let exponent: i32 = match exponent {
+                Some(exponent) => std::str::from_utf8(exponent)
+                    .ok()
+                    .and_then(|exponent| exponent.parse().ok())
+                    .ok_or(ParseAmountError::Invalid)?,
+                None => 0,
+            };
+            let (integer, fraction) = match number.iter().position(|&byte| byte == b'.') {
+                Some(dot) => (&number[..dot], &number[dot + 1..]),
+                None => (number, &[][..]),
+            };
+            if integer.is_empty() && fraction.is_empty() {
+                return Err(ParseAmountError::Invalid);
+            }
+
+            let mut digits: i128 = 0;
+            for &byte in integer.iter().chain(fraction) {
+                if !byte.is_ascii_digit() {
+                    return Err(ParseAmountError::Invalid);
+                }
+                digits = digits
+                    .checked_mul(10)
+                    .and_then(|digits| digits.checked_add(i128::from(byte - b'0')))
+                    .ok_or(ParseAmountError::Overflow)?;
+            }
+            // The digits are in units of 10^-scale, to be brought to 10^-4.
+            let mut scale = fraction.len() as i64 - i64::from(exponent);
+            while scale > i64::from(SCALE) && digits != 0 {
+                if digits % 10 != 0 {
+                    return Err(ParseAmountError::TooPrecise);
+                }
+                digits /= 10;
+                scale -= 1;
+            }
+            while scale < i64::from(SCALE) && digits != 0 {
+                digits = digits.checked_mul(10).ok_or(ParseAmountError::Overflow)?;
+                scale += 1;
+            }
+            if negative {
+                digits = -digits;
+            }
+            i64::try_from(digits)
+                .map(Amount::from_ten_thousandths)
+                .map_err(|_| ParseAmountError::Overflow)
+        }
+
+        pub(crate) fn parse_ascii(s: &[u8]) -> Option<Self> {
+            Self::parse(s).ok()
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub enum ParseAmountError {
+        Invalid,
+        TooPrecise,
+        Overflow,
+    }
+
+    impl fmt::Display for ParseAmountError {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let error = match self {
+                ParseAmountError::Invalid => "invalid amount",
+                ParseAmountError::TooPrecise => "amount has more than four decimal places",
+                ParseAmountError::Overflow => "amount out of range",
+            };
+            f.write_str(error)
+        }
+    }
+
+    impl Error for ParseAmountError {}
+
+    impl FromStr for Amount {
+        type Err = ParseAmountError;
+
+        fn from_str(s: &str) -> Result<Self, Self::Err> {
+            Self::parse(s.as_bytes())
+        }
+    }
+
+    // With its four decimal places - `2.0000`, `-0.5000`.
+    impl fmt::Display for Amount {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            let sign = if self.ten_thousandths < 0 { "-" } else { "" };
+            let units = self.ten_thousandths.unsigned_abs();
+            let unit = UNIT as u64;
+            write!(f, "{}{}.{:04}", sign, units / unit, units % unit)
+        }
+    }
+
+    impl Serialize for Amount {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.collect_str(self)
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Amount {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            deserializer.deserialize_str(AmountVisitor)
+        }
+    }
+
+    struct AmountVisitor;
+
+    impl de::Visitor<'_> for AmountVisitor {
+        type Value = Amount;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("an amount with up to four decimal places")
+        }
+
+        fn visit_str<E: de::Error>(self, value: &str) -> Result<Amount, E> {
+            value.parse().map_err(E::custom)
+        }
+
+        fn visit_i64<E: de::Error>(self, value: i64) -> Result<Amount, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        fn visit_u64<E: de::Error>(self, value: u64) -> Result<Amount, E> {
+            self.visit_str(&value.to_string())
+        }
+
+        // Floats are taken as their shortest decimal representation, as `Decimal` does.
+        fn visit_f64<E: de::Error>(self, value: f64) -> Result<Amount, E> {
+            self.visit_str(&value.to_string())
+        }
+    }
+}
+
+#[cfg(feature = "decimal")]
+mod decimal {
+    pub type Amount = rust_decimal::Decimal;
+}
+
+// The shortest form of the amount, e.g. `1.5` rather than `1.50` - fixed-point amounts have only
+// the one form.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn canonical(amount: Amount) -> Amount {
+    amount
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn canonical(amount: Amount) -> Amount {
+    amount.normalize()
+}
+
+// The amount with the four decimal places it is written with. A `Decimal` would be written with
+// those it happens to have otherwise.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn fixed_scale(amount: Amount) -> Amount {
+    amount
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn fixed_scale(mut amount: Amount) -> Amount {
+    amount.rescale(4);
+    amount
+}
+
+// For the amounts written out as they are kept, e.g. the balances of an account.
+pub(crate) fn serialize<S: Serializer>(amount: &Amount, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(&fixed_scale(*amount))
+}
+
+// The percentage part of a fee, rounded to four decimal places once the fee is complete.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn percentage(amount: Amount, percentage: Amount) -> Option<Amount> {
+    amount.checked_percentage(percentage)
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn percentage(amount: Amount, percentage: Amount) -> Option<Amount> {
+    amount
+        .checked_mul(percentage)?
+        .checked_div(Amount::ONE_HUNDRED)
+}
+
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn round(amount: Amount) -> Amount {
+    amount
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn round(amount: Amount) -> Amount {
+    amount.round_dp(4).normalize()
+}
+
+// None when the sum is out of range. The fixed-point amounts are summed up exactly, no matter the
+// order - only the sum itself has to be in range.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+    // Summed wider so that only the total has to fit.
+    let sum: i128 = amounts
+        .into_iter()
+        .map(|amount| i128::from(amount.ten_thousandths()))
+        .sum();
+    i64::try_from(sum).ok().map(Amount::from_ten_thousandths)
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn checked_sum(amounts: impl IntoIterator<Item = Amount>) -> Option<Amount> {
+    amounts
+        .into_iter()
+        .try_fold(Amount::ZERO, |sum, amount| sum.checked_add(amount))
+}
+
+// None when the negated amount is out of range, as the most negative fixed-point amount is.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn checked_neg(amount: Amount) -> Option<Amount> {
+    amount.checked_neg()
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn checked_neg(amount: Amount) -> Option<Amount> {
+    Some(-amount)
+}
+
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn checked_abs(amount: Amount) -> Option<Amount> {
+    amount.checked_abs()
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn checked_abs(amount: Amount) -> Option<Amount> {
+    Some(amount.abs())
+}
+
+// Whether the balance can go up or down by the amount without overflowing.
+pub(crate) fn has_headroom(balance: Amount, amount: Amount) -> bool {
+    balance.checked_add(amount).is_some() && balance.checked_sub(amount).is_some()
+}
+
+// Transaction amounts are taken in their shortest form, as they are by the round trip through f64
+// that serde takes with `Decimal` reading a CSV.
+pub(crate) fn deserialize_shortest<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<Amount>, D::Error> {
+    Ok(Option::<Amount>::deserialize(deserializer)?.map(canonical))
+}
+
+/// Why a transaction is rejected for its amount being a number `Amount` can not hold, rather than
+/// not a number at all.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn unrepresentable(field: &[u8]) -> Option<Rejection> {
+    match std::str::from_utf8(field).ok()?.parse::<Amount>() {
+        Err(ParseAmountError::TooPrecise) => Some(Rejection::AmountTooPrecise),
+        Err(ParseAmountError::Overflow) => Some(Rejection::AmountOverflow),
+        _ => None,
+    }
+}
+
+// A `Decimal` holds any number of decimal places, amounts are taken by serde through f64 though.
+#[cfg(feature = "decimal")]
+pub(crate) fn unrepresentable(field: &[u8]) -> Option<Rejection> {
+    let amount: f64 = std::str::from_utf8(field).ok()?.parse().ok()?;
+    (amount.is_finite() && Amount::try_from(amount).is_err()).then_some(Rejection::AmountOverflow)
+}
+
+// The byte-level parsing of the fast path, None when left for serde. Decimals with up to 15
+// significant digits survive the round trip through f64 that serde takes with `Decimal`, so parsing
+// those directly gives the very same amounts. The fixed-point amounts are parsed exactly either way.
+#[cfg(not(feature = "decimal"))]
+pub(crate) fn parse_plain(field: &[u8]) -> Option<Amount> {
+    Amount::parse_ascii(field)
+}
+
+#[cfg(feature = "decimal")]
+pub(crate) fn parse_plain(field: &[u8]) -> Option<Amount> {
+    const MAX_SIGNIFICANT_DIGITS: u32 = 15;
+    const MAX_SCALE: u32 = 28;
+
+    // Only non-negative amounts of digits with an optional fractional part, e.g. `12` or `0.5`.
+    // Trailing zeros of the fractional part are dropped, just like by the round trip through f64.
+    let (integer, fraction) = match field.iter().position(|&byte| byte == b'.') {
+        Some(dot) => (&field[..dot], &field[dot + 1..]),
+        None => {
+            let integer = std::str::from_utf8(field).ok()?;
+            if !integer.bytes().all(|byte| byte.is_ascii_digit()) {
+                return None;
+            }
+            return integer.parse::<u64>().ok().map(Amount::from);
+        }
+    };
+    let fraction = match fraction.iter().rposition(|&byte| byte != b'0') {
+        Some(last) => &fraction[..=last],
+        None => &[],
+    };
+    if integer.is_empty() {
+        return None;
+    }
+    let mut mantissa: u64 = 0;
+    let mut significant_digits = 0;
+    for &byte in integer.iter().chain(fraction) {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        if mantissa > 0 || byte != b'0' {
+            significant_digits += 1;
+        }
+        mantissa = mantissa * 10 + u64::from(byte - b'0');
+        if significant_digits > MAX_SIGNIFICANT_DIGITS {
+            return None;
+        }
+    }
+    let scale = fraction.len() as u32;
+    if scale > MAX_SCALE {
+        return None;
+    }
+    Some(Amount::from_i128_with_scale(i128::from(mantissa), scale))
+}
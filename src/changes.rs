@@ -0,0 +1,199 @@
+use crate::amount;
+use crate::Account;
+use crate::Amount;
+use crate::ClientId;
+use crate::TransactionId;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::str::FromStr;
+use std::sync::mpsc::Sender;
+
+/// How the balance changes are written.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeFormat {
+    #[default]
+    Csv,
+    /// A JSON object per line.
+    Jsonl,
+}
+
+impl fmt::Display for ChangeFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self {
+            ChangeFormat::Csv => "csv",
+            ChangeFormat::Jsonl => "jsonl",
+        };
+        f.write_str(format)
+    }
+}
+
+impl FromStr for ChangeFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(ChangeFormat::Csv),
+            "jsonl" => Ok(ChangeFormat::Jsonl),
+            _ => Err(format!("unknown change format '{}'", s)),
+        }
+    }
+}
+
+/// The balances of an account before and after a transaction applied to it.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BalanceChange {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub transaction_id: TransactionId,
+    pub available_before: Amount,
+    pub available_after: Amount,
+    pub held_before: Amount,
+    pub held_after: Amount,
+    pub total_before: Amount,
+    pub total_after: Amount,
+    pub locked_before: bool,
+    pub locked_after: bool,
+}
+
+// The balances of an account, those of an account yet to be opened being those it opens with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Balances {
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+}
+
+impl Balances {
+    const OPENING: Balances = Balances {
+        available: Amount::ZERO,
+        held: Amount::ZERO,
+        total: Amount::ZERO,
+        locked: false,
+    };
+
+    pub(crate) fn of(account: Option<&Account>) -> Self {
+        account.map_or(Balances::OPENING, |account| Balances {
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+        })
+    }
+}
+
+enum Sink {
+    Csv(Box<csv::Writer<Box<dyn Write + Send>>>),
+    Jsonl(Box<dyn Write + Send>),
+    Channel(Sender<BalanceChange>),
+}
+
+/// Emits a `BalanceChange` for every account a transaction changes, for downstream systems to
+/// mirror the balances - see `PaymentsEngine::with_change_feed`.
+pub struct ChangeFeed {
+    sink: Sink,
+    flush_each: bool,
+    // The first change that failed to be emitted, after which no more are.
+    pub(crate) error: Option<io::Error>,
+}
+
+impl ChangeFeed {
+    /// Writes the changes to the output, e.g. a file or stdout. The output is only flushed at the
+    /// end of `process_csv` or by `PaymentsEngine::flush_changes`, unless `with_flush_each`.
+    pub fn to_writer(output: impl Write + Send + 'static, format: ChangeFormat) -> Self {
+        let output: Box<dyn Write + Send> = Box::new(output);
+        let sink = match format {
+            ChangeFormat::Csv => Sink::Csv(Box::new(csv::Writer::from_writer(output))),
+            ChangeFormat::Jsonl => Sink::Jsonl(output),
+        };
+        ChangeFeed {
+            sink,
+            flush_each: false,
+            error: None,
+        }
+    }
+
+    /// Sends the changes down the channel. The receiver hanging up fails the feed.
+    pub fn to_channel(sender: Sender<BalanceChange>) -> Self {
+        ChangeFeed {
+            sink: Sink::Channel(sender),
+            flush_each: false,
+            error: None,
+        }
+    }
+
+    /// Flushes the output after the changes of each transaction, for them to reach downstream
+    /// right away, e.g. when serving.
+    pub fn with_flush_each(mut self) -> Self {
+        self.flush_each = true;
+        self
+    }
+
+    // Emits the changes of an applied transaction, the accounts it did not change left out.
+    pub(crate) fn emit(
+        &mut self,
+        transaction_id: TransactionId,
+        balances: impl Iterator<Item = (ClientId, Balances, Balances)>,
+    ) {
+        if self.error.is_some() {
+            return;
+        }
+        let mut emitted = false;
+        for (client_id, before, after) in balances {
+            if before == after {
+                continue;
+            }
+            let change = BalanceChange {
+                client_id,
+                transaction_id,
+                available_before: amount::fixed_scale(before.available),
+                available_after: amount::fixed_scale(after.available),
+                held_before: amount::fixed_scale(before.held),
+                held_after: amount::fixed_scale(after.held),
+                total_before: amount::fixed_scale(before.total),
+                total_after: amount::fixed_scale(after.total),
+                locked_before: before.locked,
+                locked_after: after.locked,
+            };
+            if let Err(e) = self.send(change) {
+                self.error = Some(e);
+                return;
+            }
+            emitted = true;
+        }
+        if emitted && self.flush_each {
+            if let Err(e) = self.flush() {
+                self.error = Some(e);
+            }
+        }
+    }
+
+    fn send(&mut self, change: BalanceChange) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Csv(wtr) => Ok(wtr.serialize(change)?),
+            Sink::Jsonl(output) => {
+                serde_json::to_writer(&mut *output, &change)?;
+                output.write_all(b"\n")
+            }
+            Sink::Channel(sender) => sender.send(change).map_err(|_| {
+                io::Error::new(
+                    io::ErrorKind::BrokenPipe,
+                    "the receiver of the balance changes hung up",
+                )
+            }),
+        }
+    }
+
+    pub(crate) fn flush(&mut self) -> io::Result<()> {
+        match &mut self.sink {
+            Sink::Csv(wtr) => wtr.flush(),
+            Sink::Jsonl(output) => output.flush(),
+            Sink::Channel(_) => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,559 @@
+use crate::fnv1a;
+use crate::Account;
+use crate::Amount;
+use crate::Applied;
+use crate::AppliedTransaction;
+use crate::ClientId;
+use crate::Invariant;
+use crate::Ledger;
+use crate::LimitUsage;
+use crate::PaymentsEngine;
+use crate::Recorded;
+use crate::Stats;
+use crate::TransactionId;
+use crate::Violation;
+use crate::FNV1A_START;
+use serde::Deserialize;
+use serde::Serialize;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::error::Error;
+use std::fs;
+use std::fs::File;
+use std::fs::OpenOptions;
+use std::io;
+use std::io::BufRead;
+use std::io::Read;
+use std::io::Seek;
+use std::io::SeekFrom;
+use std::io::Write;
+use std::path::PathBuf;
+
+/// Records the state of the engine along with the position in the input every so many rows, for
+/// a run that dies to be resumed from there rather than from scratch - see
+/// `process_csv_with_checkpoints`.
+///
+/// The checkpoint file holds a line of JSON per checkpoint: the state in full, then what changed
+/// since the checkpoint before, for the time a checkpoint takes not to grow with the state. Once
+/// the changes outgrow the full state, it is written again in a file of its own swapped in whole,
+/// so a crash never leaves it half-written - a change half-written at the end is left out. Each
+/// checkpoint carries a hash of the input up to its position, for a run not to be resumed on
+/// another input.
+///
+/// It covers the accounts, the ledger, the limit usage, the invariant violations and the run
+/// summary - not a statement, nor what was already written to snapshots, the change feed or a
+/// database.
+pub struct Checkpoints {
+    path: PathBuf,
+    interval: u64,
+    resume: bool,
+}
+
+impl Checkpoints {
+    /// Records a checkpoint to the file every `rows` rows, and at the end of the input.
+    pub fn new(path: impl Into<PathBuf>, rows: u64) -> Self {
+        Checkpoints {
+            path: path.into(),
+            interval: rows.max(1),
+            resume: false,
+        }
+    }
+
+    /// Starts from the checkpoint in the file, if there is one.
+    pub fn with_resume(mut self) -> Self {
+        self.resume = true;
+        self
+    }
+
+    fn invalid(&self, e: impl std::fmt::Display) -> Box<dyn Error> {
+        format!("invalid checkpoint {}: {}", self.path.display(), e).into()
+    }
+}
+
+/// What changed since the last checkpoint of the run, kept by the engine as it goes.
+#[derive(Default)]
+pub(crate) struct CheckpointChanges {
+    // The transactions each account was changed by.
+    accounts: HashMap<ClientId, HashSet<TransactionId>>,
+    transactions: HashSet<TransactionId>,
+}
+
+impl CheckpointChanges {
+    pub(crate) fn record(&mut self, touched: [Option<ClientId>; 3], id: TransactionId) {
+        for client_id in touched.into_iter().flatten() {
+            self.accounts.entry(client_id).or_default().insert(id);
+        }
+        self.transactions.insert(id);
+    }
+}
+
+// The hash of the input up to a position.
+#[derive(Clone, Copy)]
+struct InputPrefix {
+    bytes: u64,
+    hash: u64,
+}
+
+impl InputPrefix {
+    const EMPTY: InputPrefix = InputPrefix {
+        bytes: 0,
+        hash: FNV1A_START,
+    };
+
+    // Hashes the input on up to the byte, leaving it where it was. The bytes are read again, as
+    // the CSV reader keeps those it has read ahead to itself.
+    fn extend<R: Read + Seek>(&mut self, input: &mut R, byte: u64) -> io::Result<()> {
+        let reading = input.stream_position()?;
+        input.seek(SeekFrom::Start(self.bytes))?;
+        let mut rest = input.by_ref().take(byte - self.bytes);
+        let mut buffer = vec![0; 64 * 1024];
+        loop {
+            let read = rest.read(&mut buffer)?;
+            if read == 0 {
+                break;
+            }
+            self.hash = fnv1a(self.hash, &buffer[..read]);
+            self.bytes += read as u64;
+        }
+        input.seek(SeekFrom::Start(reading))?;
+        Ok(())
+    }
+}
+
+/// The checkpoints of a run, in the file given by `Checkpoints`.
+pub(crate) struct Recording<'a, R> {
+    checkpoints: &'a Checkpoints,
+    input: InputPrefix,
+    extend_input: fn(&mut InputPrefix, &mut R, u64) -> io::Result<()>,
+    // The size of the full state at the start of the file and of the changes after it, once the
+    // run has written it.
+    written: Option<(u64, u64)>,
+    // The invariant violations in the last checkpoint.
+    violations: usize,
+}
+
+impl<'a, R: Read + Seek> Recording<'a, R> {
+    pub(crate) fn new(checkpoints: &'a Checkpoints) -> Self {
+        Recording {
+            checkpoints,
+            input: InputPrefix::EMPTY,
+            extend_input: InputPrefix::extend,
+            written: None,
+            violations: 0,
+        }
+    }
+
+    // Restores the engine from the checkpoint when resuming from one, telling the rows processed
+    // and where the next one starts - once the input is found the same up to there.
+    pub(crate) fn restore(
+        &mut self,
+        payments_engine: &mut PaymentsEngine,
+        input: &mut R,
+    ) -> Result<Option<(u64, csv::Position)>, Box<dyn Error>> {
+        let checkpoints = self.checkpoints;
+        if !checkpoints.resume {
+            return Ok(None);
+        }
+        let file = match File::open(&checkpoints.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+        let mut file = io::BufReader::new(file);
+        let mut line = Vec::new();
+        file.read_until(b'\n', &mut line)?;
+        let checkpoint: Checkpoint =
+            serde_json::from_slice(&line).map_err(|e| checkpoints.invalid(e))?;
+        let mut changes = Vec::new();
+        loop {
+            line.clear();
+            file.read_until(b'\n', &mut line)?;
+            // Changes cut short by a crash are not part of the checkpoint.
+            if line.last() != Some(&b'\n') {
+                break;
+            }
+            let changed: Changes =
+                serde_json::from_slice(&line).map_err(|e| checkpoints.invalid(e))?;
+            changes.push(changed);
+        }
+
+        let (byte, hash) = changes
+            .last()
+            .map_or((checkpoint.byte, checkpoint.hash), |changed| {
+                (changed.byte, changed.hash)
+            });
+        input.seek(SeekFrom::Start(0))?;
+        let mut consumed = InputPrefix::EMPTY;
+        consumed.extend(input, byte)?;
+        if consumed.bytes != byte || consumed.hash != hash {
+            return Err(format!(
+                "checkpoint {} was made from a different input",
+                checkpoints.path.display()
+            )
+            .into());
+        }
+        self.input = consumed;
+
+        let mut restored = checkpoint.restore(payments_engine);
+        for changed in changes {
+            restored = changed.restore(payments_engine);
+        }
+        Ok(Some(restored))
+    }
+}
+
+impl<R: Read> Recording<'_, R> {
+    pub(crate) fn is_due(&self, rows: u64) -> bool {
+        rows.is_multiple_of(self.checkpoints.interval)
+    }
+
+    // Appends what changed since the last checkpoint, or writes the state in full first and once
+    // the changes outgrow it.
+    pub(crate) fn record(
+        &mut self,
+        payments_engine: &mut PaymentsEngine,
+        rows: u64,
+        rdr: &mut csv::Reader<R>,
+    ) -> io::Result<()> {
+        let position = rdr.position().clone();
+        (self.extend_input)(&mut self.input, rdr.get_mut(), position.byte())?;
+        let hash = self.input.hash;
+        match (payments_engine.checkpoint_changes.take(), self.written) {
+            (Some(changes), Some((full, changed))) if changed <= full => {
+                let changes = Changes::of(
+                    payments_engine,
+                    rows,
+                    &position,
+                    hash,
+                    &changes,
+                    self.violations,
+                );
+                self.written = Some((full, changed + self.append(&changes)?));
+            }
+            _ => {
+                let checkpoint = Checkpoint::of(payments_engine, rows, &position, hash);
+                self.written = Some((self.replace(&checkpoint)?, 0));
+            }
+        }
+        payments_engine.checkpoint_changes = Some(CheckpointChanges::default());
+        self.violations = payments_engine.violations.len();
+        Ok(())
+    }
+
+    // Writes the full state next to the previous file and swaps it in once it is on disk.
+    fn replace(&self, checkpoint: &Checkpoint) -> io::Result<u64> {
+        let path = &self.checkpoints.path;
+        let mut written = path.clone().into_os_string();
+        written.push(".tmp");
+        let line = to_line(checkpoint)?;
+        let mut file = File::create(&written)?;
+        file.write_all(&line)?;
+        file.sync_all()?;
+        fs::rename(&written, path)?;
+        Ok(line.len() as u64)
+    }
+
+    fn append(&self, changes: &Changes) -> io::Result<u64> {
+        let line = to_line(changes)?;
+        let mut file = OpenOptions::new()
+            .append(true)
+            .open(&self.checkpoints.path)?;
+        file.write_all(&line)?;
+        file.sync_data()?;
+        Ok(line.len() as u64)
+    }
+}
+
+fn to_line(value: &impl Serialize) -> io::Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(value)?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+#[derive(Serialize, Deserialize)]
+struct Checkpoint<'a> {
+    rows: u64,
+    // Where the next record starts, and the hash of the input before it.
+    byte: u64,
+    line: u64,
+    record: u64,
+    hash: u64,
+    accounts: Vec<AccountState<'a>>,
+    ledger: Cow<'a, Ledger>,
+    limit_usage: Cow<'a, HashMap<ClientId, LimitUsage>>,
+    clock: u64,
+    violations: Cow<'a, [Violation]>,
+    reported_violations: Cow<'a, HashSet<(ClientId, Invariant)>>,
+    stats: Cow<'a, Stats>,
+    // The transactions processed so far, with idempotency on.
+    applied: Option<Vec<Recorded>>,
+    // How far the input has come in the dispute lifecycle of each transaction.
+    cursors: Vec<(TransactionId, u32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountState<'a> {
+    balances: AccountBalances,
+    transactions: Cow<'a, HashMap<TransactionId, AppliedTransaction>>,
+    disputes: Cow<'a, HashMap<TransactionId, Amount>>,
+}
+
+// The state of an account but for its transactions and disputes.
+#[derive(Serialize, Deserialize)]
+struct AccountBalances {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+    disputed_withdrawals: Amount,
+    credit_limit: Amount,
+    last_transaction: Option<TransactionId>,
+    changed_while_locked: bool,
+}
+
+impl AccountBalances {
+    fn of(account: &Account) -> Self {
+        AccountBalances {
+            client: account.client_id,
+            available: account.available,
+            held: account.held,
+            total: account.total,
+            locked: account.locked,
+            disputed_withdrawals: account.disputed_withdrawals,
+            credit_limit: account.credit_limit,
+            last_transaction: account.last_transaction,
+            changed_while_locked: account.changed_while_locked,
+        }
+    }
+
+    fn restore(self, account: &mut Account) {
+        account.available = self.available;
+        account.held = self.held;
+        account.total = self.total;
+        account.locked = self.locked;
+        account.disputed_withdrawals = self.disputed_withdrawals;
+        account.credit_limit = self.credit_limit;
+        account.last_transaction = self.last_transaction;
+        account.changed_while_locked = self.changed_while_locked;
+    }
+}
+
+impl<'a> Checkpoint<'a> {
+    fn of(
+        payments_engine: &'a PaymentsEngine,
+        rows: u64,
+        position: &csv::Position,
+        hash: u64,
+    ) -> Self {
+        let accounts = payments_engine
+            .accounts
+            .values()
+            .map(|account| AccountState {
+                balances: AccountBalances::of(account),
+                transactions: Cow::Borrowed(&account.transactions),
+                disputes: Cow::Borrowed(&account.disputes),
+            })
+            .collect();
+        Checkpoint {
+            rows,
+            byte: position.byte(),
+            line: position.line(),
+            record: position.record(),
+            hash,
+            accounts,
+            ledger: Cow::Borrowed(&payments_engine.ledger),
+            limit_usage: Cow::Borrowed(&payments_engine.limit_usage),
+            clock: payments_engine.clock,
+            violations: Cow::Borrowed(&payments_engine.violations),
+            reported_violations: Cow::Borrowed(&payments_engine.reported_violations),
+            stats: Cow::Borrowed(&payments_engine.stats),
+            applied: payments_engine
+                .applied
+                .as_ref()
+                .map(|applied| applied.records().collect()),
+            cursors: payments_engine
+                .applied
+                .iter()
+                .flat_map(Applied::cursors)
+                .collect(),
+        }
+    }
+
+    fn restore(self, payments_engine: &mut PaymentsEngine) -> (u64, csv::Position) {
+        payments_engine.accounts = self
+            .accounts
+            .into_iter()
+            .map(|state| {
+                let client_id = state.balances.client;
+                let mut account = Account::new(client_id, None);
+                state.balances.restore(&mut account);
+                account.transactions = state.transactions.into_owned();
+                account.disputes = state.disputes.into_owned();
+                (client_id, account)
+            })
+            .collect();
+        payments_engine.ledger = self.ledger.into_owned();
+        payments_engine.limit_usage = self.limit_usage.into_owned();
+        payments_engine.clock = self.clock;
+        payments_engine.violations = self.violations.into_owned();
+        payments_engine.reported_violations = self.reported_violations.into_owned();
+        payments_engine.stats = self.stats.into_owned();
+        if let Some(records) = self.applied {
+            let mut applied = Applied::default();
+            applied.extend(records);
+            applied.set_cursors(self.cursors);
+            payments_engine.applied = Some(applied);
+        }
+        (self.rows, position(self.byte, self.line, self.record))
+    }
+}
+
+fn position(byte: u64, line: u64, record: u64) -> csv::Position {
+    let mut position = csv::Position::new();
+    position.set_byte(byte).set_line(line).set_record(record);
+    position
+}
+
+// What changed since the checkpoint before: the accounts changed, with their transactions and
+// disputes changed, and the transactions processed. The ledger and the summary are small enough
+// to be taken whole.
+#[derive(Serialize, Deserialize)]
+struct Changes<'a> {
+    rows: u64,
+    byte: u64,
+    line: u64,
+    record: u64,
+    hash: u64,
+    accounts: Vec<AccountChanges>,
+    ledger: Cow<'a, Ledger>,
+    limit_usage: Vec<(ClientId, Cow<'a, LimitUsage>)>,
+    clock: u64,
+    // Those found since the checkpoint before.
+    violations: Cow<'a, [Violation]>,
+    stats: Cow<'a, Stats>,
+    applied: Vec<Recorded>,
+    cursors: Vec<(TransactionId, Option<u32>)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountChanges {
+    balances: AccountBalances,
+    transactions: Vec<(TransactionId, AppliedTransaction)>,
+    // Disputes settled since are left without an amount.
+    disputes: Vec<(TransactionId, Option<Amount>)>,
+}
+
+impl<'a> Changes<'a> {
+    fn of(
+        payments_engine: &'a PaymentsEngine,
+        rows: u64,
+        position: &csv::Position,
+        hash: u64,
+        changes: &CheckpointChanges,
+        violations: usize,
+    ) -> Self {
+        let accounts = changes
+            .accounts
+            .iter()
+            .filter_map(|(client_id, ids)| {
+                let account = payments_engine.accounts.get(client_id)?;
+                let transactions = ids
+                    .iter()
+                    .filter_map(|id| Some((*id, account.transactions.get(id)?.clone())))
+                    .collect();
+                let disputes = ids
+                    .iter()
+                    .map(|id| (*id, account.disputes.get(id).copied()))
+                    .collect();
+                Some(AccountChanges {
+                    balances: AccountBalances::of(account),
+                    transactions,
+                    disputes,
+                })
+            })
+            .collect();
+        let limit_usage = changes
+            .accounts
+            .keys()
+            .filter_map(|client_id| {
+                let usage = payments_engine.limit_usage.get(client_id)?;
+                Some((*client_id, Cow::Borrowed(usage)))
+            })
+            .collect();
+        let applied = payments_engine.applied.as_ref();
+        Changes {
+            rows,
+            byte: position.byte(),
+            line: position.line(),
+            record: position.record(),
+            hash,
+            accounts,
+            ledger: Cow::Borrowed(&payments_engine.ledger),
+            limit_usage,
+            clock: payments_engine.clock,
+            violations: Cow::Borrowed(&payments_engine.violations[violations..]),
+            stats: Cow::Borrowed(&payments_engine.stats),
+            applied: applied
+                .iter()
+                .flat_map(|applied| {
+                    changes
+                        .transactions
+                        .iter()
+                        .flat_map(|id| applied.records_of(*id))
+                })
+                .collect(),
+            cursors: applied
+                .iter()
+                .flat_map(|applied| {
+                    changes
+                        .transactions
+                        .iter()
+                        .map(|id| (*id, applied.cursor(*id)))
+                })
+                .collect(),
+        }
+    }
+
+    fn restore(self, payments_engine: &mut PaymentsEngine) -> (u64, csv::Position) {
+        for changed in self.accounts {
+            let client_id = changed.balances.client;
+            let account = payments_engine
+                .accounts
+                .entry(client_id)
+                .or_insert_with(|| Account::new(client_id, None));
+            changed.balances.restore(account);
+            account.transactions.extend(changed.transactions);
+            for (id, dispute) in changed.disputes {
+                match dispute {
+                    Some(amount) => account.disputes.insert(id, amount),
+                    None => account.disputes.remove(&id),
+                };
+            }
+        }
+        payments_engine.ledger = self.ledger.into_owned();
+        for (client_id, usage) in self.limit_usage {
+            payments_engine
+                .limit_usage
+                .insert(client_id, usage.into_owned());
+        }
+        payments_engine.clock = self.clock;
+        for violation in self.violations.iter() {
+            payments_engine
+                .reported_violations
+                .insert((violation.client_id, violation.invariant));
+        }
+        payments_engine
+            .violations
+            .extend_from_slice(&self.violations);
+        payments_engine.stats = self.stats.into_owned();
+        if let Some(applied) = &mut payments_engine.applied {
+            applied.extend(self.applied);
+            for (id, cursor) in self.cursors {
+                applied.set_cursor(id, cursor);
+            }
+        }
+        (self.rows, position(self.byte, self.line, self.record))
+    }
+}
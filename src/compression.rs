@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fmt;
+use std::fs::File;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+const GZIP_MAGIC_BYTES: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC_BYTES: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+/// How a CSV file is compressed. Reading or writing gzip and zstd needs the `gzip` and `zstd`
+/// features respectively.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    None,
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub fn from_magic_bytes(bytes: &[u8]) -> Self {
+        if bytes.starts_with(GZIP_MAGIC_BYTES) {
+            Compression::Gzip
+        } else if bytes.starts_with(ZSTD_MAGIC_BYTES) {
+            Compression::Zstd
+        } else {
+            Compression::None
+        }
+    }
+
+    pub fn from_extension(path: impl AsRef<Path>) -> Self {
+        match path
+            .as_ref()
+            .extension()
+            .and_then(|extension| extension.to_str())
+        {
+            Some("gz" | "gzip") => Compression::Gzip,
+            Some("zst" | "zstd") => Compression::Zstd,
+            _ => Compression::None,
+        }
+    }
+
+    fn unsupported(self) -> Box<dyn Error> {
+        format!("{} compression needs the `{}` feature", self, self).into()
+    }
+}
+
+impl fmt::Display for Compression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let compression = match self {
+            Compression::None => "none",
+            Compression::Gzip => "gzip",
+            Compression::Zstd => "zstd",
+        };
+        f.write_str(compression)
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Compression::None),
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("unknown compression '{}'", s)),
+        }
+    }
+}
+
+/// Opens a CSV file for reading, decompressing it on the fly when compressed - as told by its first
+/// bytes, or by its extension when they tell nothing.
+pub fn open_csv(path: impl AsRef<Path>) -> Result<(Box<dyn Read>, Compression), Box<dyn Error>> {
+    let mut file = BufReader::new(File::open(&path)?);
+    let compression = match Compression::from_magic_bytes(file.fill_buf()?) {
+        Compression::None => Compression::from_extension(&path),
+        compression => compression,
+    };
+    Ok((decompress(file, compression)?, compression))
+}
+
+pub fn decompress<'a>(
+    input: impl BufRead + 'a,
+    compression: Compression,
+) -> Result<Box<dyn Read + 'a>, Box<dyn Error>> {
+    match compression {
+        Compression::None => Ok(Box::new(input)),
+        // Concatenated gzip files are read as one, like `gunzip` does.
+        #[cfg(feature = "gzip")]
+        Compression::Gzip => Ok(Box::new(flate2::bufread::MultiGzDecoder::new(input))),
+        #[cfg(feature = "zstd")]
+        Compression::Zstd => Ok(Box::new(zstd::Decoder::with_buffer(input)?)),
+        #[allow(unreachable_patterns)]
+        compression => Err(compression.unsupported()),
+    }
+}
+
+/// Compresses everything written to it. The compressed stream is only complete once finished.
+pub enum CompressedWriter<W: Write> {
+    None(W),
+    #[cfg(feature = "gzip")]
+    Gzip(flate2::write::GzEncoder<W>),
+    #[cfg(feature = "zstd")]
+    Zstd(zstd::Encoder<'static, W>),
+}
+
+impl<W: Write> CompressedWriter<W> {
+    pub fn new(output: W, compression: Compression) -> Result<Self, Box<dyn Error>> {
+        match compression {
+            Compression::None => Ok(CompressedWriter::None(output)),
+            #[cfg(feature = "gzip")]
+            Compression::Gzip => Ok(CompressedWriter::Gzip(flate2::write::GzEncoder::new(
+                output,
+                flate2::Compression::default(),
+            ))),
+            #[cfg(feature = "zstd")]
+            Compression::Zstd => Ok(CompressedWriter::Zstd(zstd::Encoder::new(output, 0)?)),
+            #[allow(unreachable_patterns)]
+            compression => Err(compression.unsupported()),
+        }
+    }
+
+    /// Writes the end of the compressed stream and returns the underlying writer.
+    // Without the features there is nothing to match on but the uncompressed writer.
+    #[allow(clippy::infallible_destructuring_match)]
+    pub fn finish(self) -> io::Result<W> {
+        let mut output = match self {
+            CompressedWriter::None(output) => output,
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(encoder) => encoder.finish()?,
+            #[cfg(feature = "zstd")]
+            CompressedWriter::Zstd(encoder) => encoder.finish()?,
+        };
+        output.flush()?;
+        Ok(output)
+    }
+}
+
+impl<W: Write> Write for CompressedWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            CompressedWriter::None(output) => output.write(buf),
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(encoder) => encoder.write(buf),
+            #[cfg(feature = "zstd")]
+            CompressedWriter::Zstd(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            CompressedWriter::None(output) => output.flush(),
+            #[cfg(feature = "gzip")]
+            CompressedWriter::Gzip(encoder) => encoder.flush(),
+            #[cfg(feature = "zstd")]
+            CompressedWriter::Zstd(encoder) => encoder.flush(),
+        }
+    }
+}
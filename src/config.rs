@@ -0,0 +1,273 @@
+use crate::Amount;
+use crate::ChangeFormat;
+use crate::FeeSchedule;
+use crate::Limits;
+use crate::PaymentsEngine;
+use serde::Deserialize;
+use serde::Serialize;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+/// The policies of the engine in one place, e.g. read from a TOML file:
+///
+/// ```toml
+/// fees = "fees.toml"
+/// limits = "limits.toml"
+///
+/// [strictness]
+/// max_amount = "1000000"
+/// negative_amounts = "reject"
+/// locked_accounts = "reject"
+/// verify = "each-transaction"
+///
+/// [duplicates]
+/// idempotent = true
+///
+/// [disputes]
+/// withdrawals = false
+/// lock_on_chargeback = true
+///
+/// [output]
+/// summary = "json"
+/// changes = "jsonl"
+/// ```
+///
+/// Everything is optional, policies left out being the defaults of `PaymentsEngine::new`. The
+/// output formats are not the engine's to apply, but those of whatever writes its output.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct EngineConfig {
+    /// Fee schedule to charge deposits and withdrawals by - see `FeeSchedule`.
+    pub fees: Option<PathBuf>,
+    /// Per-client limits to enforce - see `Limits`.
+    pub limits: Option<PathBuf>,
+    pub strictness: Strictness,
+    pub duplicates: Duplicates,
+    pub disputes: DisputeRules,
+    pub output: OutputFormats,
+}
+
+/// What the engine rejects, and what it checks on the way.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Strictness {
+    /// Rejects deposits, withdrawals and transfers of more than this amount.
+    pub max_amount: Option<Amount>,
+    /// What becomes of deposits, withdrawals and transfers of negative amounts.
+    pub negative_amounts: NegativeAmounts,
+    /// Whether a locked account can still have its transactions disputed.
+    pub locked_accounts: LockedAccounts,
+    /// When to check the account invariants, if at all.
+    pub verify: Option<Verify>,
+}
+
+/// How transactions sent again are handled.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Duplicates {
+    /// Skips transactions processed before - see `PaymentsEngine::with_idempotency`.
+    pub idempotent: bool,
+}
+
+/// What can be disputed, and what a chargeback does - see `PaymentsEngine::with_dispute_rules`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct DisputeRules {
+    /// Withdrawals can be disputed, the withdrawn funds being held until resolved or charged back.
+    /// Otherwise disputes of withdrawals are rejected like those of unknown transactions.
+    pub withdrawals: bool,
+    /// A chargeback locks the account.
+    pub lock_on_chargeback: bool,
+}
+
+impl Default for DisputeRules {
+    fn default() -> Self {
+        DisputeRules {
+            withdrawals: true,
+            lock_on_chargeback: true,
+        }
+    }
+}
+
+/// What becomes of deposits, withdrawals and transfers of negative amounts - see
+/// `PaymentsEngine::with_negative_amounts`.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum NegativeAmounts {
+    /// Rejected as `negative_amount`.
+    #[default]
+    Reject,
+    /// Applied as their absolute value, e.g. a deposit of -5 as one of 5.
+    Absolute,
+}
+
+/// Whether a locked account can still have its transactions disputed - see
+/// `PaymentsEngine::with_locked_accounts`. Deposits, withdrawals and transfers of a locked account
+/// are rejected as `account_locked` either way, while the disputes opened before it was locked
+/// still get resolved or charged back.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum LockedAccounts {
+    /// New disputes are rejected as `account_locked` too.
+    #[default]
+    Reject,
+    /// New disputes are opened as for any account.
+    AllowDisputes,
+}
+
+/// The formats the output is written in.
+#[derive(Deserialize, Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct OutputFormats {
+    pub summary: SummaryFormat,
+    pub changes: ChangeFormat,
+}
+
+/// When the account invariants are checked.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Verify {
+    /// The accounts touched by each transaction, after it - see
+    /// `PaymentsEngine::with_invariant_checks`.
+    EachTransaction,
+    /// All of the accounts at the end of the run, by whoever runs it - see
+    /// `PaymentsEngine::check_invariants`.
+    EndOfRun,
+}
+
+impl fmt::Display for Verify {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let verify = match self {
+            Verify::EachTransaction => "each-transaction",
+            Verify::EndOfRun => "end-of-run",
+        };
+        f.write_str(verify)
+    }
+}
+
+impl FromStr for Verify {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "each-transaction" => Ok(Verify::EachTransaction),
+            "end-of-run" => Ok(Verify::EndOfRun),
+            _ => Err(format!(
+                "unknown verify '{}', expected each-transaction or end-of-run",
+                s
+            )),
+        }
+    }
+}
+
+/// How the run summary is written.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SummaryFormat {
+    #[default]
+    Text,
+    Json,
+}
+
+impl fmt::Display for SummaryFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let format = match self {
+            SummaryFormat::Text => "text",
+            SummaryFormat::Json => "json",
+        };
+        f.write_str(format)
+    }
+}
+
+impl FromStr for SummaryFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(format!("unknown summary format '{}'", s)),
+        }
+    }
+}
+
+impl EngineConfig {
+    /// Loads and validates the config. The paths in it are relative to the config file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let path = path.as_ref();
+        let mut config: EngineConfig = toml::from_str(&fs::read_to_string(path)?)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        for file in [&mut config.fees, &mut config.limits].into_iter().flatten() {
+            *file = dir.join(&*file);
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Parses and validates the config. The paths in it are relative to the working directory.
+    pub fn from_toml(config: &str) -> Result<Self, Box<dyn Error>> {
+        let config: EngineConfig = toml::from_str(config)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Checks the policies are valid, including the fee schedule and limits they refer to.
+    pub fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(max_amount) = self.strictness.max_amount {
+            if max_amount <= Amount::ZERO {
+                return Err("strictness.max_amount must be positive".into());
+            }
+        }
+        self.fee_schedule()?;
+        self.load_limits()?;
+        Ok(())
+    }
+
+    /// An engine applying the policies.
+    pub fn build(&self) -> Result<PaymentsEngine, Box<dyn Error>> {
+        let mut payments_engine = PaymentsEngine::new().with_dispute_rules(self.disputes);
+        if let Some(fee_schedule) = self.fee_schedule()? {
+            payments_engine = payments_engine.with_fee_schedule(fee_schedule);
+        }
+        if let Some(limits) = self.load_limits()? {
+            payments_engine = payments_engine.with_limits(limits);
+        }
+        if let Some(max_amount) = self.strictness.max_amount {
+            payments_engine = payments_engine.with_max_amount(max_amount);
+        }
+        payments_engine = payments_engine
+            .with_negative_amounts(self.strictness.negative_amounts)
+            .with_locked_accounts(self.strictness.locked_accounts);
+        if let Some(Verify::EachTransaction) = self.strictness.verify {
+            payments_engine = payments_engine.with_invariant_checks();
+        }
+        if self.duplicates.idempotent {
+            payments_engine = payments_engine.with_idempotency();
+        }
+        Ok(payments_engine)
+    }
+
+    fn fee_schedule(&self) -> Result<Option<FeeSchedule>, Box<dyn Error>> {
+        self.fees
+            .as_ref()
+            .map(|path| {
+                FeeSchedule::load(path)
+                    .map_err(|e| format!("invalid fees {}: {}", path.display(), e).into())
+            })
+            .transpose()
+    }
+
+    fn load_limits(&self) -> Result<Option<Limits>, Box<dyn Error>> {
+        self.limits
+            .as_ref()
+            .map(|path| {
+                Limits::load(path)
+                    .map_err(|e| format!("invalid limits {}: {}", path.display(), e).into())
+            })
+            .transpose()
+    }
+}
@@ -0,0 +1,174 @@
+use csv::ByteRecord;
+use std::collections::HashMap;
+use std::error::Error;
+use std::io::Read;
+
+// The columns the transactions are read from, in the order of files without headers by default.
+pub(crate) const COLUMNS: [&str; 6] = ["type", "client", "tx", "amount", "to", "timestamp"];
+
+/// How a CSV file of transactions is laid out - partners differ in delimiters, quotes, headers
+/// and the names of the columns. The default is the comma-separated, double-quoted file with a
+/// `type, client, tx, amount` header.
+#[derive(Debug, Clone)]
+pub struct CsvDialect {
+    delimiter: u8,
+    quote: u8,
+    // None when the file has a header row, otherwise the columns in order.
+    columns: Option<Vec<String>>,
+    aliases: HashMap<String, String>,
+    case_insensitive_types: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CsvDialect {
+    pub fn new() -> Self {
+        CsvDialect {
+            delimiter: b',',
+            quote: b'"',
+            columns: None,
+            aliases: HashMap::new(),
+            case_insensitive_types: false,
+        }
+    }
+
+    pub fn with_delimiter(mut self, delimiter: u8) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    pub fn with_quote(mut self, quote: u8) -> Self {
+        self.quote = quote;
+        self
+    }
+
+    /// The file has no header row, its fields are the given columns in order - aliases included.
+    /// Empty for the default order, `type, client, tx, amount, to, timestamp`.
+    pub fn without_headers<S: Into<String>>(
+        mut self,
+        columns: impl IntoIterator<Item = S>,
+    ) -> Self {
+        let columns: Vec<String> = columns.into_iter().map(Into::into).collect();
+        self.columns = Some(if columns.is_empty() {
+            COLUMNS.iter().map(|column| column.to_string()).collect()
+        } else {
+            columns
+        });
+        self
+    }
+
+    /// Reads the column named `alias` as `column`, e.g. `client_id` as `client`.
+    pub fn with_alias(mut self, alias: impl Into<String>, column: impl Into<String>) -> Self {
+        self.aliases.insert(alias.into(), column.into());
+        self
+    }
+
+    /// Reads `Deposit` or `DEPOSIT` as `deposit`, and so on.
+    pub fn with_case_insensitive_types(mut self) -> Self {
+        self.case_insensitive_types = true;
+        self
+    }
+
+    pub(crate) fn has_headers(&self) -> bool {
+        self.columns.is_none()
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.delimiter == self.quote {
+            return Err("the delimiter and the quote have to differ".into());
+        }
+        for (alias, column) in &self.aliases {
+            if !COLUMNS.contains(&column.as_str()) {
+                return Err(format!("alias '{}' is for unknown column '{}'", alias, column).into());
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn reader<R: Read>(&self, input: R) -> Result<csv::Reader<R>, Box<dyn Error>> {
+        self.validate()?;
+        Ok(self
+            .builder()
+            .has_headers(self.has_headers())
+            .from_reader(input))
+    }
+
+    /// A reader of records following ones already read, e.g. of the next chunk of a file - without
+    /// headers whatever the dialect.
+    #[cfg(feature = "async")]
+    pub(crate) fn records_reader<R: Read>(&self, input: R) -> csv::Reader<R> {
+        self.builder().has_headers(false).from_reader(input)
+    }
+
+    /// A reader telling where the records end, the same as the readers of `reader` do - e.g. not
+    /// within a quoted field that spans lines.
+    #[cfg(feature = "async")]
+    pub(crate) fn record_ends(&self) -> csv_core::Reader {
+        csv_core::ReaderBuilder::new()
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .build()
+    }
+
+    fn builder(&self) -> csv::ReaderBuilder {
+        let mut builder = csv::ReaderBuilder::new();
+        builder
+            .delimiter(self.delimiter)
+            .quote(self.quote)
+            .trim(csv::Trim::Headers)
+            .flexible(true);
+        builder
+    }
+
+    /// The columns of the file under their own names, whatever they are called in the file.
+    pub(crate) fn headers(
+        &self,
+        rdr: &mut csv::Reader<impl Read>,
+    ) -> Result<ByteRecord, Box<dyn Error>> {
+        let headers = match &self.columns {
+            Some(columns) => ByteRecord::from(columns.clone()),
+            None => rdr.byte_headers()?.clone(),
+        };
+        Ok(headers
+            .iter()
+            .map(|header| {
+                let header = header.trim_ascii();
+                match std::str::from_utf8(header)
+                    .ok()
+                    .and_then(|header| self.aliases.get(header))
+                {
+                    Some(column) => column.as_bytes(),
+                    None => header,
+                }
+            })
+            .collect())
+    }
+
+    /// Lowercases the type of a record when types are case-insensitive. The record is only rebuilt
+    /// when the type has any uppercase letters.
+    pub(crate) fn normalize(&self, record: &mut ByteRecord, type_column: Option<usize>) {
+        let Some(type_column) = type_column.filter(|_| self.case_insensitive_types) else {
+            return;
+        };
+        let has_uppercase = record
+            .get(type_column)
+            .is_some_and(|field| field.iter().any(u8::is_ascii_uppercase));
+        if has_uppercase {
+            *record = record
+                .iter()
+                .enumerate()
+                .map(|(column, field)| {
+                    if column == type_column {
+                        field.to_ascii_lowercase()
+                    } else {
+                        field.to_vec()
+                    }
+                })
+                .collect();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use crate::amount;
+use crate::Amount;
+use crate::CsvDialect;
+use crate::Rejection;
+use crate::Transaction;
+use crate::TransactionType;
+use csv::ByteRecord;
+
+/// Positions of the transaction columns in the CSV records.
+pub(crate) struct Columns {
+    // Serde insists on a field for every header.
+    headers: usize,
+    tx_type: usize,
+    client_id: usize,
+    id: usize,
+    amount: Option<usize>,
+    destination_client_id: Option<usize>,
+    timestamp: Option<usize>,
+}
+
+impl Columns {
+    // None when the headers are missing a column or repeat one - left for serde to report.
+    pub(crate) fn from_headers(headers: &ByteRecord) -> Option<Self> {
+        let column = |name: &[u8]| -> Option<Option<usize>> {
+            let mut positions = headers
+                .iter()
+                .enumerate()
+                .filter(|(_, header)| *header == name)
+                .map(|(position, _)| position);
+            let position = positions.next();
+            match positions.next() {
+                Some(_) => None,
+                None => Some(position),
+            }
+        };
+        Some(Columns {
+            headers: headers.len(),
+            tx_type: column(b"type")??,
+            client_id: column(b"client")??,
+            id: column(b"tx")??,
+            amount: column(b"amount")?,
+            destination_client_id: column(b"to")?,
+            timestamp: column(b"timestamp")?,
+        })
+    }
+}
+
+/// Parses the records of a file with the given headers, straight from their bytes when possible and
+/// by serde otherwise.
+pub(crate) struct RecordParser {
+    headers: ByteRecord,
+    columns: Option<Columns>,
+    type_column: Option<usize>,
+    amount_column: Option<usize>,
+}
+
+impl RecordParser {
+    pub(crate) fn new(headers: ByteRecord, fast_path: bool) -> Self {
+        RecordParser {
+            columns: Columns::from_headers(&headers).filter(|_| fast_path),
+            type_column: headers.iter().position(|header| header == b"type"),
+            amount_column: headers.iter().position(|header| header == b"amount"),
+            headers,
+        }
+    }
+
+    /// The transaction of the record, and the reason to reject it without applying when its
+    /// amount is a number `Amount` can not hold - e.g. one with too many decimal places. Such a
+    /// transaction comes without its amount.
+    pub(crate) fn parse(
+        &self,
+        record: &mut ByteRecord,
+        dialect: &CsvDialect,
+    ) -> csv::Result<(Transaction, Option<Rejection>)> {
+        dialect.normalize(record, self.type_column);
+        let parsed = self
+            .columns
+            .as_ref()
+            .and_then(|columns| parse(record, columns));
+        if let Some(transaction) = parsed {
+            return Ok((transaction, None));
+        }
+        record.trim();
+        let error = match record.deserialize(Some(&self.headers)) {
+            Ok(transaction) => return Ok((transaction, None)),
+            Err(e) => e,
+        };
+        let rejection = self
+            .amount_column
+            .and_then(|column| record.get(column))
+            .and_then(amount::unrepresentable);
+        let Some(rejection) = rejection else {
+            return Err(error);
+        };
+        // The rest of the record has to make sense for the transaction to be rejected.
+        let without_amount: ByteRecord = record
+            .iter()
+            .enumerate()
+            .map(|(column, field)| match Some(column) == self.amount_column {
+                true => &b""[..],
+                false => field,
+            })
+            .collect();
+        match without_amount.deserialize(Some(&self.headers)) {
+            Ok(transaction) => Ok((transaction, Some(rejection))),
+            Err(_) => Err(error),
+        }
+    }
+}
+
+/// Parses a record of plain numbers and lowercase types straight from its bytes. Returns
+/// None on anything unusual, for the record to be deserialized by serde instead - which either
+/// makes sense of it the same way, or reports the error.
+pub(crate) fn parse(record: &ByteRecord, columns: &Columns) -> Option<Transaction> {
+    if record.len() < columns.headers {
+        return None;
+    }
+    let field = |column: usize| record.get(column).map(<[u8]>::trim_ascii);
+    Some(Transaction {
+        id: parse_integer(field(columns.id)?)?.try_into().ok()?,
+        tx_type: parse_type(field(columns.tx_type)?)?,
+        client_id: parse_integer(field(columns.client_id)?)?.try_into().ok()?,
+        amount: optional(record, columns.amount, parse_amount)?,
+        destination_client_id: optional(record, columns.destination_client_id, |field| {
+            parse_integer(field)?.try_into().ok()
+        })?,
+        timestamp: optional(record, columns.timestamp, parse_integer)?,
+    })
+}
+
+// Empty fields are None, fields that do not parse are left for serde.
+fn optional<T>(
+    record: &ByteRecord,
+    column: Option<usize>,
+    parse: impl Fn(&[u8]) -> Option<T>,
+) -> Option<Option<T>> {
+    match column.map(|column| record[column].trim_ascii()) {
+        None | Some(b"") => Some(None),
+        Some(field) => parse(field).map(Some),
+    }
+}
+
+fn parse_type(field: &[u8]) -> Option<TransactionType> {
+    match field {
+        b"deposit" => Some(TransactionType::Deposit),
+        b"withdrawal" => Some(TransactionType::Withdrawal),
+        b"dispute" => Some(TransactionType::Dispute),
+        b"resolve" => Some(TransactionType::Resolve),
+        b"chargeback" => Some(TransactionType::Chargeback),
+        b"transfer" => Some(TransactionType::Transfer),
+        _ => None,
+    }
+}
+
+fn parse_integer(field: &[u8]) -> Option<u64> {
+    if field.is_empty() {
+        return None;
+    }
+    field.iter().try_fold(0u64, |value, &byte| {
+        if !byte.is_ascii_digit() {
+            return None;
+        }
+        value.checked_mul(10)?.checked_add(u64::from(byte - b'0'))
+    })
+}
+
+fn parse_amount(field: &[u8]) -> Option<Amount> {
+    amount::parse_plain(field)
+}
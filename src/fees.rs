@@ -0,0 +1,151 @@
+use crate::amount;
+use crate::Amount;
+use crate::ClientId;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Clients not listed in any of the tiers are charged according to this tier.
+const DEFAULT_TIER: &str = "default";
+
+/// Fees charged on deposits and withdrawals, per client tier. The collected fees are moved to the
+/// house account.
+///
+/// ```toml
+/// house_account = 65535
+///
+/// [tiers.default]
+/// deposit = { flat = "0.1" }
+/// withdrawal = { flat = "0.25", percentage = "0.5", min = "0.3", max = "5" }
+///
+/// [tiers.premium]
+/// clients = [1, 2]
+/// withdrawal = { percentage = "0.1" }
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FeeSchedule {
+    house_account: ClientId,
+    #[serde(default)]
+    tiers: HashMap<String, Tier>,
+    #[serde(skip)]
+    client_tiers: HashMap<ClientId, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Tier {
+    #[serde(default)]
+    clients: Vec<ClientId>,
+    deposit: Option<Fee>,
+    withdrawal: Option<Fee>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Fee {
+    #[serde(default)]
+    flat: Amount,
+    #[serde(default)]
+    percentage: Amount,
+    min: Option<Amount>,
+    max: Option<Amount>,
+}
+
+impl FeeSchedule {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut fee_schedule: FeeSchedule = toml::from_str(config)?;
+        for (name, tier) in &fee_schedule.tiers {
+            for fee in tier.deposit.iter().chain(tier.withdrawal.iter()) {
+                fee.validate(name)?;
+            }
+            if name == DEFAULT_TIER && !tier.clients.is_empty() {
+                return Err(format!("tier '{}' can not list clients", DEFAULT_TIER).into());
+            }
+            for client_id in &tier.clients {
+                if let Some(other) = fee_schedule.client_tiers.insert(*client_id, name.clone()) {
+                    return Err(format!(
+                        "client {} is listed in both '{}' and '{}' tiers",
+                        client_id, other, name
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(fee_schedule)
+    }
+
+    pub(crate) fn house_account(&self) -> ClientId {
+        self.house_account
+    }
+
+    // The fees are None when out of range.
+    pub(crate) fn deposit_fee(&self, client_id: ClientId, amount: Amount) -> Option<Amount> {
+        // A deposit can never be eaten up by more than its own amount in fees.
+        self.fee(client_id, amount, |tier| tier.deposit.as_ref())
+            .map(|fee| fee.min(amount))
+    }
+
+    pub(crate) fn withdrawal_fee(&self, client_id: ClientId, amount: Amount) -> Option<Amount> {
+        self.fee(client_id, amount, |tier| tier.withdrawal.as_ref())
+    }
+
+    fn fee(
+        &self,
+        client_id: ClientId,
+        amount: Amount,
+        fee: fn(&Tier) -> Option<&Fee>,
+    ) -> Option<Amount> {
+        // The house does not charge itself.
+        if client_id == self.house_account || !amount.is_sign_positive() {
+            return Some(Amount::ZERO);
+        }
+        let tier = self
+            .client_tiers
+            .get(&client_id)
+            .map_or(DEFAULT_TIER, String::as_str);
+        // Fees a tier does not define are charged as in the default tier.
+        self.tiers
+            .get(tier)
+            .and_then(fee)
+            .or_else(|| self.tiers.get(DEFAULT_TIER).and_then(fee))
+            .map_or(Some(Amount::ZERO), |fee| fee.apply(amount))
+    }
+}
+
+impl Fee {
+    fn apply(&self, amount: Amount) -> Option<Amount> {
+        let mut fee = self
+            .flat
+            .checked_add(amount::percentage(amount, self.percentage)?)?;
+        if let Some(min) = self.min {
+            fee = fee.max(min);
+        }
+        if let Some(max) = self.max {
+            fee = fee.min(max);
+        }
+        Some(amount::round(fee))
+    }
+
+    fn validate(&self, tier: &str) -> Result<(), Box<dyn Error>> {
+        let negative = [Some(self.flat), Some(self.percentage), self.min, self.max]
+            .iter()
+            .flatten()
+            .any(|value| value.is_sign_negative());
+        if negative {
+            return Err(format!("tier '{}' has a negative fee", tier).into());
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(format!("tier '{}' has a minimum fee above the maximum", tier).into());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,231 @@
+use crate::ClientId;
+use crate::TransactionId;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::io::Write;
+
+// Disputes are raised over one of the latest deposits, the way they tend to be in practice.
+const DISPUTABLE_DEPOSITS: usize = 4096;
+
+/// Writes synthetic transactions as CSV - deposits and withdrawals of random clients, with disputes
+/// of earlier deposits that get resolved or charged back. The same seed gives the same file.
+pub struct Generator {
+    rows: u64,
+    seed: u64,
+    clients: ClientId,
+    disputes: f64,
+    chargebacks: f64,
+    errors: f64,
+}
+
+// Rows the engine is expected to reject, for generating error cases.
+#[derive(Clone, Copy)]
+enum Mistake {
+    InsufficientFunds,
+    UnknownTransaction,
+    NotDisputed,
+    NegativeAmount,
+    MissingAmount,
+}
+
+impl Generator {
+    pub fn new(rows: u64, seed: u64) -> Self {
+        Generator {
+            rows,
+            seed,
+            clients: 100,
+            disputes: 0.0,
+            chargebacks: 0.0,
+            errors: 0.0,
+        }
+    }
+
+    /// The transactions are spread over clients `1..=clients`.
+    pub fn with_clients(mut self, clients: ClientId) -> Self {
+        self.clients = clients;
+        self
+    }
+
+    /// The share of the rows that are disputes, each later followed by a resolve or a chargeback.
+    pub fn with_disputes(mut self, disputes: f64) -> Self {
+        self.disputes = disputes;
+        self
+    }
+
+    /// The share of the disputes that end with a chargeback rather than a resolve.
+    pub fn with_chargebacks(mut self, chargebacks: f64) -> Self {
+        self.chargebacks = chargebacks;
+        self
+    }
+
+    /// The share of the rows the engine rejects - withdrawals of more than is available, disputes
+    /// of unknown transactions, negative or missing amounts and the like. The CSV itself is valid.
+    pub fn with_errors(mut self, errors: f64) -> Self {
+        self.errors = errors;
+        self
+    }
+
+    pub fn write_csv(&self, output: &mut impl Write) -> Result<(), Box<dyn Error>> {
+        self.validate()?;
+        let mut wtr = csv::Writer::from_writer(output);
+        wtr.write_record(["type", "client", "tx", "amount"])?;
+
+        let mut rng = SplitMix64(self.seed);
+        let mut next_id: TransactionId = 1;
+        let mut deposits: VecDeque<(ClientId, TransactionId)> = VecDeque::new();
+        let mut open_disputes: Vec<(ClientId, TransactionId)> = Vec::new();
+        // Clients whose accounts are not locked by a chargeback - the only ones still transacting.
+        let mut active_clients: Vec<ClientId> = (1..=self.clients).collect();
+        for _ in 0..self.rows {
+            let client_id = active_clients[rng.below(active_clients.len() as u64) as usize];
+            // Every dispute gets settled eventually, so settling is as likely as disputing.
+            if !open_disputes.is_empty() && rng.chance(self.disputes) {
+                let (client_id, id) =
+                    open_disputes.swap_remove(rng.below(open_disputes.len() as u64) as usize);
+                let active = active_clients
+                    .iter()
+                    .position(|active| *active == client_id);
+                // The last active client is never locked, to keep the transactions coming.
+                let tx_type = match active {
+                    Some(position) if active_clients.len() > 1 && rng.chance(self.chargebacks) => {
+                        active_clients.swap_remove(position);
+                        "chargeback"
+                    }
+                    _ => "resolve",
+                };
+                write_row(&mut wtr, tx_type, client_id, id, "")?;
+            } else if !deposits.is_empty() && rng.chance(self.disputes) {
+                let (client_id, id) = deposits
+                    .remove(rng.below(deposits.len() as u64) as usize)
+                    .expect("deposit in range");
+                open_disputes.push((client_id, id));
+                write_row(&mut wtr, "dispute", client_id, id, "")?;
+            } else if rng.chance(self.errors) {
+                self.write_error(&mut wtr, &mut rng, client_id, &mut next_id)?;
+            } else if rng.chance(0.6) {
+                let id = take_id(&mut next_id)?;
+                let amount = format_amount(rng.range(1, 10_000_000));
+                write_row(&mut wtr, "deposit", client_id, id, &amount)?;
+                if deposits.len() == DISPUTABLE_DEPOSITS {
+                    deposits.pop_front();
+                }
+                deposits.push_back((client_id, id));
+            } else {
+                let id = take_id(&mut next_id)?;
+                let amount = format_amount(rng.range(1, 5_000_000));
+                write_row(&mut wtr, "withdrawal", client_id, id, &amount)?;
+            }
+        }
+
+        wtr.flush()?;
+
+        Ok(())
+    }
+
+    fn write_error(
+        &self,
+        wtr: &mut csv::Writer<impl Write>,
+        rng: &mut SplitMix64,
+        client_id: ClientId,
+        next_id: &mut TransactionId,
+    ) -> Result<(), Box<dyn Error>> {
+        let mistakes = [
+            Mistake::InsufficientFunds,
+            Mistake::UnknownTransaction,
+            Mistake::NotDisputed,
+            Mistake::NegativeAmount,
+            Mistake::MissingAmount,
+        ];
+        match mistakes[rng.below(mistakes.len() as u64) as usize] {
+            Mistake::InsufficientFunds => {
+                let id = take_id(next_id)?;
+                write_row(wtr, "withdrawal", client_id, id, "1000000000")
+            }
+            // Transaction ids are handed out in order, so the next one is not known yet.
+            Mistake::UnknownTransaction => write_row(wtr, "dispute", client_id, *next_id, ""),
+            Mistake::NotDisputed => {
+                let id = rng.range(1, u64::from(*next_id)) as TransactionId;
+                write_row(wtr, "resolve", client_id, id, "")
+            }
+            Mistake::NegativeAmount => {
+                let id = take_id(next_id)?;
+                let amount = format!("-{}", format_amount(rng.range(1, 10_000_000)));
+                write_row(wtr, "deposit", client_id, id, &amount)
+            }
+            Mistake::MissingAmount => {
+                let id = take_id(next_id)?;
+                write_row(wtr, "deposit", client_id, id, "")
+            }
+        }
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        if self.clients == 0 {
+            return Err("at least one client is needed".into());
+        }
+        for (name, ratio) in [
+            ("disputes", self.disputes),
+            ("chargebacks", self.chargebacks),
+            ("errors", self.errors),
+        ] {
+            if !(0.0..=1.0).contains(&ratio) {
+                return Err(format!("{} ratio {} is not between 0 and 1", name, ratio).into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn write_row(
+    wtr: &mut csv::Writer<impl Write>,
+    tx_type: &str,
+    client_id: ClientId,
+    id: TransactionId,
+    amount: &str,
+) -> Result<(), Box<dyn Error>> {
+    wtr.write_record([tx_type, &client_id.to_string(), &id.to_string(), amount])?;
+    Ok(())
+}
+
+fn take_id(next_id: &mut TransactionId) -> Result<TransactionId, Box<dyn Error>> {
+    let id = *next_id;
+    *next_id = id.checked_add(1).ok_or("ran out of transaction ids")?;
+    Ok(id)
+}
+
+// Ten-thousandths as a decimal without trailing zeros, e.g. `12.5`.
+fn format_amount(ten_thousandths: u64) -> String {
+    let (integer, fraction) = (ten_thousandths / 10_000, ten_thousandths % 10_000);
+    if fraction == 0 {
+        return integer.to_string();
+    }
+    let fraction = format!("{:04}", fraction);
+    format!("{}.{}", integer, fraction.trim_end_matches('0'))
+}
+
+// A small, fast generator with a stable output for a given seed, unlike one from a crate that may
+// change between versions.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // Slightly biased for bounds that are not powers of two, which does not matter here.
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next() % bound
+    }
+
+    fn range(&mut self, low: u64, high: u64) -> u64 {
+        low + self.below(high - low + 1)
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        ((self.next() >> 11) as f64 / (1u64 << 53) as f64) < probability
+    }
+}
@@ -0,0 +1,197 @@
+// The errors are tonic's `Status`, large as it is, like throughout its API.
+#![allow(clippy::result_large_err)]
+
+use crate::amount;
+use crate::Amount;
+use crate::PaymentsEngine;
+use crate::Transaction;
+use crate::TransactionType;
+use std::error::Error;
+use std::sync::Arc;
+use std::sync::Mutex;
+use tonic::transport::server::TcpIncoming;
+use tonic::Code;
+use tonic::Request;
+use tonic::Response;
+use tonic::Status;
+use tonic::Streaming;
+
+/// The messages and the client and server of the service in `proto/payments.proto`.
+pub mod proto {
+    tonic::include_proto!("payments");
+}
+
+use proto::payments_engine_server::PaymentsEngineServer;
+
+/// Serves the gRPC service of `proto/payments.proto` over a shared engine.
+pub struct GrpcService {
+    payments_engine: Arc<Mutex<PaymentsEngine>>,
+}
+
+impl GrpcService {
+    pub fn new(payments_engine: PaymentsEngine) -> Self {
+        GrpcService {
+            payments_engine: Arc::new(Mutex::new(payments_engine)),
+        }
+    }
+
+    /// The engine the transactions are applied to, e.g. to write its account states.
+    pub fn payments_engine(&self) -> Arc<Mutex<PaymentsEngine>> {
+        Arc::clone(&self.payments_engine)
+    }
+
+    /// Serves the connections the listener accepts until the server fails. The error can be sent
+    /// between threads, for the server to be spawned as a task.
+    pub async fn serve(
+        self,
+        listener: tokio::net::TcpListener,
+    ) -> Result<(), Box<dyn Error + Send + Sync>> {
+        let incoming = TcpIncoming::from_listener(listener, true, None)?;
+        tonic::transport::Server::builder()
+            .add_service(PaymentsEngineServer::new(self))
+            .serve_with_incoming(incoming)
+            .await?;
+        Ok(())
+    }
+
+    async fn apply(&self, transaction: proto::Transaction) -> Result<proto::Outcome, Status> {
+        let transaction = Transaction::try_from(transaction)?;
+        let result = self
+            .with_engine(|payments_engine| payments_engine.serve_transaction(transaction))
+            .await?
+            .map_err(Status::internal)?;
+        Ok(match result {
+            Ok(()) => proto::Outcome {
+                applied: true,
+                reason: String::new(),
+            },
+            Err(rejection) => proto::Outcome {
+                applied: false,
+                reason: rejection.to_string(),
+            },
+        })
+    }
+
+    // Off the runtime's threads, as the engine may be waited for while another request has it -
+    // saving a transaction to the database, say.
+    async fn with_engine<T: Send + 'static>(
+        &self,
+        f: impl FnOnce(&mut PaymentsEngine) -> T + Send + 'static,
+    ) -> Result<T, Status> {
+        let payments_engine = Arc::clone(&self.payments_engine);
+        tokio::task::spawn_blocking(move || {
+            f(&mut payments_engine.lock().expect("engine lock poisoned"))
+        })
+        .await
+        .map_err(|e| Status::internal(e.to_string()))
+    }
+}
+
+#[tonic::async_trait]
+impl proto::payments_engine_server::PaymentsEngine for GrpcService {
+    async fn submit(
+        &self,
+        request: Request<proto::Transaction>,
+    ) -> Result<Response<proto::Outcome>, Status> {
+        self.apply(request.into_inner()).await.map(Response::new)
+    }
+
+    // Transactions that make no sense are counted rather than failing the rest of the stream, unlike
+    // one that failed to be saved to the database.
+    async fn submit_bulk(
+        &self,
+        request: Request<Streaming<proto::Transaction>>,
+    ) -> Result<Response<proto::BulkOutcome>, Status> {
+        let mut transactions = request.into_inner();
+        let mut bulk_outcome = proto::BulkOutcome::default();
+        while let Some(transaction) = transactions.message().await? {
+            match self.apply(transaction).await {
+                Ok(outcome) if outcome.applied => bulk_outcome.applied += 1,
+                Ok(outcome) => {
+                    bulk_outcome.rejected += 1;
+                    *bulk_outcome.rejections.entry(outcome.reason).or_default() += 1;
+                }
+                Err(status) if status.code() == Code::Internal => return Err(status),
+                Err(_) => bulk_outcome.invalid += 1,
+            }
+        }
+        Ok(Response::new(bulk_outcome))
+    }
+
+    async fn get_account(
+        &self,
+        request: Request<proto::AccountRequest>,
+    ) -> Result<Response<proto::Account>, Status> {
+        let client = request.into_inner().client;
+        let client_id = client
+            .try_into()
+            .map_err(|_| Status::invalid_argument(format!("invalid client {}", client)))?;
+        let account = self
+            .with_engine(move |payments_engine| {
+                let with_credit = crate::with_credit(payments_engine);
+                let account = payments_engine.account(client_id)?;
+                let credit = |amount: Amount| with_credit.then(|| to_text(amount));
+                Some(proto::Account {
+                    client,
+                    available: to_text(account.available),
+                    held: to_text(account.held),
+                    total: to_text(account.total),
+                    locked: account.locked,
+                    credit_limit: credit(account.credit_limit),
+                    credit_used: credit(account.credit_used()),
+                })
+            })
+            .await?
+            .ok_or_else(|| Status::not_found(format!("unknown client {}", client)))?;
+        Ok(Response::new(account))
+    }
+}
+
+// With four decimal places, as the amounts are written everywhere else.
+fn to_text(amount: Amount) -> String {
+    amount::fixed_scale(amount).to_string()
+}
+
+impl TryFrom<proto::Transaction> for Transaction {
+    type Error = Status;
+
+    fn try_from(transaction: proto::Transaction) -> Result<Self, Self::Error> {
+        let tx_type = match proto::TransactionType::try_from(transaction.r#type) {
+            Ok(proto::TransactionType::Deposit) => TransactionType::Deposit,
+            Ok(proto::TransactionType::Withdrawal) => TransactionType::Withdrawal,
+            Ok(proto::TransactionType::Dispute) => TransactionType::Dispute,
+            Ok(proto::TransactionType::Resolve) => TransactionType::Resolve,
+            Ok(proto::TransactionType::Chargeback) => TransactionType::Chargeback,
+            Ok(proto::TransactionType::Transfer) => TransactionType::Transfer,
+            Ok(proto::TransactionType::Unspecified) | Err(_) => {
+                return Err(Status::invalid_argument("invalid transaction type"))
+            }
+        };
+        let client_id = |client: u32| {
+            client
+                .try_into()
+                .map_err(|_| Status::invalid_argument(format!("invalid client {}", client)))
+        };
+        let amount = transaction
+            .amount
+            .map(|amount| {
+                // In their shortest form, as the amounts of a CSV are - see
+                // `amount::deserialize_shortest`.
+                amount
+                    .parse::<Amount>()
+                    .map(amount::canonical)
+                    .map_err(|e| {
+                        Status::invalid_argument(format!("invalid amount '{}': {}", amount, e))
+                    })
+            })
+            .transpose()?;
+        Ok(Transaction {
+            id: transaction.tx,
+            tx_type,
+            client_id: client_id(transaction.client)?,
+            amount,
+            destination_client_id: transaction.to.map(client_id).transpose()?,
+            timestamp: transaction.timestamp,
+        })
+    }
+}
@@ -0,0 +1,220 @@
+use crate::Account;
+use crate::AccountWithCredit;
+use crate::ClientId;
+use crate::PaymentsEngine;
+use crate::Transaction;
+use serde_json::json;
+use serde_json::Value;
+use std::error::Error;
+use std::io;
+use std::io::Read;
+use std::net::SocketAddr;
+use std::net::ToSocketAddrs;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use tiny_http::Header;
+use tiny_http::Method;
+use tiny_http::Request;
+use tiny_http::Response;
+
+const DEFAULT_PAGE_SIZE: usize = 100;
+const MAX_PAGE_SIZE: usize = 1000;
+
+/// Serves an HTTP/JSON API over a shared engine:
+/// - `POST /transactions` applies the transaction in the body, e.g.
+///   `{"type": "deposit", "client": 1, "tx": 1, "amount": "10"}` - or with the amount as a number
+/// - `GET /accounts/<client>` gets the account of a client
+/// - `GET /accounts?offset=0&limit=100` lists the accounts by client id
+/// - `GET /accounts.csv` downloads the account states as `write_account_states_to_csv` writes them
+pub struct HttpServer {
+    server: tiny_http::Server,
+    payments_engine: Arc<Mutex<PaymentsEngine>>,
+}
+
+impl HttpServer {
+    pub fn bind(
+        address: impl ToSocketAddrs,
+        payments_engine: PaymentsEngine,
+    ) -> Result<Self, Box<dyn Error>> {
+        let server = tiny_http::Server::http(address).map_err(|e| e.to_string())?;
+        Ok(HttpServer {
+            server,
+            payments_engine: Arc::new(Mutex::new(payments_engine)),
+        })
+    }
+
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    /// The engine the transactions are applied to, e.g. to write its account states.
+    pub fn payments_engine(&self) -> Arc<Mutex<PaymentsEngine>> {
+        Arc::clone(&self.payments_engine)
+    }
+
+    /// Serves requests until the server fails, each on a thread of its own.
+    pub fn serve(&self) -> io::Result<()> {
+        loop {
+            let request = self.server.recv()?;
+            let payments_engine = self.payments_engine();
+            thread::spawn(move || {
+                // A request failing to be answered only fails that request.
+                let _ = handle(request, &payments_engine);
+            });
+        }
+    }
+}
+
+fn handle(mut request: Request, payments_engine: &Mutex<PaymentsEngine>) -> io::Result<()> {
+    let url = request.url().to_string();
+    let (path, query) = url.split_once('?').unwrap_or((&url, ""));
+    let (status, body, content_type) = match (request.method(), path) {
+        (Method::Post, "/transactions") => match read_transaction(request.as_reader()) {
+            Ok(transaction) => post_transaction(transaction, payments_engine),
+            Err(e) => error(400, format!("invalid transaction: {}", e)),
+        },
+        (Method::Get, "/accounts.csv") => {
+            let payments_engine = payments_engine.lock().expect("engine lock poisoned");
+            let mut output = Vec::new();
+            match crate::write_account_states_to_csv(&payments_engine, &mut output) {
+                Ok(()) => (200, output, "text/csv"),
+                Err(e) => error(500, e.to_string()),
+            }
+        }
+        (Method::Get, "/accounts") => match Page::from_query(query) {
+            Ok(page) => list_accounts(page, payments_engine),
+            Err(e) => error(400, e),
+        },
+        (Method::Get, path) if path.starts_with("/accounts/") => {
+            match path["/accounts/".len()..].parse::<ClientId>() {
+                Ok(client_id) => get_account(client_id, payments_engine),
+                Err(e) => error(400, format!("invalid client: {}", e)),
+            }
+        }
+        _ => error(404, "not found".to_string()),
+    };
+    let content_type =
+        Header::from_bytes("Content-Type", content_type).expect("content type is a valid header");
+    request.respond(
+        Response::from_data(body)
+            .with_status_code(status)
+            .with_header(content_type),
+    )
+}
+
+// The amount may be a number too, e.g. `1.5`, taken in its shortest decimal form - the same
+// whichever type the amounts are kept as.
+fn read_transaction(body: impl Read) -> serde_json::Result<Transaction> {
+    let mut transaction: Value = serde_json::from_reader(body)?;
+    if let Some(amount) = transaction
+        .get_mut("amount")
+        .filter(|amount| amount.is_number())
+    {
+        *amount = Value::String(amount.to_string());
+    }
+    serde_json::from_value(transaction)
+}
+
+type Reply = (u16, Vec<u8>, &'static str);
+
+fn json(status: u16, value: Value) -> Reply {
+    (status, value.to_string().into_bytes(), "application/json")
+}
+
+fn error(status: u16, message: String) -> Reply {
+    json(status, json!({ "error": message }))
+}
+
+// Rejected transactions are answered with 422, as the request itself was fine, and transactions
+// that failed to be saved to the database with 500.
+fn post_transaction(transaction: Transaction, payments_engine: &Mutex<PaymentsEngine>) -> Reply {
+    let mut payments_engine = payments_engine.lock().expect("engine lock poisoned");
+    match payments_engine.serve_transaction(transaction) {
+        Ok(Ok(())) => json(200, json!({ "status": "applied" })),
+        Ok(Err(rejection)) => json(
+            422,
+            json!({ "status": "rejected", "reason": rejection.to_string() }),
+        ),
+        Err(e) => error(500, e),
+    }
+}
+
+fn get_account(client_id: ClientId, payments_engine: &Mutex<PaymentsEngine>) -> Reply {
+    let payments_engine = payments_engine.lock().expect("engine lock poisoned");
+    match payments_engine.account(client_id) {
+        Some(account) => {
+            let with_credit = crate::with_credit(&payments_engine);
+            json(200, account_to_json(account, with_credit))
+        }
+        None => error(404, format!("unknown client {}", client_id)),
+    }
+}
+
+fn list_accounts(page: Page, payments_engine: &Mutex<PaymentsEngine>) -> Reply {
+    let payments_engine = payments_engine.lock().expect("engine lock poisoned");
+    // Like in the CSV, only the accounts with any transactions applied.
+    let mut accounts: Vec<&Account> = payments_engine
+        .accounts
+        .values()
+        .filter(|account| !account.transactions.is_empty())
+        .collect();
+    accounts.sort_by_key(|account| account.client_id);
+    let total = accounts.len();
+    let with_credit = crate::with_credit(&payments_engine);
+    let accounts: Vec<Value> = accounts
+        .into_iter()
+        .skip(page.offset)
+        .take(page.limit)
+        .map(|account| account_to_json(account, with_credit))
+        .collect();
+    json(
+        200,
+        json!({
+            "accounts": accounts,
+            "offset": page.offset,
+            "limit": page.limit,
+            "total": total,
+        }),
+    )
+}
+
+fn account_to_json(account: &Account, with_credit: bool) -> Value {
+    let account = if with_credit {
+        serde_json::to_value(AccountWithCredit::from(account))
+    } else {
+        serde_json::to_value(account)
+    };
+    account.expect("accounts serialize to JSON")
+}
+
+struct Page {
+    offset: usize,
+    limit: usize,
+}
+
+impl Page {
+    fn from_query(query: &str) -> Result<Self, String> {
+        let mut page = Page {
+            offset: 0,
+            limit: DEFAULT_PAGE_SIZE,
+        };
+        for parameter in query.split('&').filter(|parameter| !parameter.is_empty()) {
+            let (name, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+            let value = || {
+                value
+                    .parse::<usize>()
+                    .map_err(|e| format!("invalid {}: {}", name, e))
+            };
+            match name {
+                "offset" => page.offset = value()?,
+                "limit" => page.limit = value()?,
+                _ => return Err(format!("unknown parameter '{}'", name)),
+            }
+        }
+        if page.limit == 0 || page.limit > MAX_PAGE_SIZE {
+            return Err(format!("limit has to be from 1 to {}", MAX_PAGE_SIZE));
+        }
+        Ok(page)
+    }
+}
@@ -0,0 +1,278 @@
+use crate::amount;
+use crate::Rejection;
+use crate::Transaction;
+use crate::TransactionId;
+use crate::TransactionType;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::fmt;
+
+/// A transaction carrying the id of one processed before, but a different client, type or amount -
+/// see `PaymentsEngine::with_idempotency`.
+#[derive(Debug, Clone)]
+pub struct DuplicateConflict {
+    pub transaction: Transaction,
+}
+
+impl fmt::Display for DuplicateConflict {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tx {}: {} of client {} differs from the transaction applied under its id",
+            self.transaction.id, self.transaction.tx_type, self.transaction.client_id
+        )
+    }
+}
+
+/// Identifies a transaction by its id and a hash of its content. Deposits, withdrawals and
+/// transfers each have an id of their own, while disputes, resolves and chargebacks share the id of
+/// the transaction they are about.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub(crate) struct Fingerprint {
+    pub(crate) id: TransactionId,
+    pub(crate) kind: Kind,
+    pub(crate) hash: u64,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum Kind {
+    Transaction,
+    Dispute,
+    Resolve,
+    Chargeback,
+}
+
+// The kinds by their name in a database.
+#[cfg(feature = "sqlite")]
+impl Kind {
+    pub(crate) const ALL: [Kind; 4] = [
+        Kind::Transaction,
+        Kind::Dispute,
+        Kind::Resolve,
+        Kind::Chargeback,
+    ];
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            Kind::Transaction => "transaction",
+            Kind::Dispute => "dispute",
+            Kind::Resolve => "resolve",
+            Kind::Chargeback => "chargeback",
+        }
+    }
+}
+
+impl Fingerprint {
+    pub(crate) fn of(transaction: &Transaction) -> Self {
+        let kind = match transaction.tx_type {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+                Kind::Transaction
+            }
+            TransactionType::Dispute => Kind::Dispute,
+            TransactionType::Resolve => Kind::Resolve,
+            TransactionType::Chargeback => Kind::Chargeback,
+        };
+        let amount = transaction.amount.map(amount::canonical);
+        let optional = |value: Option<String>| value.unwrap_or_default();
+        // The timestamp is left out, for a transaction re-sent later to still be the same.
+        let content = format!(
+            "{},{},{},{}",
+            transaction.tx_type,
+            transaction.client_id,
+            optional(amount.map(|amount| amount.to_string())),
+            optional(transaction.destination_client_id.map(|to| to.to_string())),
+        );
+        Fingerprint {
+            id: transaction.id,
+            kind,
+            hash: fnv1a(FNV1A_START, content.as_bytes()),
+        }
+    }
+}
+
+// A hash that stays the same across runs and builds, as it is kept in checkpoints and databases.
+// Starting from `FNV1A_START`, it carries on over more bytes from the hash of those before.
+pub(crate) const FNV1A_START: u64 = 0xcbf2_9ce4_8422_2325;
+
+pub(crate) fn fnv1a(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+pub(crate) enum Seen {
+    // Not seen before, the position it takes in the dispute lifecycle - see `Recorded`.
+    New(u32),
+    // Seen before, with the outcome it had.
+    Duplicate(Result<(), Rejection>),
+    Conflict,
+}
+
+/// A transaction processed, with the outcome it had. Disputes, resolves and chargebacks of a
+/// transaction can come again after a resolve, so each is told apart by its position in the
+/// dispute lifecycle of the transaction - the disputes, resolves and chargebacks of it processed
+/// before, e.g. the dispute following the first dispute and resolve of it. Deposits, withdrawals
+/// and transfers are at position 0.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Recorded {
+    pub(crate) fingerprint: Fingerprint,
+    pub(crate) position: u32,
+    pub(crate) outcome: Result<(), Rejection>,
+}
+
+// The disputes, resolves and chargebacks of a transaction by their position, with their outcomes.
+type Lifecycle = BTreeMap<u32, (Fingerprint, Result<(), Rejection>)>;
+
+/// The transactions processed so far, applied or rejected.
+#[derive(Default)]
+pub(crate) struct Applied {
+    // The deposits, withdrawals and transfers.
+    outcomes: HashMap<Fingerprint, Result<(), Rejection>>,
+    // The content of each deposit, withdrawal and transfer, to tell conflicts by.
+    hashes: HashMap<TransactionId, u64>,
+    lifecycles: HashMap<TransactionId, Lifecycle>,
+    // The position in each lifecycle the current input has come to.
+    cursors: HashMap<TransactionId, u32>,
+}
+
+impl Applied {
+    // A dispute, resolve or chargeback is the one sent before when the input has not come past it
+    // yet in the lifecycle of the transaction - a file sent again goes through the lifecycle again
+    // from its start, while one overlapping it carries on where it left off. Otherwise it is new,
+    // and takes the next position.
+    pub(crate) fn seen(&mut self, fingerprint: &Fingerprint) -> Seen {
+        if fingerprint.kind == Kind::Transaction {
+            return match (
+                self.hashes.get(&fingerprint.id),
+                self.outcomes.get(fingerprint),
+            ) {
+                (Some(hash), _) if *hash != fingerprint.hash => Seen::Conflict,
+                (_, Some(outcome)) => Seen::Duplicate(*outcome),
+                _ => Seen::New(0),
+            };
+        }
+        let Some(lifecycle) = self.lifecycles.get(&fingerprint.id) else {
+            return Seen::New(0);
+        };
+        let cursor = self.cursors.get(&fingerprint.id).copied().unwrap_or(0);
+        let seen = lifecycle
+            .range(cursor..)
+            .find(|(_, (seen, _))| seen == fingerprint);
+        match seen {
+            Some((&position, &(_, outcome))) => {
+                self.cursors.insert(fingerprint.id, position + 1);
+                Seen::Duplicate(outcome)
+            }
+            None => Seen::New(lifecycle.keys().next_back().map_or(0, |last| last + 1)),
+        }
+    }
+
+    // Records a transaction just processed, which the input has come past.
+    pub(crate) fn record(&mut self, recorded: Recorded) {
+        if recorded.fingerprint.kind != Kind::Transaction {
+            self.cursors
+                .insert(recorded.fingerprint.id, recorded.position + 1);
+        }
+        self.insert(recorded);
+    }
+
+    fn insert(&mut self, recorded: Recorded) {
+        let fingerprint = recorded.fingerprint;
+        match fingerprint.kind {
+            Kind::Transaction => {
+                self.hashes.insert(fingerprint.id, fingerprint.hash);
+                self.outcomes.insert(fingerprint, recorded.outcome);
+            }
+            Kind::Dispute | Kind::Resolve | Kind::Chargeback => {
+                self.lifecycles
+                    .entry(fingerprint.id)
+                    .or_default()
+                    .insert(recorded.position, (fingerprint, recorded.outcome));
+            }
+        }
+    }
+
+    // A new input goes through the lifecycles from their start, for those of a file sent again to
+    // be the ones recorded the first time. Until then it carries on, as with a server.
+    pub(crate) fn start_input(&mut self) {
+        self.cursors.clear();
+    }
+
+    pub(crate) fn records(&self) -> impl Iterator<Item = Recorded> + '_ {
+        let transactions = self
+            .outcomes
+            .iter()
+            .map(|(&fingerprint, &outcome)| Recorded {
+                fingerprint,
+                position: 0,
+                outcome,
+            });
+        let lifecycles = self.lifecycles.values().flat_map(|lifecycle| {
+            lifecycle
+                .iter()
+                .map(|(&position, &(fingerprint, outcome))| Recorded {
+                    fingerprint,
+                    position,
+                    outcome,
+                })
+        });
+        transactions.chain(lifecycles)
+    }
+
+    // Those of a transaction and of its disputes, resolves and chargebacks.
+    pub(crate) fn records_of(&self, id: TransactionId) -> impl Iterator<Item = Recorded> + '_ {
+        let transaction = self.hashes.get(&id).and_then(|&hash| {
+            let fingerprint = Fingerprint {
+                id,
+                kind: Kind::Transaction,
+                hash,
+            };
+            self.outcomes.get(&fingerprint).map(|&outcome| Recorded {
+                fingerprint,
+                position: 0,
+                outcome,
+            })
+        });
+        let lifecycle = self.lifecycles.get(&id).into_iter().flat_map(|lifecycle| {
+            lifecycle
+                .iter()
+                .map(|(&position, &(fingerprint, outcome))| Recorded {
+                    fingerprint,
+                    position,
+                    outcome,
+                })
+        });
+        transaction.into_iter().chain(lifecycle)
+    }
+
+    pub(crate) fn cursor(&self, id: TransactionId) -> Option<u32> {
+        self.cursors.get(&id).copied()
+    }
+
+    pub(crate) fn set_cursor(&mut self, id: TransactionId, cursor: Option<u32>) {
+        match cursor {
+            Some(cursor) => self.cursors.insert(id, cursor),
+            None => self.cursors.remove(&id),
+        };
+    }
+
+    pub(crate) fn cursors(&self) -> impl Iterator<Item = (TransactionId, u32)> + '_ {
+        self.cursors.iter().map(|(&id, &cursor)| (id, cursor))
+    }
+
+    pub(crate) fn set_cursors(&mut self, cursors: impl IntoIterator<Item = (TransactionId, u32)>) {
+        self.cursors = cursors.into_iter().collect();
+    }
+}
+
+// Those recorded before, which no input has come past yet.
+impl Extend<Recorded> for Applied {
+    fn extend<T: IntoIterator<Item = Recorded>>(&mut self, records: T) {
+        for recorded in records {
+            self.insert(recorded);
+        }
+    }
+}
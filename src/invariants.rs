@@ -0,0 +1,80 @@
+use crate::amount;
+use crate::Account;
+use crate::Amount;
+use crate::ClientId;
+use crate::TransactionId;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+
+/// A consistency rule every account is expected to follow.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Invariant {
+    // The total is the available plus held funds, except for disputed withdrawals - those are held
+    // as a negative amount that does not count towards the total until charged back.
+    TotalMatchesBalances,
+    // The held funds are the sum of the amounts under dispute.
+    HeldMatchesDisputes,
+    // A locked account's balances do not change anymore. The house account is exempt, as it keeps
+    // collecting fees when locked. Disputes, resolves and chargebacks do not count, as they settle
+    // transactions from before the lock.
+    LockedAccountUnchanged,
+}
+
+impl fmt::Display for Invariant {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let invariant = match self {
+            Invariant::TotalMatchesBalances => "total does not match available and held funds",
+            Invariant::HeldMatchesDisputes => "held funds do not match the disputed amounts",
+            Invariant::LockedAccountUnchanged => "balances changed after the account was locked",
+        };
+        f.write_str(invariant)
+    }
+}
+
+/// An invariant found violated, with the transaction that caused it - or, when checked at the end
+/// of a run, the last transaction applied to the account.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Violation {
+    pub client_id: ClientId,
+    pub transaction_id: Option<TransactionId>,
+    pub invariant: Invariant,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "client {}: {}", self.client_id, self.invariant)?;
+        if let Some(transaction_id) = self.transaction_id {
+            write!(f, " (tx {})", transaction_id)?;
+        }
+        Ok(())
+    }
+}
+
+// The balances are checked against the disputes rather than the ledger accounts, so that a mistake
+// in the postings does not go unnoticed.
+pub(crate) fn violated_invariants(
+    account: &Account,
+    house_account: Option<ClientId>,
+) -> impl Iterator<Item = Invariant> {
+    let disputes = account.disputes.values();
+    let held = amount::checked_sum(disputes.clone().copied());
+    let withdrawals_held =
+        amount::checked_sum(disputes.map(|disputed_amount| (*disputed_amount).min(Amount::ZERO)));
+    // Compared as `total + withdrawals held == available + held`, not to overflow on the way.
+    let total_matches = withdrawals_held
+        .and_then(|withdrawals_held| amount::checked_sum([account.total, withdrawals_held]))
+        .is_some_and(|total| amount::checked_sum([account.available, account.held]) == Some(total));
+    let held_matches = held == Some(account.held);
+    let locked_unchanged =
+        !account.changed_while_locked || house_account == Some(account.client_id);
+
+    [
+        (total_matches, Invariant::TotalMatchesBalances),
+        (held_matches, Invariant::HeldMatchesDisputes),
+        (locked_unchanged, Invariant::LockedAccountUnchanged),
+    ]
+    .into_iter()
+    .filter(|(holds, _)| !holds)
+    .map(|(_, invariant)| invariant)
+}
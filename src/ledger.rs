@@ -0,0 +1,129 @@
+use crate::amount;
+use crate::Amount;
+use crate::ClientId;
+use crate::Rejection;
+use serde::Deserialize;
+use serde::Serialize;
+use std::fmt;
+
+/// An account of the general ledger. The client accounts are where the balances of each client's
+/// `Account` are kept, the rest are the engine's own.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LedgerAccount {
+    ClientAvailable(ClientId),
+    ClientHeld(ClientId),
+    // Funds of disputed withdrawals that the client may get back on chargeback.
+    ClientDisputedWithdrawals(ClientId),
+    // Funds that came in or went out through deposits and withdrawals.
+    ExternalSettlement,
+    // Charged back withdrawals, which the client is repaid for.
+    ChargebackLoss,
+}
+
+impl LedgerAccount {
+    pub fn client_id(&self) -> Option<ClientId> {
+        match self {
+            LedgerAccount::ClientAvailable(client_id)
+            | LedgerAccount::ClientHeld(client_id)
+            | LedgerAccount::ClientDisputedWithdrawals(client_id) => Some(*client_id),
+            LedgerAccount::ExternalSettlement | LedgerAccount::ChargebackLoss => None,
+        }
+    }
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LedgerAccount::ClientAvailable(client_id) => {
+                write!(f, "client {} available", client_id)
+            }
+            LedgerAccount::ClientHeld(client_id) => write!(f, "client {} held", client_id),
+            LedgerAccount::ClientDisputedWithdrawals(client_id) => {
+                write!(f, "client {} disputed withdrawals", client_id)
+            }
+            LedgerAccount::ExternalSettlement => f.write_str("external settlement"),
+            LedgerAccount::ChargebackLoss => f.write_str("chargeback loss"),
+        }
+    }
+}
+
+/// Moves an amount from one ledger account to another. Every balance change is made by postings,
+/// so the ledger as a whole always sums up to zero.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Posting {
+    pub(crate) from: LedgerAccount,
+    pub(crate) to: LedgerAccount,
+    pub(crate) amount: Amount,
+}
+
+impl Posting {
+    pub(crate) fn new(from: LedgerAccount, to: LedgerAccount, amount: Amount) -> Self {
+        Posting { from, to, amount }
+    }
+
+    // The balances of `from` and `to` after the posting. The headroom of every balance is checked
+    // before anything is posted, so they never saturate.
+    pub(crate) fn debited(&self, balance: Amount) -> Amount {
+        balance.saturating_sub(self.amount)
+    }
+
+    pub(crate) fn credited(&self, balance: Amount) -> Amount {
+        balance.saturating_add(self.amount)
+    }
+}
+
+/// The engine's own side of the general ledger - the client side is kept by the accounts.
+#[derive(Deserialize, Serialize, Clone)]
+pub(crate) struct Ledger {
+    external_settlement: Amount,
+    chargeback_loss: Amount,
+}
+
+impl Ledger {
+    pub(crate) fn new() -> Self {
+        Ledger {
+            external_settlement: Amount::ZERO,
+            chargeback_loss: Amount::ZERO,
+        }
+    }
+
+    pub(crate) fn record(&mut self, posting: &Posting) {
+        self.adjust(posting.from, |balance| posting.debited(balance));
+        self.adjust(posting.to, |balance| posting.credited(balance));
+    }
+
+    pub(crate) fn check_headroom(&self, amount: Amount) -> Result<(), Rejection> {
+        let has_headroom = amount::has_headroom(self.external_settlement, amount)
+            && amount::has_headroom(self.chargeback_loss, amount);
+        if !has_headroom {
+            return Err(Rejection::AmountOverflow);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn balances(&self) -> [(LedgerAccount, Amount); 2] {
+        [
+            (LedgerAccount::ExternalSettlement, self.external_settlement),
+            (LedgerAccount::ChargebackLoss, self.chargeback_loss),
+        ]
+    }
+
+    // Sets the balance of one of the engine's own accounts, e.g. as loaded from storage.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn set_balance(&mut self, account: LedgerAccount, balance: Amount) {
+        match account {
+            LedgerAccount::ExternalSettlement => self.external_settlement = balance,
+            LedgerAccount::ChargebackLoss => self.chargeback_loss = balance,
+            _ => {}
+        }
+    }
+
+    fn adjust(&mut self, account: LedgerAccount, post: impl FnOnce(Amount) -> Amount) {
+        let balance = match account {
+            LedgerAccount::ExternalSettlement => &mut self.external_settlement,
+            LedgerAccount::ChargebackLoss => &mut self.chargeback_loss,
+            _ => return,
+        };
+        *balance = post(*balance);
+    }
+}
@@ -1,203 +1,1753 @@
-use rust_decimal::Decimal;
+mod amount;
+mod changes;
+mod checkpoint;
+mod compression;
+mod config;
+mod dialect;
+mod fast_parser;
+mod fees;
+mod generator;
+#[cfg(feature = "grpc")]
+mod grpc;
+#[cfg(feature = "http")]
+mod http;
+mod idempotency;
+mod invariants;
+mod ledger;
+mod limits;
+mod progress;
+mod server;
+mod snapshot;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+mod statement;
+#[cfg(feature = "async")]
+mod streaming;
+mod summary;
+
+pub use amount::Amount;
+#[cfg(not(feature = "decimal"))]
+pub use amount::ParseAmountError;
+pub use changes::BalanceChange;
+use changes::Balances;
+pub use changes::ChangeFeed;
+pub use changes::ChangeFormat;
+use checkpoint::CheckpointChanges;
+pub use checkpoint::Checkpoints;
+use checkpoint::Recording;
+pub use compression::decompress;
+pub use compression::open_csv;
+pub use compression::CompressedWriter;
+pub use compression::Compression;
+pub use config::DisputeRules;
+pub use config::Duplicates;
+pub use config::EngineConfig;
+pub use config::LockedAccounts;
+pub use config::NegativeAmounts;
+pub use config::OutputFormats;
+pub use config::Strictness;
+pub use config::SummaryFormat;
+pub use config::Verify;
+pub use dialect::CsvDialect;
+use fast_parser::RecordParser;
+pub use fees::FeeSchedule;
+pub use generator::Generator;
+#[cfg(feature = "grpc")]
+pub use grpc::proto;
+#[cfg(feature = "grpc")]
+pub use grpc::GrpcService;
+#[cfg(feature = "http")]
+pub use http::HttpServer;
+use idempotency::fnv1a;
+use idempotency::Applied;
+pub use idempotency::DuplicateConflict;
+use idempotency::Fingerprint;
+#[cfg(feature = "sqlite")]
+use idempotency::Kind;
+use idempotency::Recorded;
+use idempotency::Seen;
+use idempotency::FNV1A_START;
+pub use invariants::Invariant;
+pub use invariants::Violation;
+use ledger::Ledger;
+pub use ledger::LedgerAccount;
+use ledger::Posting;
+use limits::LimitUsage;
+pub use limits::Limits;
+pub use progress::Progress;
+pub use progress::ProgressReporter;
 use serde::Deserialize;
 use serde::Serialize;
+pub use server::Server;
+pub use snapshot::watch_snapshot_trigger;
+pub use snapshot::Snapshots;
+#[cfg(feature = "sqlite")]
+pub use sqlite::SqliteStore;
+pub use statement::EntryStatus;
+pub use statement::EntryType;
+use statement::Statement;
+pub use statement::StatementEntry;
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::error::Error;
+use std::fmt;
+use std::io;
 use std::io::Read;
+use std::io::Seek;
 use std::io::Write;
+use std::mem;
+#[cfg(feature = "async")]
+pub use streaming::process_csv_async;
+#[cfg(feature = "async")]
+pub use streaming::process_stream;
+#[cfg(feature = "async")]
+pub use streaming::Outcome;
+use summary::Stats;
+use summary::Stopwatch;
+pub use summary::Summary;
+pub use summary::TypeSummary;
 
-type ClientId = u16;
-type TransactionId = u32;
-type Amount = Decimal;
+pub type ClientId = u16;
+pub type TransactionId = u32;
 
-#[derive(Deserialize)]
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(rename_all = "lowercase")]
-enum TransactionType {
+pub enum TransactionType {
     Deposit,
     Withdrawal,
     Dispute,
     Resolve,
     Chargeback,
+    Transfer,
+}
+
+impl fmt::Display for TransactionType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let tx_type = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Transfer => "transfer",
+        };
+        f.write_str(tx_type)
+    }
 }
 
-#[derive(Deserialize)]
-struct Transaction {
+#[derive(Deserialize, Debug, Clone)]
+pub struct Transaction {
     #[serde(rename = "tx")]
-    id: TransactionId,
+    pub id: TransactionId,
     #[serde(rename = "type")]
-    tx_type: TransactionType,
+    pub tx_type: TransactionType,
     #[serde(rename = "client")]
-    client_id: ClientId,
-    #[serde(rename = "amount")]
-    amount: Option<Amount>,
+    pub client_id: ClientId,
+    #[serde(
+        rename = "amount",
+        default,
+        deserialize_with = "amount::deserialize_shortest"
+    )]
+    pub amount: Option<Amount>,
+    // Only present for transfers - the client receiving the transferred funds.
+    #[serde(rename = "to")]
+    pub destination_client_id: Option<ClientId>,
+    // Seconds since the epoch. Transactions without one are taken to happen at the time of the
+    // latest timestamped transaction.
+    #[serde(rename = "timestamp")]
+    pub timestamp: Option<u64>,
+}
+
+impl Transaction {
+    pub fn deposit(client_id: ClientId, id: TransactionId, amount: Amount) -> Self {
+        Self::new(TransactionType::Deposit, client_id, id, Some(amount))
+    }
+
+    pub fn withdrawal(client_id: ClientId, id: TransactionId, amount: Amount) -> Self {
+        Self::new(TransactionType::Withdrawal, client_id, id, Some(amount))
+    }
+
+    pub fn dispute(client_id: ClientId, id: TransactionId) -> Self {
+        Self::new(TransactionType::Dispute, client_id, id, None)
+    }
+
+    pub fn resolve(client_id: ClientId, id: TransactionId) -> Self {
+        Self::new(TransactionType::Resolve, client_id, id, None)
+    }
+
+    pub fn chargeback(client_id: ClientId, id: TransactionId) -> Self {
+        Self::new(TransactionType::Chargeback, client_id, id, None)
+    }
+
+    pub fn transfer(
+        client_id: ClientId,
+        id: TransactionId,
+        amount: Amount,
+        destination_client_id: ClientId,
+    ) -> Self {
+        Transaction {
+            destination_client_id: Some(destination_client_id),
+            ..Self::new(TransactionType::Transfer, client_id, id, Some(amount))
+        }
+    }
+
+    pub fn at(self, timestamp: u64) -> Self {
+        Transaction {
+            timestamp: Some(timestamp),
+            ..self
+        }
+    }
+
+    fn new(
+        tx_type: TransactionType,
+        client_id: ClientId,
+        id: TransactionId,
+        amount: Option<Amount>,
+    ) -> Self {
+        Transaction {
+            id,
+            tx_type,
+            client_id,
+            amount,
+            destination_client_id: None,
+            timestamp: None,
+        }
+    }
+}
+
+/// The reason a transaction was not applied.
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "snake_case")]
+pub enum Rejection {
+    MissingAmount,
+    NegativeAmount,
+    AmountTooLarge,
+    // The amount has more decimal places than an `Amount` holds.
+    AmountTooPrecise,
+    // A balance the transaction touches could overflow.
+    AmountOverflow,
+    MissingDestination,
+    InvalidDestination,
+    AccountLocked,
+    InsufficientFunds,
+    UnknownTransaction,
+    AlreadyDisputed,
+    NotDisputed,
+    WithdrawalLimitExceeded,
+    DailyWithdrawalLimitExceeded,
+    BalanceLimitExceeded,
+    TransactionLimitExceeded,
+    // The transaction has the id of one processed before, but a different content.
+    ConflictingDuplicate,
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let reason = match self {
+            Rejection::MissingAmount => "missing_amount",
+            Rejection::NegativeAmount => "negative_amount",
+            Rejection::AmountTooLarge => "amount_too_large",
+            Rejection::AmountTooPrecise => "amount_too_precise",
+            Rejection::AmountOverflow => "amount_overflow",
+            Rejection::MissingDestination => "missing_destination",
+            Rejection::InvalidDestination => "invalid_destination",
+            Rejection::AccountLocked => "account_locked",
+            Rejection::InsufficientFunds => "insufficient_funds",
+            Rejection::UnknownTransaction => "unknown_transaction",
+            Rejection::AlreadyDisputed => "already_disputed",
+            Rejection::NotDisputed => "not_disputed",
+            Rejection::WithdrawalLimitExceeded => "withdrawal_limit_exceeded",
+            Rejection::DailyWithdrawalLimitExceeded => "daily_withdrawal_limit_exceeded",
+            Rejection::BalanceLimitExceeded => "balance_limit_exceeded",
+            Rejection::TransactionLimitExceeded => "transaction_limit_exceeded",
+            Rejection::ConflictingDuplicate => "conflicting_duplicate",
+        };
+        f.write_str(reason)
+    }
+}
+
+#[derive(Deserialize, Serialize, Clone)]
+struct AppliedTransaction {
+    amount: Amount,
+    // Fee moved to the house account. Refunded on chargeback.
+    fee: Amount,
+    // Transfers move funds between our own clients and can not be disputed.
+    disputable: bool,
 }
 
 #[derive(Serialize)]
-struct Account {
+pub struct Account {
     #[serde(rename = "client")]
     client_id: ClientId,
+    #[serde(serialize_with = "amount::serialize")]
     available: Amount,
+    #[serde(serialize_with = "amount::serialize")]
     held: Amount,
+    #[serde(serialize_with = "amount::serialize")]
     total: Amount,
     locked: bool,
     #[serde(skip_serializing)]
-    transactions: HashMap<TransactionId, Amount>,
+    disputed_withdrawals: Amount,
+    // The overdraft the client is allowed - available funds may go down to minus this amount.
+    #[serde(skip_serializing)]
+    credit_limit: Amount,
+    #[serde(skip_serializing)]
+    transactions: HashMap<TransactionId, AppliedTransaction>,
     #[serde(skip_serializing)]
     disputes: HashMap<TransactionId, Amount>,
+    // The last transaction that changed the account, for reporting invariant violations.
+    #[serde(skip_serializing)]
+    last_transaction: Option<TransactionId>,
+    #[serde(skip_serializing)]
+    changed_while_locked: bool,
 }
 
 impl Account {
-    fn new(client_id: ClientId) -> Self {
+    fn new(client_id: ClientId, limits: Option<&Limits>) -> Self {
         Account {
-            client_id: client_id,
-            available: Decimal::new(0, 4),
-            held: Decimal::new(0, 4),
-            total: Decimal::new(0, 4),
+            client_id,
+            available: Amount::ZERO,
+            held: Amount::ZERO,
+            total: Amount::ZERO,
             locked: false,
+            disputed_withdrawals: Amount::ZERO,
+            credit_limit: limits.map_or(Amount::ZERO, |limits| limits.overdraft(client_id)),
             transactions: HashMap::new(),
             disputes: HashMap::new(),
+            last_transaction: None,
+            changed_while_locked: false,
         }
     }
 
-    fn deposit(&mut self, id: TransactionId, amount: Amount) {
-        // TODO: log to stderr if deposit of negative amount is encountered?
-        if amount.is_sign_positive() {
-            self.add(id, amount);
-        }
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
     }
 
-    fn withdraw(&mut self, id: TransactionId, amount: Amount) {
-        // TODO: log to stderr if withdrawal of negative amount is encountered?
-        if amount.is_sign_positive() {
-            self.add(id, -amount);
-        }
+    pub fn available(&self) -> Amount {
+        self.available
+    }
+
+    pub fn held(&self) -> Amount {
+        self.held
+    }
+
+    pub fn total(&self) -> Amount {
+        self.total
     }
 
-    fn dispute(&mut self, id: TransactionId) {
-        if let Some(disputed_amount) = self.transactions.get(&id) {
+    pub fn locked(&self) -> bool {
+        self.locked
+    }
+
+    pub fn credit_limit(&self) -> Amount {
+        self.credit_limit
+    }
+
+    // Disputes and chargebacks are not bound by the credit limit, so the used credit may exceed it.
+    pub fn credit_used(&self) -> Amount {
+        Amount::ZERO
+            .saturating_sub(self.available)
+            .max(Amount::ZERO)
+    }
+
+    fn deposit(
+        &mut self,
+        id: TransactionId,
+        amount: Amount,
+        fee: Amount,
+        ledger: &mut Ledger,
+    ) -> Result<(), Rejection> {
+        let moved = amount.checked_add(fee).ok_or(Rejection::AmountOverflow)?;
+        let credited = amount.checked_sub(fee).ok_or(Rejection::AmountOverflow)?;
+        let debited = fee.checked_sub(amount).ok_or(Rejection::AmountOverflow)?;
+        self.check_headroom(moved, ledger)?;
+        self.can_debit(debited)?;
+        self.post(
+            ledger,
+            LedgerAccount::ExternalSettlement,
+            LedgerAccount::ClientAvailable(self.client_id),
+            amount,
+        );
+        self.record(id, credited, fee, true);
+        Ok(())
+    }
+
+    fn withdraw(
+        &mut self,
+        id: TransactionId,
+        amount: Amount,
+        fee: Amount,
+        ledger: &mut Ledger,
+    ) -> Result<(), Rejection> {
+        let moved = amount.checked_add(fee).ok_or(Rejection::AmountOverflow)?;
+        let debited = amount::checked_neg(moved).ok_or(Rejection::AmountOverflow)?;
+        self.check_headroom(moved, ledger)?;
+        self.can_debit(moved)?;
+        self.post(
+            ledger,
+            LedgerAccount::ClientAvailable(self.client_id),
+            LedgerAccount::ExternalSettlement,
+            amount,
+        );
+        self.record(id, debited, fee, true);
+        Ok(())
+    }
+
+    fn dispute(
+        &mut self,
+        id: TransactionId,
+        ledger: &mut Ledger,
+        rules: &DisputeRules,
+        locked_accounts: LockedAccounts,
+    ) -> Result<(), Rejection> {
+        self.can_dispute(locked_accounts)?;
+        // if the transaction to dispute is not found, assume an error on the parner's side
+        let disputed_amount = self
+            .transactions
+            .get(&id)
+            .filter(|transaction| transaction.disputable)
+            .map(|transaction| transaction.amount)
+            .filter(|amount| rules.withdrawals || amount.is_sign_positive())
+            .ok_or(Rejection::UnknownTransaction)?;
+        if self.disputes.contains_key(&id) {
+            return Err(Rejection::AlreadyDisputed);
+        }
+        let held = amount::checked_abs(disputed_amount).ok_or(Rejection::AmountOverflow)?;
+        self.check_headroom(held, ledger)?;
+        if disputed_amount.is_sign_positive() {
             // Only decrease the available amount for disputed deposits.
-            if disputed_amount.is_sign_positive() {
-                self.available -= disputed_amount;
-            }
-            self.held += disputed_amount;
-            self.disputes.insert(id, *disputed_amount);
+            self.post(
+                ledger,
+                LedgerAccount::ClientAvailable(self.client_id),
+                LedgerAccount::ClientHeld(self.client_id),
+                disputed_amount,
+            );
+        } else {
+            // Disputed withdrawals are held as a negative amount that does not count towards the
+            // total until charged back.
+            self.post(
+                ledger,
+                LedgerAccount::ClientHeld(self.client_id),
+                LedgerAccount::ClientDisputedWithdrawals(self.client_id),
+                held,
+            );
         }
-        // if the transaction to dispute is not found, ignore and assume an error on the parner's side
+        self.disputes.insert(id, disputed_amount);
+        self.last_transaction = Some(id);
+        Ok(())
     }
 
-    fn resolve(&mut self, id: TransactionId) {
-        if let Some(disputed_amount) = self.disputes.remove(&id) {
+    // Disputes opened before the account was locked still settle.
+    fn resolve(&mut self, id: TransactionId, ledger: &mut Ledger) -> Result<(), Rejection> {
+        // if the dispute is not found, assume an error on the parner's side
+        let disputed_amount = *self.disputes.get(&id).ok_or(Rejection::NotDisputed)?;
+        let held = amount::checked_abs(disputed_amount).ok_or(Rejection::AmountOverflow)?;
+        self.check_headroom(held, ledger)?;
+        self.disputes.remove(&id);
+        if disputed_amount.is_sign_positive() {
             // Release available funds only for disputed deposits.
+            self.post(
+                ledger,
+                LedgerAccount::ClientHeld(self.client_id),
+                LedgerAccount::ClientAvailable(self.client_id),
+                disputed_amount,
+            );
+        } else {
             // Disputed withdrawals (negative disputed amount) do not increase the available
             // amount.
-            if disputed_amount.is_sign_positive() {
-                self.available += disputed_amount;
-            }
-            self.held -= disputed_amount;
+            self.post(
+                ledger,
+                LedgerAccount::ClientDisputedWithdrawals(self.client_id),
+                LedgerAccount::ClientHeld(self.client_id),
+                held,
+            );
         }
-        // if the dispute is not found, ignore and assume an error on the parner's side
+        self.last_transaction = Some(id);
+        Ok(())
     }
 
-    fn chargeback(&mut self, id: TransactionId) {
-        if let Some(disputed_amount) = self.disputes.remove(&id) {
-            self.held -= disputed_amount;
-            self.total -= disputed_amount;
+    // Returns the fee that was charged for the charged back transaction, for the engine to refund
+    // from the house account before locking the account.
+    fn chargeback(&mut self, id: TransactionId, ledger: &mut Ledger) -> Result<Amount, Rejection> {
+        // if the dispute is not found, assume an error on the parner's side
+        let disputed_amount = *self.disputes.get(&id).ok_or(Rejection::NotDisputed)?;
+        let fee = self.refunded_fee(id);
+        // A charged back withdrawal is posted twice, and the fee refunded on top.
+        let held = amount::checked_abs(disputed_amount).ok_or(Rejection::AmountOverflow)?;
+        let lost = held.checked_sub(fee).ok_or(Rejection::AmountOverflow)?;
+        let moved = amount::checked_sum([held, held, fee]).ok_or(Rejection::AmountOverflow)?;
+        self.check_headroom(moved, ledger)?;
+        self.disputes.remove(&id);
+        if disputed_amount.is_sign_positive() {
+            // The deposited funds are returned to where they came from.
+            self.post(
+                ledger,
+                LedgerAccount::ClientHeld(self.client_id),
+                LedgerAccount::ExternalSettlement,
+                disputed_amount,
+            );
+        } else {
             // If the disputed amount is negative, then a withdrawal was disputed.
-            // We should return the disputed amount on chargeback in this case.
-            if disputed_amount.is_sign_negative() {
-                self.available -= disputed_amount;
-            }
-            self.locked = true;
+            // We should return the disputed amount on chargeback in this case - the withdrawn
+            // funds are lost, the fee comes back from the house account.
+            self.post(
+                ledger,
+                LedgerAccount::ClientDisputedWithdrawals(self.client_id),
+                LedgerAccount::ClientHeld(self.client_id),
+                held,
+            );
+            self.post(
+                ledger,
+                LedgerAccount::ChargebackLoss,
+                LedgerAccount::ClientAvailable(self.client_id),
+                lost,
+            );
+        }
+        self.last_transaction = Some(id);
+        Ok(fee)
+    }
+
+    fn lock(&mut self) {
+        self.locked = true;
+    }
+
+    // The fee charged for a transaction, refunded when it is charged back.
+    fn refunded_fee(&self, id: TransactionId) -> Amount {
+        self.transactions
+            .get(&id)
+            .map_or(Amount::ZERO, |transaction| transaction.fee)
+    }
+
+    // A transaction's postings change any balance by no more than their sum. Making sure that much
+    // fits into every balance up front means a transaction is never left half-applied by an
+    // overflow - at the cost of rejecting transactions that come within their amount of it.
+    fn check_headroom(&self, amount: Amount, ledger: &Ledger) -> Result<(), Rejection> {
+        let has_headroom = [
+            self.available,
+            self.held,
+            self.disputed_withdrawals,
+            self.total,
+        ]
+        .into_iter()
+        .all(|balance| amount::has_headroom(balance, amount));
+        if !has_headroom {
+            return Err(Rejection::AmountOverflow);
         }
-        // if the dispute is not found, ignore and assume an error on the parner's side
+        ledger.check_headroom(amount)
     }
 
-    fn add(&mut self, id: TransactionId, amount: Amount) {
-        let new_available = self.available + amount;
-        // Only adjust balance if the account is not locked and the new available amount is not negative. Ignore the transaction otherwise.
-        if !self.locked && !new_available.is_sign_negative() {
-            self.available = new_available;
-            self.total += amount;
-            self.transactions.insert(id, amount);
+    fn can_credit(&self) -> Result<(), Rejection> {
+        if self.locked {
+            return Err(Rejection::AccountLocked);
+        }
+        Ok(())
+    }
+
+    fn can_dispute(&self, locked_accounts: LockedAccounts) -> Result<(), Rejection> {
+        match locked_accounts {
+            LockedAccounts::Reject => self.can_credit(),
+            LockedAccounts::AllowDisputes => Ok(()),
+        }
+    }
+
+    // Only adjust balance if the account is not locked and the new available amount does not go
+    // beyond the credit limit.
+    fn can_debit(&self, amount: Amount) -> Result<(), Rejection> {
+        self.can_credit()?;
+        // Saturating at the bounds gives the same answer as the exact difference would.
+        let available = self.available.saturating_sub(amount);
+        if available < Amount::ZERO.saturating_sub(self.credit_limit) {
+            return Err(Rejection::InsufficientFunds);
+        }
+        Ok(())
+    }
+
+    fn record(&mut self, id: TransactionId, amount: Amount, fee: Amount, disputable: bool) {
+        self.transactions.insert(
+            id,
+            AppliedTransaction {
+                amount,
+                fee,
+                disputable,
+            },
+        );
+        self.last_transaction = Some(id);
+    }
+
+    // For postings between this account and the engine's own ledger accounts.
+    fn post(
+        &mut self,
+        ledger: &mut Ledger,
+        from: LedgerAccount,
+        to: LedgerAccount,
+        amount: Amount,
+    ) {
+        let posting = Posting::new(from, to, amount);
+        ledger.record(&posting);
+        self.book(&posting);
+    }
+
+    // Applies this account's side(s) of the posting. The total is the sum of all of the client's
+    // ledger accounts.
+    fn book(&mut self, posting: &Posting) {
+        if self.locked {
+            self.changed_while_locked = true;
+        }
+        for (ledger_account, credited) in [(posting.from, false), (posting.to, true)] {
+            let post = |balance| match credited {
+                true => posting.credited(balance),
+                false => posting.debited(balance),
+            };
+            let balance = match ledger_account {
+                LedgerAccount::ClientAvailable(client_id) if client_id == self.client_id => {
+                    &mut self.available
+                }
+                LedgerAccount::ClientHeld(client_id) if client_id == self.client_id => {
+                    &mut self.held
+                }
+                LedgerAccount::ClientDisputedWithdrawals(client_id)
+                    if client_id == self.client_id =>
+                {
+                    &mut self.disputed_withdrawals
+                }
+                _ => continue,
+            };
+            *balance = post(*balance);
+            self.total = post(self.total);
         }
-        // TODO: should we log something to stderr when a transaction can not be handled?
     }
 }
 
-struct PaymentsEngine {
+pub struct PaymentsEngine {
     accounts: HashMap<ClientId, Account>,
+    ledger: Ledger,
+    fee_schedule: Option<FeeSchedule>,
+    limits: Option<Limits>,
+    limit_usage: HashMap<ClientId, LimitUsage>,
+    max_amount: Option<Amount>,
+    negative_amounts: NegativeAmounts,
+    locked_accounts: LockedAccounts,
+    dispute_rules: DisputeRules,
+    // The latest transaction timestamp seen.
+    clock: u64,
+    invariant_checks: bool,
+    violations: Vec<Violation>,
+    // Each violated invariant is reported only for the transaction first breaking it.
+    reported_violations: HashSet<(ClientId, Invariant)>,
+    statement: Option<Statement>,
+    snapshots: Option<Snapshots>,
+    change_feed: Option<ChangeFeed>,
+    // The transactions applied so far, when recognising those sent again.
+    applied: Option<Applied>,
+    duplicate_conflicts: Vec<DuplicateConflict>,
+    #[cfg(feature = "sqlite")]
+    sqlite_store: Option<SqliteStore>,
+    // What changed since the last checkpoint, while recording them.
+    checkpoint_changes: Option<CheckpointChanges>,
+    stats: Stats,
+}
+
+impl Default for PaymentsEngine {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl PaymentsEngine {
-    fn new() -> Self {
+    pub fn new() -> Self {
         PaymentsEngine {
             accounts: HashMap::new(),
+            ledger: Ledger::new(),
+            fee_schedule: None,
+            limits: None,
+            limit_usage: HashMap::new(),
+            max_amount: None,
+            negative_amounts: NegativeAmounts::default(),
+            locked_accounts: LockedAccounts::default(),
+            dispute_rules: DisputeRules::default(),
+            clock: 0,
+            invariant_checks: false,
+            violations: Vec::new(),
+            reported_violations: HashSet::new(),
+            statement: None,
+            snapshots: None,
+            change_feed: None,
+            applied: None,
+            duplicate_conflicts: Vec::new(),
+            #[cfg(feature = "sqlite")]
+            sqlite_store: None,
+            checkpoint_changes: None,
+            stats: Stats::default(),
+        }
+    }
+
+    pub fn with_fee_schedule(mut self, fee_schedule: FeeSchedule) -> Self {
+        self.fee_schedule = Some(fee_schedule);
+        self
+    }
+
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = Some(limits);
+        self
+    }
+
+    /// Rejects deposits, withdrawals and transfers of more than the given amount.
+    pub fn with_max_amount(mut self, max_amount: Amount) -> Self {
+        self.max_amount = Some(max_amount);
+        self
+    }
+
+    /// Sets what becomes of deposits, withdrawals and transfers of negative amounts, rejected by
+    /// default.
+    pub fn with_negative_amounts(mut self, negative_amounts: NegativeAmounts) -> Self {
+        self.negative_amounts = negative_amounts;
+        self
+    }
+
+    /// Sets whether a locked account can still have its transactions disputed, as it can not by
+    /// default.
+    pub fn with_locked_accounts(mut self, locked_accounts: LockedAccounts) -> Self {
+        self.locked_accounts = locked_accounts;
+        self
+    }
+
+    /// Sets what can be disputed and what a chargeback does, withdrawals being disputable and
+    /// chargebacks locking the account by default.
+    pub fn with_dispute_rules(mut self, dispute_rules: DisputeRules) -> Self {
+        self.dispute_rules = dispute_rules;
+        self
+    }
+
+    /// Checks the invariants of the accounts touched by each processed transaction - see
+    /// `violations`.
+    pub fn with_invariant_checks(mut self) -> Self {
+        self.invariant_checks = true;
+        self
+    }
+
+    /// Keeps the history of the given client's account, or of all accounts - see `statement`.
+    pub fn with_statement(mut self, client_id: Option<ClientId>) -> Self {
+        self.statement = Some(Statement::new(client_id));
+        self
+    }
+
+    /// Writes snapshots of the account states while processing transactions. A snapshot failing to
+    /// be written stops any more from being written - see `take_snapshot_error`.
+    pub fn with_snapshots(mut self, snapshots: Snapshots) -> Self {
+        self.snapshots = Some(snapshots);
+        self
+    }
+
+    /// Emits the balance changes of every transaction applied. A change failing to be emitted
+    /// stops any more from being emitted - see `take_change_feed_error`.
+    pub fn with_change_feed(mut self, change_feed: ChangeFeed) -> Self {
+        self.change_feed = Some(change_feed);
+        self
+    }
+
+    /// Saves every transaction applied to the database, starting from the state kept in it - the
+    /// accounts and ledger so far are replaced by the stored ones. A transaction failing to be
+    /// saved stops any more from being saved, and a CSV from being processed any further - see
+    /// `take_sqlite_error`.
+    #[cfg(feature = "sqlite")]
+    pub fn with_sqlite_store(mut self, mut sqlite_store: SqliteStore) -> Self {
+        self.accounts = mem::take(&mut sqlite_store.accounts);
+        self.ledger = mem::replace(&mut sqlite_store.ledger, Ledger::new());
+        if let Some(applied) = &mut self.applied {
+            applied.extend(mem::take(&mut sqlite_store.applied));
+        }
+        self.limit_usage = mem::take(&mut sqlite_store.limit_usage);
+        self.clock = sqlite_store.clock;
+        self.sqlite_store = Some(sqlite_store);
+        self
+    }
+
+    /// Recognises transactions processed before by their tx id and content, e.g. in a file sent again
+    /// that overlaps one already processed, and answers them with the outcome they had - applied or
+    /// rejected - rather than processing them again. A transaction with the id of one processed
+    /// before but a different client, type or amount is rejected as a conflicting duplicate - see
+    /// `duplicate_conflicts`. Disputes, resolves and chargebacks are recognised by the id of the
+    /// transaction they are about and their order among those of the same input, so a transaction
+    /// disputed again after a resolve is disputed again, while a file sent again disputes nothing
+    /// twice. A server counts them across its run instead, taking each as a new one.
+    pub fn with_idempotency(mut self) -> Self {
+        self.applied = Some(Applied::default());
+        #[cfg(feature = "sqlite")]
+        if let (Some(applied), Some(sqlite_store)) = (&mut self.applied, &mut self.sqlite_store) {
+            applied.extend(mem::take(&mut sqlite_store.applied));
+        }
+        self
+    }
+
+    pub fn account(&self, client_id: ClientId) -> Option<&Account> {
+        self.accounts.get(&client_id)
+    }
+
+    /// Invariant violations found while processing transactions with invariant checks on.
+    pub fn violations(&self) -> &[Violation] {
+        &self.violations
+    }
+
+    /// Transactions rejected for having the id of one processed before but a different content, with
+    /// idempotency on.
+    pub fn duplicate_conflicts(&self) -> &[DuplicateConflict] {
+        &self.duplicate_conflicts
+    }
+
+    /// Every transaction applied or rejected so far with the balances after it, when keeping a
+    /// statement.
+    pub fn statement(&self) -> &[StatementEntry] {
+        self.statement
+            .as_ref()
+            .map_or(&[], |statement| &statement.entries)
+    }
+
+    /// Counts of the transactions processed so far and the totals they moved.
+    pub fn summary(&self) -> Summary {
+        let held = self.accounts.values().fold(Amount::ZERO, |held, account| {
+            held.saturating_add(account.held)
+        });
+        let locked_accounts = self
+            .accounts
+            .values()
+            .filter(|account| account.locked)
+            .count();
+        self.stats.summary(held, locked_accounts)
+    }
+
+    /// Checks the invariants of every account, e.g. at the end of a run.
+    pub fn check_invariants(&self) -> Vec<Violation> {
+        let house_account = self.fee_schedule.as_ref().map(FeeSchedule::house_account);
+        let mut client_ids: Vec<&ClientId> = self.accounts.keys().collect();
+        client_ids.sort();
+        client_ids
+            .into_iter()
+            .map(|client_id| &self.accounts[client_id])
+            .flat_map(|account| {
+                invariants::violated_invariants(account, house_account).map(|invariant| Violation {
+                    client_id: account.client_id,
+                    transaction_id: account.last_transaction,
+                    invariant,
+                })
+            })
+            .collect()
+    }
+
+    /// Writes a snapshot of the account states now, whatever the interval of the snapshots.
+    pub fn write_snapshot(&mut self) -> Result<(), Box<dyn Error>> {
+        if self.snapshots.is_none() {
+            return Err("snapshots are not configured".into());
+        }
+        Ok(self.snapshot()?)
+    }
+
+    /// The error a snapshot failed with while processing transactions, if any.
+    pub fn take_snapshot_error(&mut self) -> Option<io::Error> {
+        self.snapshots.as_mut()?.error.take()
+    }
+
+    /// Flushes the balance changes emitted so far to their output.
+    pub fn flush_changes(&mut self) -> io::Result<()> {
+        match &mut self.change_feed {
+            Some(change_feed) => change_feed.flush(),
+            None => Ok(()),
+        }
+    }
+
+    /// The error a balance change failed to be emitted with, if any.
+    pub fn take_change_feed_error(&mut self) -> Option<io::Error> {
+        self.change_feed.as_mut()?.error.take()
+    }
+
+    /// The error a transaction failed to be saved to the database with, if any.
+    #[cfg(feature = "sqlite")]
+    pub fn take_sqlite_error(&mut self) -> Option<rusqlite::Error> {
+        self.sqlite_store.as_mut()?.error.take()
+    }
+
+    // Processes a transaction posted to a server, failing it rather than answering as if it was
+    // kept once the database failed to save it or one before it. Transactions are not saved after
+    // a failure, so none are applied any more either.
+    pub(crate) fn serve_transaction(
+        &mut self,
+        transaction: Transaction,
+    ) -> Result<Result<(), Rejection>, String> {
+        self.check_saved()?;
+        let result = self.process_transaction(transaction);
+        self.check_saved()?;
+        Ok(result)
+    }
+
+    fn check_saved(&self) -> Result<(), String> {
+        #[cfg(feature = "sqlite")]
+        if let Some(e) = self
+            .sqlite_store
+            .as_ref()
+            .and_then(|store| store.error.as_ref())
+        {
+            return Err(format!("failed to save a transaction: {}", e));
+        }
+        Ok(())
+    }
+
+    pub fn process_transaction(&mut self, transaction: Transaction) -> Result<(), Rejection> {
+        self.process_record(transaction, None)
+    }
+
+    // Processes a transaction read from a CSV, rejected right away when its amount could not be
+    // read - see `RecordParser::parse`.
+    pub(crate) fn process_record(
+        &mut self,
+        transaction: Transaction,
+        rejection: Option<Rejection>,
+    ) -> Result<(), Rejection> {
+        let (transaction, rejection) = self.check_negative_amount(transaction, rejection);
+        let (tx_type, amount) = (transaction.tx_type, transaction.amount);
+        let transaction_id = transaction.id;
+        let touched = self.touched_clients(&transaction);
+        if let Some(checkpoint_changes) = &mut self.checkpoint_changes {
+            checkpoint_changes.record(touched, transaction_id);
+        }
+        let fingerprint = self.applied.as_ref().map(|_| Fingerprint::of(&transaction));
+        let mut position = 0;
+        if let Some(fingerprint) = &fingerprint {
+            match self.skip_duplicate(&transaction, fingerprint) {
+                Ok(new_position) => position = new_position,
+                Err(result) => return result,
+            }
+        }
+        let balances_before = self.change_feed.is_some().then(|| self.balances(touched));
+        let charged_back = match tx_type {
+            TransactionType::Chargeback => self
+                .accounts
+                .get(&transaction.client_id)
+                .and_then(|account| account.disputes.get(&transaction.id))
+                .copied(),
+            _ => None,
+        };
+        let result = match rejection {
+            Some(rejection) => self.reject(&transaction, rejection),
+            None if self.invariant_checks || self.statement.is_some() => {
+                self.apply_and_observe(transaction)
+            }
+            None => self.apply(transaction),
+        };
+        self.stats.record(tx_type, amount, charged_back, result);
+        let recorded = fingerprint.map(|fingerprint| Recorded {
+            fingerprint,
+            position,
+            outcome: result,
+        });
+        if let (Some(applied), Some(recorded)) = (&mut self.applied, recorded) {
+            applied.record(recorded);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(mut sqlite_store) = self.sqlite_store.take() {
+            let transaction = (tx_type, transaction_id);
+            sqlite_store.save(self, transaction, result, touched, recorded);
+            self.sqlite_store = Some(sqlite_store);
+        }
+        if let (Some(balances_before), Ok(())) = (balances_before, result) {
+            self.emit_changes(transaction_id, balances_before);
+        }
+        if self.snapshots.is_some() {
+            self.snapshot_if_due(touched, result);
+        }
+        result
+    }
+
+    // The balances of each client once, as the house account may be the client of the transaction.
+    fn balances(&self, touched: [Option<ClientId>; 3]) -> [Option<(ClientId, Balances)>; 3] {
+        let mut balances = [None; 3];
+        for (i, client_id) in touched.into_iter().enumerate() {
+            if let Some(client_id) = client_id.filter(|id| !touched[..i].contains(&Some(*id))) {
+                balances[i] = Some((client_id, Balances::of(self.accounts.get(&client_id))));
+            }
+        }
+        balances
+    }
+
+    fn emit_changes(
+        &mut self,
+        transaction_id: TransactionId,
+        balances_before: [Option<(ClientId, Balances)>; 3],
+    ) {
+        let Some(change_feed) = &mut self.change_feed else {
+            return;
+        };
+        let accounts = &self.accounts;
+        let balances = balances_before
+            .into_iter()
+            .flatten()
+            .map(|(client_id, before)| (client_id, before, Balances::of(accounts.get(&client_id))));
+        change_feed.emit(transaction_id, balances);
+    }
+
+    // Skips a transaction processed before with the outcome it had, and rejects one conflicting with
+    // it. Otherwise gives the position the transaction takes - see `Recorded`.
+    fn skip_duplicate(
+        &mut self,
+        transaction: &Transaction,
+        fingerprint: &Fingerprint,
+    ) -> Result<u32, Result<(), Rejection>> {
+        let Some(applied) = &mut self.applied else {
+            return Ok(0);
+        };
+        match applied.seen(fingerprint) {
+            Seen::New(position) => Ok(position),
+            Seen::Duplicate(outcome) => {
+                self.stats.duplicates += 1;
+                Err(outcome)
+            }
+            Seen::Conflict => {
+                self.duplicate_conflicts.push(DuplicateConflict {
+                    transaction: transaction.clone(),
+                });
+                let result = Err(Rejection::ConflictingDuplicate);
+                self.stats
+                    .record(transaction.tx_type, transaction.amount, None, result);
+                Err(result)
+            }
+        }
+    }
+
+    // Disputes, resolves and chargebacks sent again are told apart from new ones by how far the
+    // input has come in the dispute lifecycle of their transaction - see `Applied::seen`.
+    pub(crate) fn start_input(&mut self) {
+        if let Some(applied) = &mut self.applied {
+            applied.start_input();
+        }
+    }
+
+    // Fails with the first error met writing out along the way - snapshots, the database or the
+    // change feed, flushed at the end of the input.
+    pub(crate) fn finish_input(&mut self) -> Result<(), String> {
+        if let Some(e) = self.take_snapshot_error() {
+            return Err(format!("failed to write a snapshot: {}", e));
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(e) = self.take_sqlite_error() {
+            return Err(format!("failed to save a transaction: {}", e));
+        }
+        let flushed = self.flush_changes();
+        if let Some(e) = self.take_change_feed_error().or(flushed.err()) {
+            return Err(format!("failed to emit the balance changes: {}", e));
+        }
+        Ok(())
+    }
+
+    // Negative amounts of deposits, withdrawals and transfers are rejected or taken as positive, as
+    // configured, before anything else sees them.
+    fn check_negative_amount(
+        &self,
+        transaction: Transaction,
+        rejection: Option<Rejection>,
+    ) -> (Transaction, Option<Rejection>) {
+        let negative_amount = transaction
+            .amount
+            .filter(|amount| !amount.is_sign_positive())
+            .filter(|_| {
+                matches!(
+                    transaction.tx_type,
+                    TransactionType::Deposit
+                        | TransactionType::Withdrawal
+                        | TransactionType::Transfer
+                )
+            });
+        let Some(amount) = negative_amount else {
+            return (transaction, rejection);
+        };
+        match self.negative_amounts {
+            NegativeAmounts::Reject => (transaction, rejection.or(Some(Rejection::NegativeAmount))),
+            NegativeAmounts::Absolute => {
+                // The most negative amount has no positive counterpart.
+                let Some(amount) = amount::checked_neg(amount) else {
+                    return (transaction, rejection.or(Some(Rejection::AmountOverflow)));
+                };
+                let transaction = Transaction {
+                    amount: Some(amount),
+                    ..transaction
+                };
+                (transaction, rejection)
+            }
+        }
+    }
+
+    // The clients whose accounts a transaction may change - the house account too, for the fees.
+    fn touched_clients(&self, transaction: &Transaction) -> [Option<ClientId>; 3] {
+        let house_account = self.fee_schedule.as_ref().map(FeeSchedule::house_account);
+        [
+            Some(transaction.client_id),
+            transaction.destination_client_id,
+            house_account,
+        ]
+    }
+
+    fn snapshot_if_due(&mut self, touched: [Option<ClientId>; 3], result: Result<(), Rejection>) {
+        let Some(snapshots) = &mut self.snapshots else {
+            return;
+        };
+        if result.is_ok() && snapshots.is_delta() {
+            snapshots.changed.extend(touched.into_iter().flatten());
+        }
+        if snapshots.transaction_processed() {
+            if let Err(e) = self.snapshot() {
+                self.snapshots.as_mut().expect("snapshots configured").error = Some(e);
+            }
+        }
+    }
+
+    fn snapshot(&mut self) -> io::Result<()> {
+        let snapshots = self.snapshots.as_mut().expect("snapshots configured");
+        let mut output = snapshots.open()?;
+        let changed = snapshots
+            .is_delta()
+            .then(|| mem::take(&mut snapshots.changed));
+        write_accounts(self, &mut output, changed.as_ref())?;
+        output.flush()
+    }
+
+    // Applies the transaction, checking the invariants and keeping the statement as configured.
+    fn apply_and_observe(&mut self, transaction: Transaction) -> Result<(), Rejection> {
+        let house_account = self.fee_schedule.as_ref().map(FeeSchedule::house_account);
+        let fee = self.charged_fee(&transaction);
+        let result = self.apply(transaction.clone());
+        if self.invariant_checks {
+            for client_id in self.touched_clients(&transaction).into_iter().flatten() {
+                self.check_account_invariants(client_id, transaction.id);
+            }
         }
+        if let Some(statement) = &mut self.statement {
+            statement.record(&self.accounts, &transaction, fee, house_account, result);
+        }
+        result
+    }
+
+    // Rejects the transaction without applying it, listing it on the statement as configured.
+    fn reject(&mut self, transaction: &Transaction, rejection: Rejection) -> Result<(), Rejection> {
+        let result = Err(rejection);
+        if let Some(statement) = &mut self.statement {
+            let house_account = self.fee_schedule.as_ref().map(FeeSchedule::house_account);
+            statement.record(
+                &self.accounts,
+                transaction,
+                Amount::ZERO,
+                house_account,
+                result,
+            );
+        }
+        result
     }
 
-    fn process_transaction(&mut self, transaction: Transaction) {
+    fn apply(&mut self, transaction: Transaction) -> Result<(), Rejection> {
+        if let Some(timestamp) = transaction.timestamp {
+            self.clock = self.clock.max(timestamp);
+        }
+        if let (Some(max_amount), Some(amount)) = (self.max_amount, transaction.amount) {
+            if amount > max_amount {
+                return Err(Rejection::AmountTooLarge);
+            }
+        }
+        if let TransactionType::Transfer = transaction.tx_type {
+            return self.transfer(transaction);
+        }
+
+        let fee = self.fee(&transaction)?;
+        let house_fee = match transaction.tx_type {
+            TransactionType::Chargeback => self.refunded_fee(&transaction),
+            _ => fee,
+        };
+        self.check_house_account(house_fee)?;
         let account = self
             .accounts
             .entry(transaction.client_id)
-            .or_insert_with(|| Account::new(transaction.client_id));
+            .or_insert_with(|| Account::new(transaction.client_id, self.limits.as_ref()));
+        let ledger = &mut self.ledger;
+        // Disputes, resolves and chargebacks settle transactions from before the account was
+        // locked, so they do not count as changing it - see `Invariant::LockedAccountUnchanged`.
+        let changed_while_locked = account.changed_while_locked;
 
         match transaction.tx_type {
             TransactionType::Deposit => {
-                if let Some(amount) = transaction.amount {
-                    account.deposit(transaction.id, amount)
+                // TODO: panic with a pointer to this bad data entry? Or stderr and skip?
+                let amount = transaction.amount.ok_or(Rejection::MissingAmount)?;
+                if let Some(limits) = &self.limits {
+                    let usage = self.limit_usage.entry(transaction.client_id).or_default();
+                    let credited = amount.checked_sub(fee).ok_or(Rejection::AmountOverflow)?;
+                    let new_total = account
+                        .total
+                        .checked_add(credited)
+                        .ok_or(Rejection::AmountOverflow)?;
+                    limits.check_deposit(transaction.client_id, usage, new_total, self.clock)?;
                 }
-                // TODO: else panic with a pointer to this bad data entry? Or stderr and skip?
+                account.deposit(transaction.id, amount, fee, ledger)?;
+                self.record_limit_usage(transaction.client_id, None);
+                self.collect_fee(transaction.client_id, transaction.id, fee);
             }
             TransactionType::Withdrawal => {
-                if let Some(amount) = transaction.amount {
-                    account.withdraw(transaction.id, amount)
+                // TODO: panic with a pointer to this bad data entry? Or stderr and skip?
+                let amount = transaction.amount.ok_or(Rejection::MissingAmount)?;
+                if let Some(limits) = &self.limits {
+                    let usage = self.limit_usage.entry(transaction.client_id).or_default();
+                    limits.check_withdrawal(transaction.client_id, usage, amount, self.clock)?;
+                }
+                account.withdraw(transaction.id, amount, fee, ledger)?;
+                self.record_limit_usage(transaction.client_id, Some(amount));
+                self.collect_fee(transaction.client_id, transaction.id, fee);
+            }
+            TransactionType::Dispute => account.dispute(
+                transaction.id,
+                ledger,
+                &self.dispute_rules,
+                self.locked_accounts,
+            )?,
+            TransactionType::Resolve => account.resolve(transaction.id, ledger)?,
+            TransactionType::Chargeback => {
+                let deposit = account
+                    .disputes
+                    .get(&transaction.id)
+                    .is_some_and(|disputed_amount| disputed_amount.is_sign_positive());
+                let fee = account.chargeback(transaction.id, ledger)?;
+                self.refund_fee(transaction.client_id, fee, deposit);
+                if self.dispute_rules.lock_on_chargeback {
+                    self.accounts
+                        .get_mut(&transaction.client_id)
+                        .expect("charged back account is open")
+                        .lock();
                 }
-                // TODO: else panic with a pointer to this bad data entry? Or stderr and skip?
             }
-            TransactionType::Dispute => account.dispute(transaction.id),
-            TransactionType::Resolve => account.resolve(transaction.id),
-            TransactionType::Chargeback => account.chargeback(transaction.id),
+            TransactionType::Transfer => unreachable!("transfers are handled separately"),
         }
+        if let TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback =
+            transaction.tx_type
+        {
+            self.accounts
+                .get_mut(&transaction.client_id)
+                .expect("disputing account is open")
+                .changed_while_locked = changed_while_locked;
+        }
+        Ok(())
+    }
+
+    /// Balances of all the ledger accounts.
+    pub fn trial_balance(&self) -> Vec<(LedgerAccount, Amount)> {
+        let mut client_ids: Vec<&ClientId> = self.accounts.keys().collect();
+        client_ids.sort();
+        client_ids
+            .into_iter()
+            .map(|client_id| &self.accounts[client_id])
+            .flat_map(|account| {
+                [
+                    (
+                        LedgerAccount::ClientAvailable(account.client_id),
+                        account.available,
+                    ),
+                    (LedgerAccount::ClientHeld(account.client_id), account.held),
+                    (
+                        LedgerAccount::ClientDisputedWithdrawals(account.client_id),
+                        account.disputed_withdrawals,
+                    ),
+                ]
+            })
+            .chain(self.ledger.balances())
+            .collect()
+    }
+
+    /// Verifies that the ledger sums up to zero and that every account's total is the sum of its
+    /// ledger accounts.
+    pub fn check_ledger(&self) -> Result<(), Box<dyn Error>> {
+        for account in self.accounts.values() {
+            let balances = [
+                account.available,
+                account.held,
+                account.disputed_withdrawals,
+            ];
+            if amount::checked_sum(balances) != Some(account.total) {
+                return Err(format!(
+                    "ledger accounts of client {} do not add up to its total",
+                    account.client_id
+                )
+                .into());
+            }
+        }
+        let sum = amount::checked_sum(self.trial_balance().into_iter().map(|(_, balance)| balance));
+        match sum {
+            Some(sum) if sum.is_zero() => {}
+            Some(sum) => return Err(format!("ledger is out of balance by {}", sum).into()),
+            None => return Err("ledger is out of balance".into()),
+        }
+        Ok(())
+    }
+
+    fn check_account_invariants(&mut self, client_id: ClientId, id: TransactionId) {
+        let house_account = self.fee_schedule.as_ref().map(FeeSchedule::house_account);
+        let Some(account) = self.accounts.get(&client_id) else {
+            return;
+        };
+        for invariant in invariants::violated_invariants(account, house_account) {
+            if self.reported_violations.insert((client_id, invariant)) {
+                self.violations.push(Violation {
+                    client_id,
+                    transaction_id: Some(id),
+                    invariant,
+                });
+            }
+        }
+    }
+
+    fn record_limit_usage(&mut self, client_id: ClientId, withdrawn: Option<Amount>) {
+        if let Some(limits) = &self.limits {
+            let usage = self.limit_usage.entry(client_id).or_default();
+            limits.record(client_id, usage, withdrawn, self.clock);
+        }
+    }
+
+    // The fee a transaction would charge, or refund when a chargeback.
+    fn charged_fee(&self, transaction: &Transaction) -> Amount {
+        match transaction.tx_type {
+            // Fees are never negative, so their negation does not saturate.
+            TransactionType::Chargeback => {
+                Amount::ZERO.saturating_sub(self.refunded_fee(transaction))
+            }
+            _ => self.fee(transaction).unwrap_or(Amount::ZERO),
+        }
+    }
+
+    fn refunded_fee(&self, transaction: &Transaction) -> Amount {
+        self.accounts
+            .get(&transaction.client_id)
+            .map_or(Amount::ZERO, |account| account.refunded_fee(transaction.id))
+    }
+
+    fn fee(&self, transaction: &Transaction) -> Result<Amount, Rejection> {
+        let fee = match (&self.fee_schedule, transaction.amount) {
+            (Some(fee_schedule), Some(amount)) => match transaction.tx_type {
+                TransactionType::Deposit => fee_schedule.deposit_fee(transaction.client_id, amount),
+                TransactionType::Withdrawal => {
+                    fee_schedule.withdrawal_fee(transaction.client_id, amount)
+                }
+                _ => Some(Amount::ZERO),
+            },
+            _ => Some(Amount::ZERO),
+        };
+        fee.ok_or(Rejection::AmountOverflow)
+    }
+
+    // The house account has to have room for the fee it collects or refunds.
+    fn check_house_account(&self, fee: Amount) -> Result<(), Rejection> {
+        let house_account = match &self.fee_schedule {
+            Some(fee_schedule) if !fee.is_zero() => fee_schedule.house_account(),
+            _ => return Ok(()),
+        };
+        match self.accounts.get(&house_account) {
+            Some(account) => account.check_headroom(fee, &self.ledger),
+            None => self.ledger.check_headroom(fee),
+        }
+    }
+
+    // Fees are our own bookkeeping, so the house account takes them even when locked.
+    fn collect_fee(&mut self, client_id: ClientId, id: TransactionId, fee: Amount) {
+        if let Some(house_account) = self.house_account(fee) {
+            self.post(Posting::new(
+                LedgerAccount::ClientAvailable(client_id),
+                LedgerAccount::ClientAvailable(house_account),
+                fee,
+            ));
+            self.accounts
+                .get_mut(&house_account)
+                .expect("house account is open")
+                .record(id, fee, Amount::ZERO, false);
+        }
+    }
+
+    // On chargeback the fee is given back by the house account along with the charged back funds -
+    // returned with the deposit it was charged for, or repaid to the client with the withdrawal.
+    fn refund_fee(&mut self, client_id: ClientId, fee: Amount, deposit: bool) {
+        if let Some(house_account) = self.house_account(fee) {
+            let refunded_to = if deposit {
+                LedgerAccount::ExternalSettlement
+            } else {
+                LedgerAccount::ClientAvailable(client_id)
+            };
+            self.post(Posting::new(
+                LedgerAccount::ClientAvailable(house_account),
+                refunded_to,
+                fee,
+            ));
+        }
+    }
+
+    fn house_account(&mut self, fee: Amount) -> Option<ClientId> {
+        let house_account = self.fee_schedule.as_ref()?.house_account();
+        if fee.is_zero() {
+            return None;
+        }
+        self.accounts
+            .entry(house_account)
+            .or_insert_with(|| Account::new(house_account, self.limits.as_ref()));
+        Some(house_account)
+    }
+
+    // For postings that may be between two clients' accounts.
+    fn post(&mut self, posting: Posting) {
+        self.ledger.record(&posting);
+        let from = posting.from.client_id();
+        // An account books both sides of a posting within it at once.
+        let to = posting.to.client_id().filter(|to| Some(*to) != from);
+        for client_id in [from, to].into_iter().flatten() {
+            if let Some(account) = self.accounts.get_mut(&client_id) {
+                account.book(&posting);
+            }
+        }
+    }
+
+    // A transfer either debits the source and credits the destination account, or is rejected as
+    // a whole - when the amount or the destination is missing, the source lacks funds, either
+    // account is locked or a limit would be breached.
+    fn transfer(&mut self, transaction: Transaction) -> Result<(), Rejection> {
+        // TODO: panic with a pointer to this bad data entry? Or stderr and skip?
+        let amount = transaction.amount.ok_or(Rejection::MissingAmount)?;
+        let destination_client_id = transaction
+            .destination_client_id
+            .ok_or(Rejection::MissingDestination)?;
+        if destination_client_id == transaction.client_id {
+            return Err(Rejection::InvalidDestination);
+        }
+        // A new destination account is only opened once the transfer is sure to be applied.
+        let new_destination;
+        let destination = match self.accounts.get(&destination_client_id) {
+            Some(destination) => destination,
+            None => {
+                new_destination = Account::new(destination_client_id, self.limits.as_ref());
+                &new_destination
+            }
+        };
+        destination.can_credit()?;
+        destination.check_headroom(amount, &self.ledger)?;
+        if let Some(limits) = &self.limits {
+            let new_total = destination
+                .total
+                .checked_add(amount)
+                .ok_or(Rejection::AmountOverflow)?;
+            limits.check_transfer_in(destination_client_id, new_total)?;
+        }
+
+        let source = self
+            .accounts
+            .entry(transaction.client_id)
+            .or_insert_with(|| Account::new(transaction.client_id, self.limits.as_ref()));
+        let debited = amount::checked_neg(amount).ok_or(Rejection::AmountOverflow)?;
+        source.check_headroom(amount, &self.ledger)?;
+        source.can_debit(amount)?;
+        if let Some(limits) = &self.limits {
+            let usage = self.limit_usage.entry(transaction.client_id).or_default();
+            limits.check_transfer_out(transaction.client_id, usage, amount, self.clock)?;
+        }
+        source.record(transaction.id, debited, Amount::ZERO, false);
+        self.record_limit_usage(transaction.client_id, Some(amount));
+
+        self.accounts
+            .entry(destination_client_id)
+            .or_insert_with(|| Account::new(destination_client_id, self.limits.as_ref()))
+            .record(transaction.id, amount, Amount::ZERO, false);
+        self.post(Posting::new(
+            LedgerAccount::ClientAvailable(transaction.client_id),
+            LedgerAccount::ClientAvailable(destination_client_id),
+            amount,
+        ));
+        Ok(())
     }
 }
 
 pub fn run(transactions_csv: impl Read, output: &mut impl Write) -> Result<(), Box<dyn Error>> {
-    let mut payments_engine = PaymentsEngine::new();
+    run_with_engine(PaymentsEngine::new(), transactions_csv, output)
+}
+
+pub fn run_with_engine(
+    mut payments_engine: PaymentsEngine,
+    transactions_csv: impl Read,
+    output: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
     process_csv(transactions_csv, &mut payments_engine)?;
-    write_account_states_to_csv(payments_engine.accounts, output)
+    payments_engine.check_ledger()?;
+    write_account_states_to_csv(&payments_engine, output)
+}
+
+pub fn process_csv(
+    transactions_csv: impl Read,
+    payments_engine: &mut PaymentsEngine,
+) -> Result<(), Box<dyn Error>> {
+    read_csv(
+        transactions_csv,
+        payments_engine,
+        &CsvDialect::new(),
+        None,
+        true,
+    )
+}
+
+// Only deserializes the transactions with serde, to compare the fast path against.
+#[doc(hidden)]
+pub fn process_csv_with_serde(
+    transactions_csv: impl Read,
+    payments_engine: &mut PaymentsEngine,
+) -> Result<(), Box<dyn Error>> {
+    read_csv(
+        transactions_csv,
+        payments_engine,
+        &CsvDialect::new(),
+        None,
+        false,
+    )
+}
+
+/// Processes the transactions like `process_csv`, reporting the progress along the way.
+pub fn process_csv_with_progress(
+    transactions_csv: impl Read,
+    payments_engine: &mut PaymentsEngine,
+    progress: &mut ProgressReporter,
+) -> Result<(), Box<dyn Error>> {
+    read_csv(
+        transactions_csv,
+        payments_engine,
+        &CsvDialect::new(),
+        Some(progress),
+        true,
+    )
+}
+
+/// Processes the transactions of a file laid out in the given dialect, reporting the progress
+/// along the way when given a reporter.
+pub fn process_csv_with_dialect(
+    transactions_csv: impl Read,
+    payments_engine: &mut PaymentsEngine,
+    dialect: &CsvDialect,
+    progress: Option<&mut ProgressReporter>,
+) -> Result<(), Box<dyn Error>> {
+    read_csv(transactions_csv, payments_engine, dialect, progress, true)
 }
 
-fn process_csv(
+/// Processes the transactions like `process_csv_with_dialect`, recording checkpoints along the
+/// way. When resuming from a checkpoint, the engine is restored from it and the input read on from
+/// where it was made, so every transaction is applied exactly once over the runs - given the same
+/// engine configuration. Resuming fails when the input up to there is not the one the checkpoint
+/// was made from.
+pub fn process_csv_with_checkpoints(
+    transactions_csv: impl Read + Seek,
+    payments_engine: &mut PaymentsEngine,
+    dialect: &CsvDialect,
+    checkpoints: &Checkpoints,
+    progress: Option<&mut ProgressReporter>,
+) -> Result<(), Box<dyn Error>> {
+    let mut rdr = dialect.reader(transactions_csv)?;
+    let parser = RecordParser::new(dialect.headers(&mut rdr)?, true);
+    let mut rows = 0;
+    let mut recording = Recording::new(checkpoints);
+    if let Some((rows_processed, position)) = recording.restore(payments_engine, rdr.get_mut())? {
+        rdr.seek(position)?;
+        rows = rows_processed;
+    }
+    let result = read_records(
+        rdr,
+        &parser,
+        payments_engine,
+        dialect,
+        progress,
+        Some(recording),
+        rows,
+    );
+    payments_engine.checkpoint_changes = None;
+    result
+}
+
+// The records are parsed straight from their bytes when possible, and trimmed and deserialized by
+// serde otherwise - trimming the fields of each record up front is costly.
+fn read_csv(
     transactions_csv: impl Read,
     payments_engine: &mut PaymentsEngine,
+    dialect: &CsvDialect,
+    progress: Option<&mut ProgressReporter>,
+    fast_path: bool,
 ) -> Result<(), Box<dyn Error>> {
-    let mut rdr = csv::ReaderBuilder::new()
-        .trim(csv::Trim::All)
-        .flexible(true)
-        .from_reader(transactions_csv);
+    let mut rdr = dialect.reader(transactions_csv)?;
+    let parser = RecordParser::new(dialect.headers(&mut rdr)?, fast_path);
+    read_records(rdr, &parser, payments_engine, dialect, progress, None, 0)
+}
 
-    let headers = rdr.byte_headers()?.clone();
+// Checkpoints, when recorded, carry on from the rows already processed.
+fn read_records<R: Read>(
+    mut rdr: csv::Reader<R>,
+    parser: &RecordParser,
+    payments_engine: &mut PaymentsEngine,
+    dialect: &CsvDialect,
+    mut progress: Option<&mut ProgressReporter>,
+    mut checkpoints: Option<Recording<'_, R>>,
+    mut rows: u64,
+) -> Result<(), Box<dyn Error>> {
     let mut raw_record = csv::ByteRecord::new();
 
+    if rows == 0 {
+        payments_engine.start_input();
+    }
+    let mut stopwatch = Stopwatch::start();
+    if let Some(progress) = progress.as_mut() {
+        progress.start();
+    }
     while rdr.read_byte_record(&mut raw_record)? {
-        let transaction: Transaction = raw_record.deserialize(Some(&headers))?;
-        payments_engine.process_transaction(transaction);
+        let (transaction, rejection) = parser.parse(&mut raw_record, dialect)?;
+        // Rejected transactions are ignored, assuming an error on the partner's side.
+        let _ = payments_engine.process_record(transaction, rejection);
+        // Nothing is applied past a transaction that failed to be saved.
+        payments_engine.check_saved()?;
+        rows += 1;
+        if let Some(progress) = progress.as_mut() {
+            progress.row_processed(rows, rdr.position().byte());
+        }
+        let due = checkpoints
+            .as_mut()
+            .filter(|checkpoints| checkpoints.is_due(rows));
+        if let Some(checkpoints) = due {
+            // The time so far is part of the checkpoint, for the summary of a resumed run.
+            stopwatch.stop(&mut payments_engine.stats);
+            checkpoints.record(payments_engine, rows, &mut rdr)?;
+        }
     }
+    stopwatch.stop(&mut payments_engine.stats);
+    if let Some(progress) = progress {
+        progress.finish(rows, rdr.position().byte());
+    }
+    if let Some(mut checkpoints) = checkpoints {
+        checkpoints.record(payments_engine, rows, &mut rdr)?;
+    }
+    Ok(payments_engine.finish_input()?)
+}
 
-    Ok(())
+#[derive(Serialize)]
+struct AccountWithCredit {
+    client: ClientId,
+    available: Amount,
+    held: Amount,
+    total: Amount,
+    locked: bool,
+    credit_limit: Amount,
+    credit_used: Amount,
 }
 
-fn write_account_states_to_csv(
-    accounts: HashMap<ClientId, Account>,
+impl From<&Account> for AccountWithCredit {
+    fn from(account: &Account) -> Self {
+        AccountWithCredit {
+            client: account.client_id,
+            available: amount::fixed_scale(account.available),
+            held: amount::fixed_scale(account.held),
+            total: amount::fixed_scale(account.total),
+            locked: account.locked,
+            credit_limit: amount::fixed_scale(account.credit_limit),
+            credit_used: amount::fixed_scale(account.credit_used()),
+        }
+    }
+}
+
+pub fn write_account_states_to_csv(
+    payments_engine: &PaymentsEngine,
     output: &mut impl Write,
 ) -> Result<(), Box<dyn Error>> {
+    Ok(write_accounts(payments_engine, output, None)?)
+}
+
+// Writes the accounts of the given clients, or of all clients.
+fn write_accounts(
+    payments_engine: &PaymentsEngine,
+    output: &mut impl Write,
+    client_ids: Option<&HashSet<ClientId>>,
+) -> csv::Result<()> {
+    let with_credit = with_credit(payments_engine);
     let mut wtr = csv::Writer::from_writer(output);
 
-    for account in accounts.values() {
-        if !account.transactions.is_empty() {
-            wtr.serialize(account)?;
+    let accounts: Box<dyn Iterator<Item = &Account>> = match client_ids {
+        Some(client_ids) => Box::new(
+            client_ids
+                .iter()
+                .filter_map(|client_id| payments_engine.accounts.get(client_id)),
+        ),
+        None => Box::new(payments_engine.accounts.values()),
+    };
+    for account in accounts {
+        if account.transactions.is_empty() {
+            continue;
         }
+        write_account(&mut wtr, account, with_credit)?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+// The credit columns are only written when any client is allowed an overdraft.
+fn with_credit(payments_engine: &PaymentsEngine) -> bool {
+    payments_engine
+        .limits
+        .as_ref()
+        .is_some_and(Limits::has_overdraft)
+}
+
+fn write_account(
+    wtr: &mut csv::Writer<impl Write>,
+    account: &Account,
+    with_credit: bool,
+) -> csv::Result<()> {
+    if with_credit {
+        wtr.serialize(AccountWithCredit::from(account))
+    } else {
+        wtr.serialize(account)
+    }
+}
+
+// The account as a row of `write_account_states_to_csv`, without the header.
+fn account_to_csv_row(
+    payments_engine: &PaymentsEngine,
+    account: &Account,
+) -> Result<String, Box<dyn Error>> {
+    let mut wtr = csv::WriterBuilder::new()
+        .has_headers(false)
+        .from_writer(Vec::new());
+    write_account(&mut wtr, account, with_credit(payments_engine))?;
+    let row = String::from_utf8(wtr.into_inner()?)?;
+    Ok(row.trim_end().to_string())
+}
+
+pub fn write_statement_to_csv(
+    payments_engine: &PaymentsEngine,
+    output: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(output);
+
+    for entry in payments_engine.statement() {
+        wtr.serialize(entry)?;
+    }
+
+    wtr.flush()?;
+
+    Ok(())
+}
+
+pub fn write_trial_balance_to_csv(
+    payments_engine: &PaymentsEngine,
+    output: &mut impl Write,
+) -> Result<(), Box<dyn Error>> {
+    let mut wtr = csv::Writer::from_writer(output);
+    wtr.write_record(["account", "balance"])?;
+
+    let trial_balance = payments_engine.trial_balance();
+    for (ledger_account, balance) in &trial_balance {
+        wtr.write_record([
+            ledger_account.to_string(),
+            amount::fixed_scale(*balance).to_string(),
+        ])?;
     }
+    let sum = amount::checked_sum(trial_balance.into_iter().map(|(_, balance)| balance))
+        .ok_or("trial balance is out of range")?;
+    wtr.write_record(["total".to_string(), amount::fixed_scale(sum).to_string()])?;
 
     wtr.flush()?;
 
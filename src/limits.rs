@@ -0,0 +1,312 @@
+use crate::amount;
+use crate::Amount;
+use crate::ClientId;
+use crate::Rejection;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const DAY: u64 = 24 * 60 * 60;
+
+/// Per-client limits enforced by the engine - a default applying to every client, with per-client
+/// overrides of individual limits. A limit that is not configured is not enforced.
+///
+/// ```toml
+/// [default]
+/// max_withdrawal = "1000"
+/// max_daily_withdrawal = "2500"
+/// max_balance = "100000"
+/// max_transactions = 100
+/// transaction_period = 3600
+///
+/// [[clients]]
+/// client = 1
+/// max_withdrawal = "5000"
+/// overdraft = "500"
+/// ```
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Limits {
+    #[serde(default)]
+    default: ClientLimits,
+    #[serde(default, rename = "clients")]
+    overrides: Vec<ClientOverride>,
+    #[serde(skip)]
+    clients: HashMap<ClientId, ClientLimits>,
+}
+
+#[derive(Deserialize, Default, Clone, Copy)]
+#[serde(deny_unknown_fields)]
+struct ClientLimits {
+    max_withdrawal: Option<Amount>,
+    // Withdrawn within the last 24 hours.
+    max_daily_withdrawal: Option<Amount>,
+    max_balance: Option<Amount>,
+    // Deposits, withdrawals and outgoing transfers within the transaction period.
+    max_transactions: Option<usize>,
+    // In seconds, defaults to a day.
+    transaction_period: Option<u64>,
+    // Credit line - how far below zero the available funds may go.
+    overdraft: Option<Amount>,
+}
+
+// The limits of `ClientLimits` along with the client, spelled out rather than flattened for unknown
+// fields to be rejected.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ClientOverride {
+    client: ClientId,
+    max_withdrawal: Option<Amount>,
+    max_daily_withdrawal: Option<Amount>,
+    max_balance: Option<Amount>,
+    max_transactions: Option<usize>,
+    transaction_period: Option<u64>,
+    overdraft: Option<Amount>,
+}
+
+/// Recent activity of a client, as far as the limits are concerned.
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub(crate) struct LimitUsage {
+    withdrawals: VecDeque<(u64, Amount)>,
+    // The sum of the withdrawals, kept as they come and go. Summed up again when not known, e.g.
+    // once it took more than can be represented.
+    #[serde(skip)]
+    withdrawn: Option<Amount>,
+    transactions: VecDeque<u64>,
+}
+
+impl Limits {
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        Self::from_toml(&fs::read_to_string(path)?)
+    }
+
+    pub fn from_toml(config: &str) -> Result<Self, Box<dyn Error>> {
+        let mut limits: Limits = toml::from_str(config)?;
+        limits.default.validate("default")?;
+        for client_override in &limits.overrides {
+            let client_id = client_override.client;
+            let client_limits = client_override.limits();
+            client_limits.validate(&format!("client {}", client_id))?;
+            let merged = client_limits.or(&limits.default);
+            if limits.clients.insert(client_id, merged).is_some() {
+                return Err(
+                    format!("client {} has more than one limits override", client_id).into(),
+                );
+            }
+        }
+        Ok(limits)
+    }
+
+    fn for_client(&self, client_id: ClientId) -> &ClientLimits {
+        self.clients.get(&client_id).unwrap_or(&self.default)
+    }
+
+    pub(crate) fn overdraft(&self, client_id: ClientId) -> Amount {
+        self.for_client(client_id).overdraft.unwrap_or(Amount::ZERO)
+    }
+
+    pub(crate) fn has_overdraft(&self) -> bool {
+        self.clients.values().chain([&self.default]).any(|limits| {
+            limits
+                .overdraft
+                .is_some_and(|overdraft| !overdraft.is_zero())
+        })
+    }
+
+    pub(crate) fn check_deposit(
+        &self,
+        client_id: ClientId,
+        usage: &mut LimitUsage,
+        new_total: Amount,
+        now: u64,
+    ) -> Result<(), Rejection> {
+        let limits = self.for_client(client_id);
+        limits.check_transaction_count(usage, now)?;
+        limits.check_balance(new_total)
+    }
+
+    pub(crate) fn check_withdrawal(
+        &self,
+        client_id: ClientId,
+        usage: &mut LimitUsage,
+        amount: Amount,
+        now: u64,
+    ) -> Result<(), Rejection> {
+        let limits = self.for_client(client_id);
+        limits.check_transaction_count(usage, now)?;
+        if limits.max_withdrawal.is_some_and(|max| amount > max) {
+            return Err(Rejection::WithdrawalLimitExceeded);
+        }
+        if let Some(max) = limits.max_daily_withdrawal {
+            let withdrawn = usage
+                .withdrawn_within(DAY, now)
+                .and_then(|withdrawn| withdrawn.checked_add(amount));
+            if withdrawn.is_none_or(|withdrawn| withdrawn > max) {
+                return Err(Rejection::DailyWithdrawalLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    // Outgoing transfers are held to the withdrawal limits of the source client, for them not to be
+    // got around by transferring the funds to another client to withdraw them there.
+    pub(crate) fn check_transfer_out(
+        &self,
+        client_id: ClientId,
+        usage: &mut LimitUsage,
+        amount: Amount,
+        now: u64,
+    ) -> Result<(), Rejection> {
+        self.check_withdrawal(client_id, usage, amount, now)
+    }
+
+    // Only the usage of configured limits is kept track of.
+    pub(crate) fn record(
+        &self,
+        client_id: ClientId,
+        usage: &mut LimitUsage,
+        withdrawn: Option<Amount>,
+        now: u64,
+    ) {
+        let limits = self.for_client(client_id);
+        if limits.max_transactions.is_some() {
+            usage.transactions.push_back(now);
+        }
+        if let (Some(amount), Some(_)) = (withdrawn, limits.max_daily_withdrawal) {
+            usage.push_withdrawal(now, amount);
+        }
+    }
+
+    pub(crate) fn check_transfer_in(
+        &self,
+        client_id: ClientId,
+        new_total: Amount,
+    ) -> Result<(), Rejection> {
+        self.for_client(client_id).check_balance(new_total)
+    }
+}
+
+impl ClientOverride {
+    fn limits(&self) -> ClientLimits {
+        ClientLimits {
+            max_withdrawal: self.max_withdrawal,
+            max_daily_withdrawal: self.max_daily_withdrawal,
+            max_balance: self.max_balance,
+            max_transactions: self.max_transactions,
+            transaction_period: self.transaction_period,
+            overdraft: self.overdraft,
+        }
+    }
+}
+
+impl ClientLimits {
+    fn or(&self, default: &ClientLimits) -> ClientLimits {
+        ClientLimits {
+            max_withdrawal: self.max_withdrawal.or(default.max_withdrawal),
+            max_daily_withdrawal: self.max_daily_withdrawal.or(default.max_daily_withdrawal),
+            max_balance: self.max_balance.or(default.max_balance),
+            max_transactions: self.max_transactions.or(default.max_transactions),
+            transaction_period: self.transaction_period.or(default.transaction_period),
+            overdraft: self.overdraft.or(default.overdraft),
+        }
+    }
+
+    fn check_transaction_count(&self, usage: &mut LimitUsage, now: u64) -> Result<(), Rejection> {
+        if let Some(max) = self.max_transactions {
+            if usage.transactions_within(self.transaction_period.unwrap_or(DAY), now) >= max {
+                return Err(Rejection::TransactionLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_balance(&self, new_total: Amount) -> Result<(), Rejection> {
+        if self.max_balance.is_some_and(|max| new_total > max) {
+            return Err(Rejection::BalanceLimitExceeded);
+        }
+        Ok(())
+    }
+
+    fn validate(&self, name: &str) -> Result<(), Box<dyn Error>> {
+        let negative = [
+            self.max_withdrawal,
+            self.max_daily_withdrawal,
+            self.max_balance,
+            self.overdraft,
+        ]
+        .iter()
+        .flatten()
+        .any(|limit| limit.is_sign_negative());
+        if negative {
+            return Err(format!("{} has a negative limit", name).into());
+        }
+        if self.transaction_period == Some(0) {
+            return Err(format!("{} has an empty transaction period", name).into());
+        }
+        Ok(())
+    }
+}
+
+impl LimitUsage {
+    /// The withdrawals counted against the daily limit, with their timestamps.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn withdrawals(&self) -> impl Iterator<Item = (u64, Amount)> + '_ {
+        self.withdrawals.iter().copied()
+    }
+
+    /// The timestamps of the transactions counted against the transaction limit.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn transactions(&self) -> impl Iterator<Item = u64> + '_ {
+        self.transactions.iter().copied()
+    }
+
+    // Recorded in order, as they were when saved.
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn restore_withdrawal(&mut self, timestamp: u64, amount: Amount) {
+        self.push_withdrawal(timestamp, amount);
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn restore_transaction(&mut self, timestamp: u64) {
+        self.transactions.push_back(timestamp);
+    }
+
+    fn push_withdrawal(&mut self, timestamp: u64, amount: Amount) {
+        self.withdrawals.push_back((timestamp, amount));
+        self.withdrawn = self
+            .withdrawn
+            .and_then(|withdrawn| withdrawn.checked_add(amount));
+    }
+
+    // None when more than can be represented.
+    fn withdrawn_within(&mut self, period: u64, now: u64) -> Option<Amount> {
+        while let Some(&(timestamp, amount)) = self.withdrawals.front() {
+            if now.saturating_sub(timestamp) < period {
+                break;
+            }
+            self.withdrawals.pop_front();
+            self.withdrawn = self
+                .withdrawn
+                .and_then(|withdrawn| withdrawn.checked_sub(amount));
+        }
+        if self.withdrawn.is_none() {
+            self.withdrawn =
+                amount::checked_sum(self.withdrawals.iter().map(|(_, amount)| *amount));
+        }
+        self.withdrawn
+    }
+
+    fn transactions_within(&mut self, period: u64, now: u64) -> usize {
+        while let Some(timestamp) = self.transactions.front() {
+            if now.saturating_sub(*timestamp) < period {
+                break;
+            }
+            self.transactions.pop_front();
+        }
+        self.transactions.len()
+    }
+}
@@ -1,18 +1,603 @@
-use std::env;
+use clap::Args;
+use clap::Parser;
+use clap::Subcommand;
+use payments_engine::Amount;
+use payments_engine::ChangeFeed;
+use payments_engine::ChangeFormat;
+use payments_engine::Checkpoints;
+use payments_engine::ClientId;
+use payments_engine::CompressedWriter;
+use payments_engine::Compression;
+use payments_engine::CsvDialect;
+use payments_engine::EngineConfig;
+use payments_engine::Generator;
+#[cfg(feature = "grpc")]
+use payments_engine::GrpcService;
+#[cfg(feature = "http")]
+use payments_engine::HttpServer;
+use payments_engine::PaymentsEngine;
+use payments_engine::Progress;
+use payments_engine::ProgressReporter;
+use payments_engine::Server;
+use payments_engine::Snapshots;
+#[cfg(feature = "sqlite")]
+use payments_engine::SqliteStore;
+use payments_engine::SummaryFormat;
+use payments_engine::Verify;
+use std::error::Error;
+use std::fs;
 use std::fs::File;
 use std::io;
+use std::io::Write;
+use std::net::TcpListener;
+use std::path::Path;
+use std::path::PathBuf;
 use std::process;
+#[cfg(unix)]
+use std::sync::atomic::AtomicBool;
+#[cfg(unix)]
+use std::sync::Arc;
+use std::time::Duration;
+
+// How often a server looks at the snapshot trigger while no transactions come in.
+const SNAPSHOT_TRIGGER_PERIOD: Duration = Duration::from_millis(100);
+
+#[derive(Parser)]
+#[command(
+    about = "Processes a CSV of transactions and writes the resulting account states to stdout",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
+)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// CSV file with the transactions to process
+    #[arg(required = true)]
+    transactions_csv: Option<PathBuf>,
+
+    #[command(flatten)]
+    engine: EngineArgs,
+
+    /// Also write the trial balance of the general ledger to a CSV file
+    #[arg(long, value_name = "FILE")]
+    trial_balance: Option<PathBuf>,
+
+    /// Compress the account states written to stdout - gzip or zstd
+    #[arg(long, value_name = "FORMAT")]
+    compress: Option<Compression>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Writes the history of the clients' accounts - every transaction applied or rejected, with
+    /// the balances after it - to stdout
+    Statement {
+        /// CSV file with the transactions to process
+        transactions_csv: PathBuf,
+
+        /// Only write the statement of this client
+        #[arg(long, value_name = "ID")]
+        client: Option<ClientId>,
+
+        #[command(flatten)]
+        engine: EngineArgs,
+    },
+
+    /// Applies transactions posted over TCP, one per line, acknowledging each with its outcome and
+    /// answering `balance <client>` queries
+    Serve {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:7878")]
+        listen: String,
+
+        #[command(flatten)]
+        engine: EngineArgs,
+    },
+
+    /// Serves an HTTP/JSON API for submitting transactions and querying accounts
+    #[cfg(feature = "http")]
+    ServeHttp {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:8080")]
+        listen: String,
+
+        #[command(flatten)]
+        engine: EngineArgs,
+    },
+
+    /// Serves the gRPC service of `proto/payments.proto`
+    #[cfg(feature = "grpc")]
+    ServeGrpc {
+        /// Address to listen on
+        #[arg(long, value_name = "ADDR", default_value = "127.0.0.1:50051")]
+        listen: String,
+
+        #[command(flatten)]
+        engine: EngineArgs,
+    },
+
+    /// Writes the effective configuration - the config file with the options given on top of it - to
+    /// stdout as TOML, after validating it
+    Config {
+        #[command(flatten)]
+        engine: EngineArgs,
+    },
+
+    /// Writes synthetic transactions to stdout, e.g. for benchmarking
+    Generate {
+        /// Number of transactions
+        #[arg(long, default_value_t = 100_000)]
+        rows: u64,
+
+        /// Number of clients to spread the transactions over
+        #[arg(long, default_value_t = 100)]
+        clients: ClientId,
+
+        /// Share of the transactions that are disputes
+        #[arg(long, value_name = "RATIO", default_value_t = 0.0)]
+        disputes: f64,
+
+        /// Share of the disputes that are charged back rather than resolved
+        #[arg(long, value_name = "RATIO", default_value_t = 0.0)]
+        chargebacks: f64,
+
+        /// Share of the transactions that are to be rejected
+        #[arg(long, value_name = "RATIO", default_value_t = 0.0)]
+        errors: f64,
+
+        /// Seed of the random number generator - the same seed gives the same transactions
+        #[arg(long, default_value_t = 0)]
+        seed: u64,
+    },
+}
+
+#[derive(Args)]
+struct EngineArgs {
+    /// TOML config of the engine's policies, which the options below override
+    #[arg(long, value_name = "FILE")]
+    config: Option<PathBuf>,
+
+    /// TOML fee schedule to charge deposits and withdrawals by
+    #[arg(long, value_name = "FILE")]
+    fees: Option<PathBuf>,
+
+    /// TOML per-client limits to enforce
+    #[arg(long, value_name = "FILE")]
+    limits: Option<PathBuf>,
+
+    /// Reject deposits, withdrawals and transfers of more than AMOUNT
+    #[arg(long, value_name = "AMOUNT")]
+    max_amount: Option<Amount>,
+
+    /// Check the account invariants after each transaction or at the end of the run -
+    /// each-transaction or end-of-run
+    #[arg(long, value_name = "WHEN")]
+    verify: Option<Verify>,
+
+    /// Skip transactions processed before, recognised by their tx id and content, and reject those
+    /// with the id of one processed before but a different content
+    #[arg(long)]
+    idempotent: bool,
+
+    /// Write a summary of the run to stderr, or to a file with `--summary=FILE`
+    #[arg(
+        long,
+        value_name = "FILE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "-"
+    )]
+    summary: Option<PathBuf>,
+
+    /// Report the progress to stderr every SECS seconds, every second by default
+    #[arg(
+        long,
+        value_name = "SECS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "1"
+    )]
+    progress: Option<f64>,
+
+    /// Format of the run summary - text, the default, or json
+    #[arg(long, value_name = "FORMAT")]
+    summary_format: Option<SummaryFormat>,
+
+    /// Write snapshots of the account states to DIR, on SIGUSR1 or every `--snapshot-every`
+    /// transactions
+    #[arg(long, value_name = "DIR")]
+    snapshot_dir: Option<PathBuf>,
+
+    /// Write a snapshot every N transactions
+    #[arg(long, value_name = "N", requires = "snapshot_dir")]
+    snapshot_every: Option<u64>,
+
+    /// Only write the accounts changed since the previous snapshot
+    #[arg(long, requires = "snapshot_dir")]
+    snapshot_delta: bool,
+
+    /// Write the balance changes of every transaction applied to FILE, or to stdout with `-`
+    #[arg(long, value_name = "FILE")]
+    changes: Option<PathBuf>,
+
+    /// Format of the balance changes - csv, the default, or jsonl
+    #[arg(long, value_name = "FORMAT", requires = "changes")]
+    changes_format: Option<ChangeFormat>,
+
+    /// Keep the accounts and transactions in an SQLite database, starting from the state kept in it
+    #[cfg(feature = "sqlite")]
+    #[arg(long, value_name = "FILE")]
+    db: Option<PathBuf>,
+
+    /// Record a checkpoint of the run to FILE every `--checkpoint-every` rows, for it to be resumed
+    /// with `--resume`
+    #[arg(long, value_name = "FILE")]
+    checkpoint: Option<PathBuf>,
+
+    /// Record a checkpoint every N rows
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = 1_000_000,
+        requires = "checkpoint"
+    )]
+    checkpoint_every: u64,
+
+    /// Resume the run from the checkpoint, if there is one
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    #[command(flatten)]
+    dialect: DialectArgs,
+}
+
+#[derive(Args)]
+struct DialectArgs {
+    /// Character separating the fields of the CSV
+    #[arg(long, value_name = "CHAR", default_value_t = ',')]
+    delimiter: char,
+
+    /// Character quoting the fields of the CSV
+    #[arg(long, value_name = "CHAR", default_value_t = '"')]
+    quote: char,
+
+    /// The CSV has no header row - its columns are `type, client, tx, amount, to, timestamp`
+    /// unless given by `--columns`
+    #[arg(long)]
+    no_headers: bool,
+
+    /// Columns of a CSV without a header row, in order
+    #[arg(
+        long,
+        value_name = "NAMES",
+        value_delimiter = ',',
+        requires = "no_headers"
+    )]
+    columns: Vec<String>,
+
+    /// Read the column ALIAS as COLUMN, e.g. `--alias client_id=client`
+    #[arg(long, value_name = "ALIAS=COLUMN", value_parser = parse_alias)]
+    alias: Vec<(String, String)>,
+
+    /// Read transaction types regardless of case, e.g. `Deposit` as `deposit`
+    #[arg(long)]
+    ignore_type_case: bool,
+}
 
 fn main() {
-    // TODO: unsure about best practices for Rust error handling. Do some reading.
-    let transactions_csv = env::args_os()
-        .nth(1)
-        .map(File::open)
-        .expect("Could not get CSV path")
-        .expect("Could not open CSV file");
-
-    if let Err(e) = payments_engine::run(transactions_csv, &mut io::stdout()) {
+    let cli = Cli::parse();
+
+    if let Err(e) = run(cli) {
         eprintln!("error: {}", e);
         process::exit(1);
     }
 }
+
+fn run(cli: Cli) -> Result<(), Box<dyn Error>> {
+    match cli.command {
+        Some(Command::Statement {
+            transactions_csv,
+            client,
+            engine,
+        }) => {
+            let config = engine.config()?;
+            let payments_engine = engine.build(&config)?.with_statement(client);
+            let payments_engine = process(payments_engine, transactions_csv, &engine, &config)?;
+            payments_engine::write_statement_to_csv(&payments_engine, &mut io::stdout())
+        }
+        Some(Command::Serve { listen, engine }) => {
+            let listener = TcpListener::bind(&listen)?;
+            eprintln!("listening on {}", listener.local_addr()?);
+            let server = Server::new(engine.build_serving(&engine.config()?)?);
+            payments_engine::watch_snapshot_trigger(
+                &server.payments_engine(),
+                SNAPSHOT_TRIGGER_PERIOD,
+            );
+            server.serve(listener)?;
+            Ok(())
+        }
+        #[cfg(feature = "http")]
+        Some(Command::ServeHttp { listen, engine }) => {
+            let server = HttpServer::bind(&listen, engine.build_serving(&engine.config()?)?)?;
+            payments_engine::watch_snapshot_trigger(
+                &server.payments_engine(),
+                SNAPSHOT_TRIGGER_PERIOD,
+            );
+            if let Some(address) = server.local_addr() {
+                eprintln!("listening on http://{}", address);
+            }
+            server.serve()?;
+            Ok(())
+        }
+        #[cfg(feature = "grpc")]
+        Some(Command::ServeGrpc { listen, engine }) => {
+            let service = GrpcService::new(engine.build_serving(&engine.config()?)?);
+            payments_engine::watch_snapshot_trigger(
+                &service.payments_engine(),
+                SNAPSHOT_TRIGGER_PERIOD,
+            );
+            let served = tokio::runtime::Runtime::new()?.block_on(async {
+                let listener = tokio::net::TcpListener::bind(&listen).await?;
+                eprintln!("listening on {}", listener.local_addr()?);
+                service.serve(listener).await
+            });
+            served.map_err(|e| e as Box<dyn Error>)
+        }
+        Some(Command::Config { engine }) => {
+            print!("{}", toml::to_string(&engine.config()?)?);
+            Ok(())
+        }
+        Some(Command::Generate {
+            rows,
+            clients,
+            disputes,
+            chargebacks,
+            errors,
+            seed,
+        }) => Generator::new(rows, seed)
+            .with_clients(clients)
+            .with_disputes(disputes)
+            .with_chargebacks(chargebacks)
+            .with_errors(errors)
+            .write_csv(&mut io::stdout().lock()),
+        None => {
+            let transactions_csv = cli.transactions_csv.expect("required without a command");
+            let config = cli.engine.config()?;
+            let payments_engine = process(
+                cli.engine.build(&config)?,
+                transactions_csv,
+                &cli.engine,
+                &config,
+            )?;
+            let mut output = CompressedWriter::new(
+                io::stdout().lock(),
+                cli.compress.unwrap_or(Compression::None),
+            )?;
+            payments_engine::write_account_states_to_csv(&payments_engine, &mut output)?;
+            let _stdout = output.finish()?;
+
+            if let Some(trial_balance) = cli.trial_balance {
+                payments_engine::write_trial_balance_to_csv(
+                    &payments_engine,
+                    &mut File::create(trial_balance)?,
+                )?;
+            }
+            Ok(())
+        }
+    }
+}
+
+impl EngineArgs {
+    // The config file, if any, with the options given on top of it.
+    fn config(&self) -> Result<EngineConfig, Box<dyn Error>> {
+        let mut config = match &self.config {
+            Some(path) => EngineConfig::load(path)
+                .map_err(|e| format!("invalid config {}: {}", path.display(), e))?,
+            None => EngineConfig::default(),
+        };
+        if let Some(fees) = &self.fees {
+            config.fees = Some(fees.clone());
+        }
+        if let Some(limits) = &self.limits {
+            config.limits = Some(limits.clone());
+        }
+        if let Some(max_amount) = self.max_amount {
+            config.strictness.max_amount = Some(max_amount);
+        }
+        if let Some(verify) = self.verify {
+            config.strictness.verify = Some(verify);
+        }
+        if self.idempotent {
+            config.duplicates.idempotent = true;
+        }
+        if let Some(summary_format) = self.summary_format {
+            config.output.summary = summary_format;
+        }
+        if let Some(changes_format) = self.changes_format {
+            config.output.changes = changes_format;
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn build(&self, config: &EngineConfig) -> Result<PaymentsEngine, Box<dyn Error>> {
+        self.build_engine(config, false)
+    }
+
+    // Serving, the balance changes are flushed as they come rather than at the end of the run.
+    fn build_serving(&self, config: &EngineConfig) -> Result<PaymentsEngine, Box<dyn Error>> {
+        self.build_engine(config, true)
+    }
+
+    fn build_engine(
+        &self,
+        config: &EngineConfig,
+        serving: bool,
+    ) -> Result<PaymentsEngine, Box<dyn Error>> {
+        let mut payments_engine = config.build()?;
+        if let Some(snapshot_dir) = &self.snapshot_dir {
+            payments_engine = payments_engine.with_snapshots(self.snapshots(snapshot_dir)?);
+        }
+        #[cfg(feature = "sqlite")]
+        if let Some(db) = &self.db {
+            payments_engine = payments_engine.with_sqlite_store(SqliteStore::open(db)?);
+        }
+        if let Some(changes) = &self.changes {
+            let output: Box<dyn Write + Send> = if changes == Path::new("-") {
+                Box::new(io::BufWriter::new(io::stdout()))
+            } else {
+                Box::new(io::BufWriter::new(File::create(changes)?))
+            };
+            let mut change_feed = ChangeFeed::to_writer(output, config.output.changes);
+            if serving {
+                change_feed = change_feed.with_flush_each();
+            }
+            payments_engine = payments_engine.with_change_feed(change_feed);
+        }
+        Ok(payments_engine)
+    }
+
+    fn snapshots(&self, snapshot_dir: &Path) -> Result<Snapshots, Box<dyn Error>> {
+        fs::create_dir_all(snapshot_dir)?;
+        let mut snapshots = Snapshots::to_dir(snapshot_dir);
+        if let Some(snapshot_every) = self.snapshot_every {
+            snapshots = snapshots.with_interval(snapshot_every);
+        }
+        if self.snapshot_delta {
+            snapshots = snapshots.with_delta();
+        }
+        #[cfg(unix)]
+        {
+            let trigger = Arc::new(AtomicBool::new(false));
+            signal_hook::flag::register(signal_hook::consts::SIGUSR1, Arc::clone(&trigger))?;
+            snapshots = snapshots.with_trigger(trigger);
+        }
+        Ok(snapshots)
+    }
+}
+
+impl DialectArgs {
+    fn build(&self) -> Result<CsvDialect, Box<dyn Error>> {
+        let mut dialect = CsvDialect::new()
+            .with_delimiter(ascii(self.delimiter)?)
+            .with_quote(ascii(self.quote)?);
+        if self.no_headers {
+            dialect = dialect.without_headers(self.columns.iter().cloned());
+        }
+        for (alias, column) in &self.alias {
+            dialect = dialect.with_alias(alias, column);
+        }
+        if self.ignore_type_case {
+            dialect = dialect.with_case_insensitive_types();
+        }
+        Ok(dialect)
+    }
+}
+
+fn ascii(c: char) -> Result<u8, Box<dyn Error>> {
+    u8::try_from(c)
+        .ok()
+        .filter(u8::is_ascii)
+        .ok_or_else(|| format!("'{}' is not an ASCII character", c).into())
+}
+
+fn parse_alias(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((alias, column)) => Ok((alias.trim().to_string(), column.trim().to_string())),
+        None => Err(format!("expected ALIAS=COLUMN, got '{}'", s)),
+    }
+}
+
+// Processes the transactions and checks the resulting state before anything gets written.
+fn process(
+    mut payments_engine: PaymentsEngine,
+    transactions_csv: PathBuf,
+    engine: &EngineArgs,
+    config: &EngineConfig,
+) -> Result<PaymentsEngine, Box<dyn Error>> {
+    let path = transactions_csv;
+    let total_bytes = fs::metadata(&path)?.len();
+    let (transactions_csv, compression) = payments_engine::open_csv(&path)?;
+    let dialect = engine.dialect.build()?;
+    let mut progress = match engine.progress {
+        Some(interval) => {
+            let mut progress = ProgressReporter::new(
+                Duration::try_from_secs_f64(interval)?,
+                |progress: &Progress| eprintln!("progress: {}", progress),
+            );
+            // The progress is in bytes decompressed, which do not compare to the size of the file.
+            if compression == Compression::None {
+                progress = progress.with_total_bytes(total_bytes);
+            }
+            Some(progress)
+        }
+        None => None,
+    };
+    match &engine.checkpoint {
+        Some(checkpoint) => {
+            // Resuming seeks the input to where the checkpoint was made.
+            if compression != Compression::None {
+                return Err("checkpoints need an uncompressed transactions file".into());
+            }
+            let mut checkpoints = Checkpoints::new(checkpoint, engine.checkpoint_every);
+            if engine.resume {
+                checkpoints = checkpoints.with_resume();
+            }
+            payments_engine::process_csv_with_checkpoints(
+                File::open(path)?,
+                &mut payments_engine,
+                &dialect,
+                &checkpoints,
+                progress.as_mut(),
+            )?;
+        }
+        None => payments_engine::process_csv_with_dialect(
+            transactions_csv,
+            &mut payments_engine,
+            &dialect,
+            progress.as_mut(),
+        )?,
+    }
+    payments_engine.check_ledger()?;
+    if let Some(summary) = &engine.summary {
+        write_summary(&payments_engine, summary, config.output.summary)?;
+    }
+    // Conflicting duplicates are rejected like any other transaction, only flagged on the way.
+    for conflict in payments_engine.duplicate_conflicts() {
+        eprintln!("conflicting duplicate: {}", conflict);
+    }
+    let violations = match config.strictness.verify {
+        Some(Verify::EachTransaction) => payments_engine.violations().to_vec(),
+        Some(Verify::EndOfRun) => payments_engine.check_invariants(),
+        None => Vec::new(),
+    };
+    if !violations.is_empty() {
+        for violation in &violations {
+            eprintln!("invariant violated: {}", violation);
+        }
+        return Err(format!("{} invariant violation(s)", violations.len()).into());
+    }
+    Ok(payments_engine)
+}
+
+fn write_summary(
+    payments_engine: &PaymentsEngine,
+    path: &Path,
+    format: SummaryFormat,
+) -> Result<(), Box<dyn Error>> {
+    let mut output: Box<dyn Write> = if path == Path::new("-") {
+        Box::new(io::stderr())
+    } else {
+        Box::new(File::create(path)?)
+    };
+    let summary = payments_engine.summary();
+    match format {
+        SummaryFormat::Text => write!(output, "{}", summary)?,
+        SummaryFormat::Json => {
+            serde_json::to_writer_pretty(&mut output, &summary)?;
+            writeln!(output)?;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+// The clock is only looked at every so many rows, to keep the per-row overhead down.
+const ROWS_BETWEEN_CLOCK_CHECKS: u64 = 1024;
+
+/// How far `process_csv_with_progress` got through the transactions.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Progress {
+    pub rows: u64,
+    pub bytes: u64,
+    // The size of the input, when known.
+    pub total_bytes: Option<u64>,
+    pub elapsed: Duration,
+}
+
+impl Progress {
+    pub fn rows_per_second(&self) -> f64 {
+        self.rows as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+
+    /// Estimated time left, based on the bytes read so far.
+    pub fn eta(&self) -> Option<Duration> {
+        let total_bytes = self.total_bytes?;
+        if self.bytes == 0 {
+            return None;
+        }
+        let remaining = total_bytes.saturating_sub(self.bytes) as f64 / self.bytes as f64;
+        Some(self.elapsed.mul_f64(remaining))
+    }
+}
+
+impl fmt::Display for Progress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} rows, {}", self.rows, megabytes(self.bytes))?;
+        if let Some(total_bytes) = self.total_bytes {
+            write!(
+                f,
+                " of {} ({:.1}%)",
+                megabytes(total_bytes),
+                self.bytes as f64 * 100.0 / total_bytes.max(1) as f64
+            )?;
+        }
+        write!(f, ", {:.0} rows/s", self.rows_per_second())?;
+        if let Some(eta) = self.eta() {
+            write!(f, ", ETA {}s", eta.as_secs())?;
+        }
+        Ok(())
+    }
+}
+
+fn megabytes(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+/// Calls back with the progress at most once per interval while processing, and once at the end.
+pub struct ProgressReporter {
+    interval: Duration,
+    total_bytes: Option<u64>,
+    callback: Box<dyn FnMut(&Progress)>,
+    started: Instant,
+    last_report: Instant,
+}
+
+impl ProgressReporter {
+    pub fn new(interval: Duration, callback: impl FnMut(&Progress) + 'static) -> Self {
+        let now = Instant::now();
+        ProgressReporter {
+            interval,
+            total_bytes: None,
+            callback: Box::new(callback),
+            started: now,
+            last_report: now,
+        }
+    }
+
+    /// The size of the input, to report the progress and ETA against.
+    pub fn with_total_bytes(mut self, total_bytes: u64) -> Self {
+        self.total_bytes = Some(total_bytes);
+        self
+    }
+
+    pub(crate) fn start(&mut self) {
+        self.started = Instant::now();
+        self.last_report = self.started;
+    }
+
+    pub(crate) fn row_processed(&mut self, rows: u64, bytes: u64) {
+        if !rows.is_multiple_of(ROWS_BETWEEN_CLOCK_CHECKS) {
+            return;
+        }
+        let now = Instant::now();
+        if now - self.last_report >= self.interval {
+            self.last_report = now;
+            self.report(rows, bytes, now);
+        }
+    }
+
+    pub(crate) fn finish(&mut self, rows: u64, bytes: u64) {
+        self.report(rows, bytes, Instant::now());
+    }
+
+    fn report(&mut self, rows: u64, bytes: u64, now: Instant) {
+        (self.callback)(&Progress {
+            rows,
+            bytes,
+            total_bytes: self.total_bytes,
+            elapsed: now - self.started,
+        });
+    }
+}
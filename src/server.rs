@@ -0,0 +1,110 @@
+use crate::dialect;
+use crate::PaymentsEngine;
+use crate::Transaction;
+use csv::ByteRecord;
+use std::error::Error;
+use std::io;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+
+/// Applies transactions posted over TCP to a shared engine, for services to post them live.
+///
+/// Each line a connection sends is either a transaction - a CSV row of the columns `type, client,
+/// tx, amount, to, timestamp`, trailing ones left out - or a `balance <client>` query. Every line is
+/// answered with one line, in order:
+/// - `applied`, or `rejected <reason>` for a transaction
+/// - `balance <client>,<available>,<held>,<total>,<locked>` for a query
+/// - `error <message>` for a line that makes no sense, a query of an unknown client, or a
+///   transaction that failed to be saved to the database - after which no more are applied
+pub struct Server {
+    payments_engine: Arc<Mutex<PaymentsEngine>>,
+}
+
+impl Server {
+    pub fn new(payments_engine: PaymentsEngine) -> Self {
+        Server {
+            payments_engine: Arc::new(Mutex::new(payments_engine)),
+        }
+    }
+
+    /// The engine the transactions are applied to, e.g. to write its account states.
+    pub fn payments_engine(&self) -> Arc<Mutex<PaymentsEngine>> {
+        Arc::clone(&self.payments_engine)
+    }
+
+    /// Accepts connections until the listener fails, serving each on a thread of its own.
+    pub fn serve(&self, listener: TcpListener) -> io::Result<()> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let payments_engine = self.payments_engine();
+            thread::spawn(move || {
+                // A connection failing only ends that connection.
+                let _ = serve_connection(stream, &payments_engine);
+            });
+        }
+        Ok(())
+    }
+}
+
+fn serve_connection(stream: TcpStream, payments_engine: &Mutex<PaymentsEngine>) -> io::Result<()> {
+    // The responses are small and each awaited before the next line, so they go out right away.
+    stream.set_nodelay(true)?;
+    let mut output = io::BufWriter::new(stream.try_clone()?);
+    for line in BufReader::new(stream).lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let response = respond(&line, payments_engine);
+        writeln!(output, "{}", response)?;
+        output.flush()?;
+    }
+    Ok(())
+}
+
+fn respond(line: &str, payments_engine: &Mutex<PaymentsEngine>) -> String {
+    if let Some(client_id) = line.trim().strip_prefix("balance ") {
+        let client_id = match client_id.trim().parse() {
+            Ok(client_id) => client_id,
+            Err(e) => return format!("error invalid client: {}", e),
+        };
+        let payments_engine = payments_engine.lock().expect("engine lock poisoned");
+        return match payments_engine.account(client_id) {
+            Some(account) => match crate::account_to_csv_row(&payments_engine, account) {
+                Ok(row) => format!("balance {}", row),
+                Err(e) => format!("error {}", e),
+            },
+            None => format!("error unknown client {}", client_id),
+        };
+    }
+    let transaction = match parse_transaction(line) {
+        Ok(transaction) => transaction,
+        Err(e) => return format!("error {}", e),
+    };
+    let mut payments_engine = payments_engine.lock().expect("engine lock poisoned");
+    match payments_engine.serve_transaction(transaction) {
+        Ok(Ok(())) => "applied".to_string(),
+        Ok(Err(rejection)) => format!("rejected {}", rejection),
+        Err(e) => format!("error {}", e),
+    }
+}
+
+fn parse_transaction(line: &str) -> Result<Transaction, Box<dyn Error>> {
+    let mut rdr = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(line.as_bytes());
+    let mut record = ByteRecord::new();
+    if !rdr.read_byte_record(&mut record)? {
+        return Err("empty transaction".into());
+    }
+    let headers = ByteRecord::from(&dialect::COLUMNS[..record.len().min(dialect::COLUMNS.len())]);
+    Ok(record.deserialize(Some(&headers))?)
+}
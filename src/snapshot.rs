@@ -0,0 +1,150 @@
+use crate::ClientId;
+use crate::PaymentsEngine;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+type Output = Box<dyn FnMut(u64) -> io::Result<Box<dyn Write + Send>> + Send>;
+
+/// Writes the account states while transactions are processed - every so many transactions, on
+/// demand, or both - see `PaymentsEngine::with_snapshots`. Each snapshot is written like
+/// `write_account_states_to_csv` writes the final account states.
+pub struct Snapshots {
+    output: Output,
+    interval: Option<u64>,
+    trigger: Option<Arc<AtomicBool>>,
+    delta: bool,
+    // Transactions processed since the last snapshot.
+    processed: u64,
+    // The number of the latest snapshot, counting from 1.
+    sequence: u64,
+    // The clients whose accounts changed since the last snapshot, when writing deltas.
+    pub(crate) changed: HashSet<ClientId>,
+    // The first snapshot that failed to be written, after which no more are.
+    pub(crate) error: Option<io::Error>,
+}
+
+impl Snapshots {
+    /// Writes each snapshot to the output opened for its number, counting from 1.
+    pub fn new(
+        output: impl FnMut(u64) -> io::Result<Box<dyn Write + Send>> + Send + 'static,
+    ) -> Self {
+        Snapshots {
+            output: Box::new(output),
+            interval: None,
+            trigger: None,
+            delta: false,
+            processed: 0,
+            sequence: 0,
+            changed: HashSet::new(),
+            error: None,
+        }
+    }
+
+    /// Writes each snapshot to a file of its own in the directory, `accounts-000001.csv` and so on.
+    pub fn to_dir(dir: impl Into<PathBuf>) -> Self {
+        let dir = dir.into();
+        Self::new(move |sequence| {
+            let file = File::create(dir.join(format!("accounts-{:06}.csv", sequence)))?;
+            Ok(Box::new(io::BufWriter::new(file)))
+        })
+    }
+
+    /// Writes a snapshot every `transactions` transactions processed.
+    pub fn with_interval(mut self, transactions: u64) -> Self {
+        self.interval = Some(transactions).filter(|transactions| *transactions > 0);
+        self
+    }
+
+    /// Writes a snapshot after the next transaction once the trigger is set, e.g. by a signal
+    /// handler, and clears it - see `watch_snapshot_trigger` for an engine that may sit idle.
+    pub fn with_trigger(mut self, trigger: Arc<AtomicBool>) -> Self {
+        self.trigger = Some(trigger);
+        self
+    }
+
+    /// Only writes the accounts changed since the previous snapshot.
+    pub fn with_delta(mut self) -> Self {
+        self.delta = true;
+        self
+    }
+
+    pub(crate) fn is_delta(&self) -> bool {
+        self.delta
+    }
+
+    // Counts the transaction, telling whether a snapshot is due after it.
+    pub(crate) fn transaction_processed(&mut self) -> bool {
+        if self.error.is_some() {
+            return false;
+        }
+        self.processed += 1;
+        let interval_passed = self
+            .interval
+            .is_some_and(|interval| self.processed >= interval);
+        // The trigger is cleared whether or not the interval passed, to not write twice in a row.
+        let triggered = self.take_trigger();
+        interval_passed || triggered
+    }
+
+    // Clears the trigger, telling whether it was set. Only swapped once set, as it is looked at for
+    // every transaction.
+    fn take_trigger(&self) -> bool {
+        self.trigger.as_ref().is_some_and(|trigger| {
+            trigger.load(Ordering::Relaxed) && trigger.swap(false, Ordering::Relaxed)
+        })
+    }
+
+    pub(crate) fn open(&mut self) -> io::Result<Box<dyn Write + Send>> {
+        self.processed = 0;
+        self.sequence += 1;
+        (self.output)(self.sequence)
+    }
+}
+
+/// Looks at the trigger of the engine's snapshots every `period` on a thread of its own, writing a
+/// snapshot right away once it is set - for a server to write one on demand while no transactions
+/// come in. The thread ends once the engine is dropped, and is not started for an engine without
+/// snapshots.
+pub fn watch_snapshot_trigger(
+    payments_engine: &Arc<Mutex<PaymentsEngine>>,
+    period: Duration,
+) -> Option<JoinHandle<()>> {
+    payments_engine
+        .lock()
+        .expect("engine lock poisoned")
+        .snapshots
+        .as_ref()?
+        .trigger
+        .as_ref()?;
+    let payments_engine = Arc::downgrade(payments_engine);
+    Some(thread::spawn(move || loop {
+        thread::sleep(period);
+        let Some(payments_engine) = payments_engine.upgrade() else {
+            return;
+        };
+        let mut payments_engine = payments_engine.lock().expect("engine lock poisoned");
+        let snapshots = payments_engine
+            .snapshots
+            .as_mut()
+            .expect("snapshots configured");
+        if snapshots.error.is_none() && snapshots.take_trigger() {
+            if let Err(e) = payments_engine.snapshot() {
+                payments_engine
+                    .snapshots
+                    .as_mut()
+                    .expect("snapshots configured")
+                    .error = Some(e);
+            }
+        }
+    }))
+}
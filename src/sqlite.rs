@@ -0,0 +1,414 @@
+use crate::amount;
+use crate::limits::LimitUsage;
+use crate::Account;
+use crate::Amount;
+use crate::AppliedTransaction;
+use crate::ClientId;
+use crate::Fingerprint;
+use crate::Kind;
+use crate::Ledger;
+use crate::LedgerAccount;
+use crate::PaymentsEngine;
+use crate::Recorded;
+use crate::Rejection;
+use crate::TransactionId;
+use crate::TransactionType;
+use rusqlite::params;
+use rusqlite::Connection;
+use rusqlite::OptionalExtension;
+use serde::de::IntoDeserializer;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::error::Error;
+use std::path::Path;
+
+const SCHEMA: &str = "
+    CREATE TABLE IF NOT EXISTS accounts (
+        client INTEGER PRIMARY KEY,
+        available TEXT NOT NULL,
+        held TEXT NOT NULL,
+        total TEXT NOT NULL,
+        locked INTEGER NOT NULL,
+        disputed_withdrawals TEXT NOT NULL,
+        credit_limit TEXT NOT NULL,
+        last_transaction INTEGER,
+        changed_while_locked INTEGER NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS transactions (
+        client INTEGER NOT NULL,
+        tx INTEGER NOT NULL,
+        type TEXT NOT NULL,
+        amount TEXT NOT NULL,
+        fee TEXT NOT NULL,
+        disputable INTEGER NOT NULL,
+        state TEXT NOT NULL,
+        PRIMARY KEY (client, tx)
+    );
+    CREATE TABLE IF NOT EXISTS ledger (
+        account TEXT PRIMARY KEY,
+        balance TEXT NOT NULL
+    );
+    CREATE TABLE IF NOT EXISTS applied (
+        tx INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        hash INTEGER NOT NULL,
+        position INTEGER NOT NULL,
+        outcome TEXT NOT NULL,
+        PRIMARY KEY (tx, kind, hash, position)
+    );
+    CREATE TABLE IF NOT EXISTS limit_usage (
+        client INTEGER NOT NULL,
+        kind TEXT NOT NULL,
+        timestamp INTEGER NOT NULL,
+        amount TEXT
+    );
+    CREATE INDEX IF NOT EXISTS limit_usage_client ON limit_usage (client);
+    CREATE TABLE IF NOT EXISTS clock (
+        id INTEGER PRIMARY KEY CHECK (id = 0),
+        timestamp INTEGER NOT NULL
+    );
+";
+
+// The engine's own ledger accounts, by their name in the `ledger` table.
+const LEDGER_ACCOUNTS: [(&str, LedgerAccount); 2] = [
+    ("external_settlement", LedgerAccount::ExternalSettlement),
+    ("chargeback_loss", LedgerAccount::ChargebackLoss),
+];
+
+/// Keeps the accounts, the transactions applied to them and their dispute states in an SQLite
+/// database, for the state to survive restarts and to be queried with SQL - see
+/// `PaymentsEngine::with_sqlite_store`.
+///
+/// The database has the tables:
+/// - `accounts` - the balances of each client, as `write_account_states_to_csv` writes them, with
+///   the last transaction that changed them and whether one did once the account was locked - for
+///   the invariants to be checked as before a restart
+/// - `transactions` - the transactions applied to each account by client and tx id, with the amount
+///   they moved, negative for withdrawals, and their `state`: `applied`, `disputed`, `resolved` or
+///   `charged_back`
+/// - `ledger` - the balances of the engine's own ledger accounts
+/// - `applied` - the id and content hash of each transaction processed, with idempotency on, its
+///   `position` in the dispute lifecycle of the transaction and its `outcome`: `applied` or the
+///   reason it was rejected
+/// - `limit_usage` - the recent `withdrawal`s and `transaction`s of each client counted against
+///   their limits, by timestamp, with the amount of each withdrawal
+/// - `clock` - the latest timestamp of the transactions, in its only row
+///
+/// Amounts are kept as text, for them to be exact, and timestamps as the signed integers SQLite
+/// has.
+pub struct SqliteStore {
+    connection: Connection,
+    // The state loaded on opening, until moved into the engine.
+    pub(crate) accounts: HashMap<ClientId, Account>,
+    pub(crate) ledger: Ledger,
+    pub(crate) applied: Vec<Recorded>,
+    pub(crate) limit_usage: HashMap<ClientId, LimitUsage>,
+    pub(crate) clock: u64,
+    // The first transaction that failed to be saved, after which no more are.
+    pub(crate) error: Option<rusqlite::Error>,
+}
+
+impl SqliteStore {
+    /// Opens the database, creating it if need be, and loads the state kept in it.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, Box<dyn Error>> {
+        let connection = Connection::open(path)?;
+        // Each transaction is committed on its own, which the write-ahead log keeps cheap.
+        connection.pragma_update(None, "journal_mode", "WAL")?;
+        connection.pragma_update(None, "synchronous", "NORMAL")?;
+        connection.execute_batch(SCHEMA)?;
+        let mut store = SqliteStore {
+            connection,
+            accounts: HashMap::new(),
+            ledger: Ledger::new(),
+            applied: Vec::new(),
+            limit_usage: HashMap::new(),
+            clock: 0,
+            error: None,
+        };
+        store.load()?;
+        Ok(store)
+    }
+
+    fn load(&mut self) -> Result<(), Box<dyn Error>> {
+        let mut statement = self.connection.prepare(
+            "SELECT client, available, held, total, locked, disputed_withdrawals, credit_limit,
+                last_transaction, changed_while_locked
+            FROM accounts",
+        )?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let client_id: ClientId = row.get(0)?;
+            let account = Account {
+                available: parse_amount(row.get(1)?)?,
+                held: parse_amount(row.get(2)?)?,
+                total: parse_amount(row.get(3)?)?,
+                locked: row.get(4)?,
+                disputed_withdrawals: parse_amount(row.get(5)?)?,
+                credit_limit: parse_amount(row.get(6)?)?,
+                last_transaction: row.get(7)?,
+                changed_while_locked: row.get(8)?,
+                ..Account::new(client_id, None)
+            };
+            self.accounts.insert(client_id, account);
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT client, tx, amount, fee, disputable, state FROM transactions")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let client_id: ClientId = row.get(0)?;
+            let id: TransactionId = row.get(1)?;
+            let account = self
+                .accounts
+                .get_mut(&client_id)
+                .ok_or_else(|| format!("transaction {} of unknown client {}", id, client_id))?;
+            let amount = parse_amount(row.get(2)?)?;
+            let transaction = AppliedTransaction {
+                amount,
+                fee: parse_amount(row.get(3)?)?,
+                disputable: row.get(4)?,
+            };
+            account.transactions.insert(id, transaction);
+            if row.get::<_, String>(5)? == "disputed" {
+                account.disputes.insert(id, amount);
+            }
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT account, balance FROM ledger")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(0)?;
+            let (_, ledger_account) = LEDGER_ACCOUNTS
+                .into_iter()
+                .find(|(ledger_account_name, _)| *ledger_account_name == name)
+                .ok_or_else(|| format!("unknown ledger account '{}'", name))?;
+            self.ledger
+                .set_balance(ledger_account, parse_amount(row.get(1)?)?);
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT tx, kind, hash, position, outcome FROM applied")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let name: String = row.get(1)?;
+            let kind = Kind::ALL
+                .into_iter()
+                .find(|kind| kind.name() == name)
+                .ok_or_else(|| format!("unknown kind of transaction '{}'", name))?;
+            let outcome = match row.get::<_, String>(4)?.as_str() {
+                "applied" => Ok(()),
+                reason => Err(Rejection::deserialize(reason.into_deserializer())
+                    .map_err(|e: serde::de::value::Error| format!("invalid outcome: {}", e))?),
+            };
+            self.applied.push(Recorded {
+                fingerprint: Fingerprint {
+                    id: row.get(0)?,
+                    kind,
+                    // Kept as the signed integers SQLite has.
+                    hash: row.get::<_, i64>(2)? as u64,
+                },
+                position: row.get(3)?,
+                outcome,
+            });
+        }
+
+        let mut statement = self
+            .connection
+            .prepare("SELECT client, kind, timestamp, amount FROM limit_usage ORDER BY rowid")?;
+        let mut rows = statement.query([])?;
+        while let Some(row) = rows.next()? {
+            let usage = self.limit_usage.entry(row.get(0)?).or_default();
+            let timestamp = row.get::<_, i64>(2)? as u64;
+            match (row.get::<_, String>(1)?.as_str(), row.get(3)?) {
+                ("withdrawal", Some(amount)) => {
+                    usage.restore_withdrawal(timestamp, parse_amount(amount)?);
+                }
+                ("transaction", None) => usage.restore_transaction(timestamp),
+                (kind, _) => return Err(format!("invalid limit usage '{}'", kind).into()),
+            }
+        }
+
+        let clock: Option<i64> = self
+            .connection
+            .query_row("SELECT timestamp FROM clock", [], |row| row.get(0))
+            .optional()?;
+        self.clock = clock.map_or(0, |clock| clock as u64);
+        Ok(())
+    }
+
+    // Saves what a transaction changed - the accounts it touched and their limit usage, itself or
+    // the state of the transaction it disputed, the ledger and the clock - in one database
+    // transaction, along with its outcome with idempotency on. A rejected one only moves the clock.
+    pub(crate) fn save(
+        &mut self,
+        payments_engine: &PaymentsEngine,
+        transaction: (TransactionType, TransactionId),
+        result: Result<(), Rejection>,
+        touched: [Option<ClientId>; 3],
+        recorded: Option<Recorded>,
+    ) {
+        if self.error.is_some() {
+            return;
+        }
+        if let Err(e) = self.try_save(payments_engine, transaction, result, touched, recorded) {
+            self.error = Some(e);
+        }
+    }
+
+    fn try_save(
+        &mut self,
+        payments_engine: &PaymentsEngine,
+        transaction: (TransactionType, TransactionId),
+        result: Result<(), Rejection>,
+        touched: [Option<ClientId>; 3],
+        recorded: Option<Recorded>,
+    ) -> rusqlite::Result<()> {
+        let sql = self.connection.transaction()?;
+        if result.is_ok() {
+            save_changes(&sql, payments_engine, transaction, touched)?;
+        }
+        sql.prepare_cached("INSERT OR REPLACE INTO clock (id, timestamp) VALUES (0, ?1)")?
+            .execute(params![payments_engine.clock as i64])?;
+        if let Some(recorded) = recorded {
+            let fingerprint = recorded.fingerprint;
+            let outcome = match recorded.outcome {
+                Ok(()) => "applied".to_string(),
+                Err(rejection) => rejection.to_string(),
+            };
+            sql.prepare_cached(
+                "INSERT OR REPLACE INTO applied (tx, kind, hash, position, outcome)
+                VALUES (?1, ?2, ?3, ?4, ?5)",
+            )?
+            .execute(params![
+                fingerprint.id,
+                fingerprint.kind.name(),
+                fingerprint.hash as i64,
+                recorded.position,
+                outcome
+            ])?;
+        }
+        sql.commit()
+    }
+}
+
+fn save_changes(
+    sql: &rusqlite::Transaction,
+    payments_engine: &PaymentsEngine,
+    (tx_type, id): (TransactionType, TransactionId),
+    touched: [Option<ClientId>; 3],
+) -> rusqlite::Result<()> {
+    let accounts = &payments_engine.accounts;
+    let [client_id, destination_client_id, house_account] = touched;
+    for client_id in touched.into_iter().flatten() {
+        let Some(account) = accounts.get(&client_id) else {
+            continue;
+        };
+        sql.prepare_cached(
+            "INSERT OR REPLACE INTO accounts
+                (client, available, held, total, locked, disputed_withdrawals, credit_limit,
+                    last_transaction, changed_while_locked)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+        )?
+        .execute(params![
+            client_id,
+            to_text(account.available),
+            to_text(account.held),
+            to_text(account.total),
+            account.locked,
+            to_text(account.disputed_withdrawals),
+            to_text(account.credit_limit),
+            account.last_transaction,
+            account.changed_while_locked,
+        ])?;
+    }
+
+    let state = match tx_type {
+        TransactionType::Dispute => Some("disputed"),
+        TransactionType::Resolve => Some("resolved"),
+        TransactionType::Chargeback => Some("charged_back"),
+        TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => None,
+    };
+    if let Some(state) = state {
+        sql.prepare_cached("UPDATE transactions SET state = ?1 WHERE client = ?2 AND tx = ?3")?
+            .execute(params![state, client_id, id])?;
+    } else {
+        // The fee of a transaction is recorded on the house account under the same id.
+        let recorded = [
+            (client_id, tx_type.to_string()),
+            (destination_client_id, tx_type.to_string()),
+            (house_account, "fee".to_string()),
+        ];
+        for (client_id, tx_type) in recorded {
+            let Some((client_id, transaction)) = client_id.and_then(|client_id| {
+                let transaction = accounts.get(&client_id)?.transactions.get(&id)?;
+                Some((client_id, transaction))
+            }) else {
+                continue;
+            };
+            sql.prepare_cached(
+                "INSERT OR REPLACE INTO transactions
+                    (client, tx, type, amount, fee, disputable, state)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'applied')",
+            )?
+            .execute(params![
+                client_id,
+                id,
+                tx_type,
+                to_text(transaction.amount),
+                to_text(transaction.fee),
+                transaction.disputable,
+            ])?;
+        }
+    }
+
+    for client_id in touched.into_iter().flatten() {
+        let Some(usage) = payments_engine.limit_usage.get(&client_id) else {
+            continue;
+        };
+        sql.prepare_cached("DELETE FROM limit_usage WHERE client = ?1")?
+            .execute(params![client_id])?;
+        let mut insert = sql.prepare_cached(
+            "INSERT INTO limit_usage (client, kind, timestamp, amount) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (timestamp, amount) in usage.withdrawals() {
+            insert.execute(params![
+                client_id,
+                "withdrawal",
+                timestamp as i64,
+                to_text(amount)
+            ])?;
+        }
+        for timestamp in usage.transactions() {
+            insert.execute(params![
+                client_id,
+                "transaction",
+                timestamp as i64,
+                None::<String>
+            ])?;
+        }
+    }
+
+    for (ledger_account, balance) in payments_engine.ledger.balances() {
+        let (name, _) = LEDGER_ACCOUNTS
+            .into_iter()
+            .find(|(_, named)| *named == ledger_account)
+            .expect("the engine's ledger accounts are named");
+        sql.prepare_cached("INSERT OR REPLACE INTO ledger (account, balance) VALUES (?1, ?2)")?
+            .execute(params![name, to_text(balance)])?;
+    }
+    Ok(())
+}
+
+// With four decimal places, as the amounts are written everywhere else.
+fn to_text(amount: Amount) -> String {
+    amount::fixed_scale(amount).to_string()
+}
+
+fn parse_amount(text: String) -> Result<Amount, Box<dyn Error>> {
+    text.parse()
+        .map_err(|e| format!("invalid amount '{}': {}", text, e).into())
+}
@@ -0,0 +1,152 @@
+use crate::amount;
+use crate::Account;
+use crate::Amount;
+use crate::ClientId;
+use crate::Rejection;
+use crate::Transaction;
+use crate::TransactionId;
+use crate::TransactionType;
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// What a statement entry is about - a client's own transaction, or a fee collected to (or refunded
+/// from) the house account for another client's transaction.
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryType {
+    Deposit,
+    Withdrawal,
+    Dispute,
+    Resolve,
+    Chargeback,
+    Transfer,
+    Fee,
+}
+
+impl From<TransactionType> for EntryType {
+    fn from(tx_type: TransactionType) -> Self {
+        match tx_type {
+            TransactionType::Deposit => EntryType::Deposit,
+            TransactionType::Withdrawal => EntryType::Withdrawal,
+            TransactionType::Dispute => EntryType::Dispute,
+            TransactionType::Resolve => EntryType::Resolve,
+            TransactionType::Chargeback => EntryType::Chargeback,
+            TransactionType::Transfer => EntryType::Transfer,
+        }
+    }
+}
+
+/// A line of a client's statement - a transaction applied to or rejected for their account, with
+/// the balances right after it. The balances are missing when the account was never opened.
+#[derive(Serialize, Debug, Clone)]
+pub struct StatementEntry {
+    #[serde(rename = "client")]
+    pub client_id: ClientId,
+    #[serde(rename = "tx")]
+    pub transaction_id: TransactionId,
+    #[serde(rename = "type")]
+    pub entry_type: EntryType,
+    pub amount: Option<Amount>,
+    // Charged to the client - negative when refunded on chargeback.
+    pub fee: Option<Amount>,
+    pub status: EntryStatus,
+    #[serde(rename = "reason")]
+    pub rejection: Option<Rejection>,
+    pub available: Option<Amount>,
+    pub held: Option<Amount>,
+    pub total: Option<Amount>,
+    pub locked: Option<bool>,
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum EntryStatus {
+    Applied,
+    Rejected,
+}
+
+/// The history of the clients' accounts, in the order the transactions were processed.
+pub(crate) struct Statement {
+    // Only this client's history is kept when given.
+    client_id: Option<ClientId>,
+    pub(crate) entries: Vec<StatementEntry>,
+}
+
+impl Statement {
+    pub(crate) fn new(client_id: Option<ClientId>) -> Self {
+        Statement {
+            client_id,
+            entries: Vec::new(),
+        }
+    }
+
+    // A transfer shows on the statements of both clients, a fee on the house account's. Rejected
+    // transactions only show on the statement of the client that sent them.
+    pub(crate) fn record(
+        &mut self,
+        accounts: &HashMap<ClientId, Account>,
+        transaction: &Transaction,
+        fee: Amount,
+        house_account: Option<ClientId>,
+        result: Result<(), Rejection>,
+    ) {
+        let fee = Some(fee)
+            .filter(|fee| !fee.is_zero() && result.is_ok())
+            .map(amount::fixed_scale);
+        let entry = StatementEntry {
+            client_id: transaction.client_id,
+            transaction_id: transaction.id,
+            entry_type: transaction.tx_type.into(),
+            amount: transaction.amount.map(amount::fixed_scale),
+            fee,
+            status: match result {
+                Ok(()) => EntryStatus::Applied,
+                Err(_) => EntryStatus::Rejected,
+            },
+            rejection: result.err(),
+            available: None,
+            held: None,
+            total: None,
+            locked: None,
+        };
+        self.add(accounts, entry.clone());
+        if result.is_err() {
+            return;
+        }
+        if let Some(destination_client_id) = transaction.destination_client_id {
+            self.add(
+                accounts,
+                StatementEntry {
+                    client_id: destination_client_id,
+                    ..entry.clone()
+                },
+            );
+        }
+        if let (Some(house_account), Some(_)) = (house_account, fee) {
+            self.add(
+                accounts,
+                StatementEntry {
+                    client_id: house_account,
+                    entry_type: EntryType::Fee,
+                    amount: fee,
+                    fee: None,
+                    ..entry
+                },
+            );
+        }
+    }
+
+    fn add(&mut self, accounts: &HashMap<ClientId, Account>, entry: StatementEntry) {
+        if self.client_id.is_some_and(|only| only != entry.client_id) {
+            return;
+        }
+        let account = accounts.get(&entry.client_id);
+        self.entries.push(StatementEntry {
+            available: account.map(|account| amount::fixed_scale(account.available())),
+            held: account.map(|account| amount::fixed_scale(account.held())),
+            total: account.map(|account| amount::fixed_scale(account.total())),
+            locked: account.map(Account::locked),
+            ..entry
+        });
+    }
+}
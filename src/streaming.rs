@@ -0,0 +1,201 @@
+use crate::CsvDialect;
+use crate::PaymentsEngine;
+use crate::RecordParser;
+use crate::Rejection;
+use crate::Stopwatch;
+use crate::Transaction;
+use csv::ByteRecord;
+use csv_core::ReadRecordResult;
+use futures_util::stream;
+use futures_util::Stream;
+use futures_util::StreamExt;
+use std::collections::VecDeque;
+use std::error::Error;
+use std::pin::Pin;
+use tokio::io::AsyncBufReadExt;
+use tokio::io::AsyncRead;
+use tokio::io::BufReader;
+
+// The CSV is parsed a chunk of whole records at a time, by the same parser as `process_csv`.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A transaction processed from a stream, and whether it was applied.
+#[derive(Debug, Clone)]
+pub struct Outcome {
+    pub transaction: Transaction,
+    pub result: Result<(), Rejection>,
+}
+
+/// Processes the transactions as they come, yielding the outcome of each.
+pub fn process_stream<'a>(
+    transactions: impl Stream<Item = Transaction> + 'a,
+    payments_engine: &'a mut PaymentsEngine,
+) -> impl Stream<Item = Outcome> + 'a {
+    payments_engine.start_input();
+    transactions.map(move |transaction| process(payments_engine, transaction, None))
+}
+
+/// Processes the transactions of a CSV read asynchronously, yielding the outcome of each. The
+/// stream ends with an error at the first record that does not parse, or at the end of the input
+/// when writing out snapshots, to a database or the change feed failed along the way - like
+/// `process_csv` does.
+pub fn process_csv_async<'a>(
+    transactions_csv: impl AsyncRead + Send + 'a,
+    payments_engine: &'a mut PaymentsEngine,
+    dialect: &CsvDialect,
+) -> impl Stream<Item = Result<Outcome, Box<dyn Error + Send + Sync>>> + 'a {
+    payments_engine.start_input();
+    let chunks = Chunks {
+        input: BufReader::new(Box::pin(transactions_csv)),
+        dialect: dialect.clone(),
+        parser: None,
+        record_ends: RecordEnds::new(dialect),
+        chunk: Vec::new(),
+        transactions: VecDeque::new(),
+        error: None,
+        done: false,
+    };
+    stream::unfold(
+        (chunks, payments_engine, Some(Stopwatch::start())),
+        |(mut chunks, payments_engine, mut stopwatch)| async move {
+            // Nothing is applied past a transaction that failed to be saved, which ends the input.
+            let next = match payments_engine.check_saved() {
+                Ok(()) => chunks.next().await,
+                Err(_) => Ok(None),
+            };
+            let outcome = match next {
+                Ok(Some((transaction, rejection))) => {
+                    Ok(process(payments_engine, transaction, rejection))
+                }
+                // Stopped once, at the end of the input.
+                Ok(None) => {
+                    stopwatch.take()?.stop(&mut payments_engine.stats);
+                    Err(payments_engine.finish_input().err()?.into())
+                }
+                Err(e) => Err(e),
+            };
+            Some((outcome, (chunks, payments_engine, stopwatch)))
+        },
+    )
+}
+
+fn process(
+    payments_engine: &mut PaymentsEngine,
+    transaction: Transaction,
+    rejection: Option<Rejection>,
+) -> Outcome {
+    let result = payments_engine.process_record(transaction.clone(), rejection);
+    Outcome {
+        transaction,
+        result,
+    }
+}
+
+struct Chunks<'a> {
+    input: BufReader<Pin<Box<dyn AsyncRead + Send + 'a>>>,
+    dialect: CsvDialect,
+    // Known once the headers are read.
+    parser: Option<RecordParser>,
+    record_ends: RecordEnds,
+    chunk: Vec<u8>,
+    transactions: VecDeque<(Transaction, Option<Rejection>)>,
+    // Reported after the transactions parsed before it.
+    error: Option<Box<dyn Error + Send + Sync>>,
+    done: bool,
+}
+
+impl Chunks<'_> {
+    async fn next(
+        &mut self,
+    ) -> Result<Option<(Transaction, Option<Rejection>)>, Box<dyn Error + Send + Sync>> {
+        loop {
+            if let Some(transaction) = self.transactions.pop_front() {
+                return Ok(Some(transaction));
+            }
+            if let Some(error) = self.error.take() {
+                return Err(error);
+            }
+            if self.done {
+                return Ok(None);
+            }
+            if let Err(e) = self.read_chunk().await {
+                self.error = Some(e);
+                self.done = true;
+            }
+        }
+    }
+
+    async fn read_chunk(&mut self) -> Result<(), Box<dyn Error + Send + Sync>> {
+        if self.parser.is_none() {
+            self.parser = Some(self.read_headers().await?);
+        }
+        self.chunk.clear();
+        // A chunk ends with a whole record, even if it takes reading on past its size.
+        while self.chunk.len() < CHUNK_SIZE || !self.record_ends.at_record_end() {
+            let start = self.chunk.len();
+            if self.input.read_until(b'\n', &mut self.chunk).await? == 0 {
+                self.done = true;
+                break;
+            }
+            self.record_ends.read(&self.chunk[start..]);
+        }
+        let parser = self.parser.as_ref().expect("headers read");
+        let mut rdr = self.dialect.records_reader(self.chunk.as_slice());
+        let mut record = ByteRecord::new();
+        while rdr.read_byte_record(&mut record)? {
+            let transaction = parser.parse(&mut record, &self.dialect)?;
+            self.transactions.push_back(transaction);
+        }
+        Ok(())
+    }
+
+    async fn read_headers(&mut self) -> Result<RecordParser, Box<dyn Error + Send + Sync>> {
+        let mut line = Vec::new();
+        if self.dialect.has_headers() {
+            self.input.read_until(b'\n', &mut line).await?;
+        }
+        let mut rdr = self
+            .dialect
+            .reader(line.as_slice())
+            .map_err(|e| e.to_string())?;
+        let headers = self.dialect.headers(&mut rdr).map_err(|e| e.to_string())?;
+        Ok(RecordParser::new(headers, true))
+    }
+}
+
+// Follows the records through the lines read, for a line ending within a quoted field not to be
+// taken for the end of a record.
+struct RecordEnds {
+    reader: csv_core::Reader,
+    // Part of a record has been read, e.g. the first line of a quoted field spanning lines.
+    in_record: bool,
+}
+
+impl RecordEnds {
+    fn new(dialect: &CsvDialect) -> Self {
+        RecordEnds {
+            reader: dialect.record_ends(),
+            in_record: false,
+        }
+    }
+
+    fn at_record_end(&self) -> bool {
+        !self.in_record
+    }
+
+    fn read(&mut self, mut input: &[u8]) {
+        // The fields themselves are of no interest, the buffers are reused whenever full.
+        let mut output = [0; 1024];
+        let mut ends = [0; 64];
+        while !input.is_empty() {
+            let (result, read, written, ended) =
+                self.reader.read_record(input, &mut output, &mut ends);
+            input = &input[read..];
+            // Line endings and blank lines between records are read without any output.
+            self.in_record = match result {
+                ReadRecordResult::Record | ReadRecordResult::End => false,
+                _ => self.in_record || written > 0 || ended > 0,
+            };
+        }
+    }
+}
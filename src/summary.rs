@@ -0,0 +1,175 @@
+use crate::amount;
+use crate::Amount;
+use crate::Rejection;
+use crate::TransactionType;
+use serde::Deserialize;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+use std::time::Instant;
+
+/// What happened during a run - see `PaymentsEngine::summary`.
+#[derive(Serialize, Debug, Clone)]
+pub struct Summary {
+    pub transactions: usize,
+    pub applied: usize,
+    pub rejected: usize,
+    pub by_type: BTreeMap<TransactionType, TypeSummary>,
+    pub rejections: BTreeMap<Rejection, usize>,
+    // Transactions skipped as processed before, with idempotency on. Not counted as transactions.
+    pub duplicates: usize,
+    pub deposited: Amount,
+    pub withdrawn: Amount,
+    // Held at the end of the run.
+    pub held: Amount,
+    pub charged_back: Amount,
+    pub locked_accounts: usize,
+    pub elapsed_seconds: f64,
+    // Only known when the transactions were read by `process_csv`.
+    pub transactions_per_second: Option<f64>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TypeSummary {
+    pub applied: usize,
+    pub rejected: usize,
+}
+
+const TRANSACTION_TYPES: [TransactionType; 6] = [
+    TransactionType::Deposit,
+    TransactionType::Withdrawal,
+    TransactionType::Dispute,
+    TransactionType::Resolve,
+    TransactionType::Chargeback,
+    TransactionType::Transfer,
+];
+
+/// Running counts kept by the engine for the summary.
+#[derive(Deserialize, Serialize, Default, Clone)]
+pub(crate) struct Stats {
+    // Indexed by the transaction type, as this is updated for every transaction.
+    by_type: [TypeSummary; TRANSACTION_TYPES.len()],
+    rejections: BTreeMap<Rejection, usize>,
+    pub(crate) duplicates: usize,
+    deposited: Amount,
+    withdrawn: Amount,
+    charged_back: Amount,
+    pub(crate) elapsed: Duration,
+}
+
+impl Stats {
+    // The charged back amount is the disputed amount - negative for withdrawals.
+    pub(crate) fn record(
+        &mut self,
+        tx_type: TransactionType,
+        amount: Option<Amount>,
+        charged_back: Option<Amount>,
+        result: Result<(), Rejection>,
+    ) {
+        let counts = &mut self.by_type[tx_type as usize];
+        match result {
+            Ok(()) => counts.applied += 1,
+            Err(rejection) => {
+                counts.rejected += 1;
+                *self.rejections.entry(rejection).or_default() += 1;
+                return;
+            }
+        }
+        let amount = amount.unwrap_or(Amount::ZERO);
+        match tx_type {
+            // The totals of a run may exceed the balances of any single account, so they saturate
+            // rather than overflow.
+            TransactionType::Deposit => self.deposited = self.deposited.saturating_add(amount),
+            TransactionType::Withdrawal => self.withdrawn = self.withdrawn.saturating_add(amount),
+            TransactionType::Chargeback => {
+                let charged_back = charged_back.unwrap_or(Amount::ZERO);
+                let charged_back = amount::checked_abs(charged_back).unwrap_or(Amount::MAX);
+                self.charged_back = self.charged_back.saturating_add(charged_back)
+            }
+            _ => {}
+        }
+    }
+
+    pub(crate) fn summary(&self, held: Amount, locked_accounts: usize) -> Summary {
+        let (applied, rejected) = self
+            .by_type
+            .iter()
+            .fold((0, 0), |(applied, rejected), counts| {
+                (applied + counts.applied, rejected + counts.rejected)
+            });
+        let transactions = applied + rejected;
+        let elapsed_seconds = self.elapsed.as_secs_f64();
+        Summary {
+            transactions,
+            applied,
+            rejected,
+            by_type: TRANSACTION_TYPES
+                .into_iter()
+                .zip(self.by_type)
+                .filter(|(_, counts)| counts.applied + counts.rejected > 0)
+                .collect(),
+            rejections: self.rejections.clone(),
+            duplicates: self.duplicates,
+            deposited: amount::fixed_scale(self.deposited),
+            withdrawn: amount::fixed_scale(self.withdrawn),
+            held: amount::fixed_scale(held),
+            charged_back: amount::fixed_scale(self.charged_back),
+            locked_accounts,
+            elapsed_seconds,
+            transactions_per_second: Some(transactions as f64 / elapsed_seconds)
+                .filter(|_| elapsed_seconds > 0.0),
+        }
+    }
+}
+
+// Times the processing of an input, adding the time since started or last stopped to the summary
+// whenever stopped.
+pub(crate) struct Stopwatch(Instant);
+
+impl Stopwatch {
+    pub(crate) fn start() -> Self {
+        Stopwatch(Instant::now())
+    }
+
+    pub(crate) fn stop(&mut self, stats: &mut Stats) {
+        let now = Instant::now();
+        stats.elapsed += now - self.0;
+        self.0 = now;
+    }
+}
+
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "transactions: {} ({} applied, {} rejected) in {:.3}s",
+            self.transactions, self.applied, self.rejected, self.elapsed_seconds
+        )?;
+        if let Some(transactions_per_second) = self.transactions_per_second {
+            write!(f, ", {:.0} per second", transactions_per_second)?;
+        }
+        writeln!(f)?;
+        for (tx_type, counts) in &self.by_type {
+            writeln!(
+                f,
+                "  {}: {} applied, {} rejected",
+                tx_type, counts.applied, counts.rejected
+            )?;
+        }
+        if !self.rejections.is_empty() {
+            writeln!(f, "rejections:")?;
+            for (rejection, count) in &self.rejections {
+                writeln!(f, "  {}: {}", rejection, count)?;
+            }
+        }
+        if self.duplicates > 0 {
+            writeln!(f, "duplicates skipped: {}", self.duplicates)?;
+        }
+        writeln!(f, "deposited: {}", self.deposited)?;
+        writeln!(f, "withdrawn: {}", self.withdrawn)?;
+        writeln!(f, "held: {}", self.held)?;
+        writeln!(f, "charged back: {}", self.charged_back)?;
+        writeln!(f, "locked accounts: {}", self.locked_accounts)
+    }
+}
@@ -0,0 +1,111 @@
+#[cfg(test)]
+#[cfg(not(feature = "decimal"))]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::ParseAmountError;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    #[test]
+    fn parses_amounts_exactly() {
+        assert_eq!(amount("1.5"), Amount::from_ten_thousandths(15000));
+        assert_eq!(amount("0.0001"), Amount::from_ten_thousandths(1));
+        assert_eq!(amount("-2.25"), Amount::from_ten_thousandths(-22500));
+        assert_eq!(amount("+007.50"), amount("7.5"));
+        assert_eq!(amount("10."), amount("10"));
+        assert_eq!(amount(".5"), amount("0.5"));
+        assert_eq!(amount("1.23450000"), amount("1.2345"));
+        assert_eq!(amount("1e3"), amount("1000"));
+        assert_eq!(amount("12.5E-3"), amount("0.00125e1"));
+        assert_eq!(amount("0e-100000000"), Amount::ZERO);
+        assert_eq!(amount("922337203685477.5807"), Amount::MAX);
+        assert_eq!(amount("-922337203685477.5808"), Amount::MIN);
+    }
+
+    #[test]
+    fn rejects_invalid_amounts() {
+        for (input, error) in [
+            ("", ParseAmountError::Invalid),
+            (".", ParseAmountError::Invalid),
+            ("-", ParseAmountError::Invalid),
+            ("ten", ParseAmountError::Invalid),
+            ("1.2.3", ParseAmountError::Invalid),
+            ("1e", ParseAmountError::Invalid),
+            (" 1", ParseAmountError::Invalid),
+            ("0.00001", ParseAmountError::TooPrecise),
+            ("1.23456", ParseAmountError::TooPrecise),
+            ("1e-5", ParseAmountError::TooPrecise),
+            ("922337203685477.5808", ParseAmountError::Overflow),
+            ("1e15", ParseAmountError::Overflow),
+            (
+                "99999999999999999999999999999999999999999",
+                ParseAmountError::Overflow,
+            ),
+        ] {
+            assert_eq!(input.parse::<Amount>(), Err(error), "{}", input);
+        }
+    }
+
+    #[test]
+    fn formats_amounts_with_four_decimal_places() {
+        for (input, formatted) in [
+            ("0", "0.0000"),
+            ("-0", "0.0000"),
+            ("10.00", "10.0000"),
+            ("1e3", "1000.0000"),
+            ("1.5", "1.5000"),
+            ("-0.25", "-0.2500"),
+            ("0.0001", "0.0001"),
+            ("-922337203685477.5808", "-922337203685477.5808"),
+        ] {
+            assert_eq!(amount(input).to_string(), formatted);
+        }
+    }
+
+    #[test]
+    fn takes_up_no_more_than_an_i64() {
+        assert_eq!(std::mem::size_of::<Amount>(), std::mem::size_of::<i64>());
+    }
+
+    #[test]
+    fn detects_overflow() {
+        assert_eq!(Amount::MAX.checked_add(amount("0.0001")), None);
+        assert_eq!(Amount::MIN.checked_sub(amount("0.0001")), None);
+        assert_eq!(Amount::MIN.checked_neg(), None);
+        assert_eq!(Amount::MIN.checked_abs(), None);
+        assert_eq!(Amount::MAX.checked_sub(Amount::MAX), Some(Amount::ZERO));
+        assert_eq!(Amount::MAX.checked_percentage(amount("100.0001")), None);
+    }
+
+    #[test]
+    fn saturates_at_bounds() {
+        assert_eq!(Amount::MAX.saturating_add(amount("0.0001")), Amount::MAX);
+        assert_eq!(Amount::MIN.saturating_sub(amount("0.0001")), Amount::MIN);
+    }
+
+    #[test]
+    fn rounds_percentages_half_to_even() {
+        assert_eq!(
+            amount("10").checked_percentage(amount("0.5")),
+            Some(amount("0.05"))
+        );
+        assert_eq!(
+            amount("0.0001").checked_percentage(amount("50")),
+            Some(Amount::ZERO)
+        );
+        assert_eq!(
+            amount("0.0003").checked_percentage(amount("50")),
+            Some(amount("0.0002"))
+        );
+        assert_eq!(
+            amount("0.0003").checked_percentage(amount("60")),
+            Some(amount("0.0002"))
+        );
+        assert_eq!(
+            amount("-0.0003").checked_percentage(amount("50")),
+            Some(amount("-0.0002"))
+        );
+    }
+}
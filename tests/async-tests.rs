@@ -0,0 +1,171 @@
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use futures_util::stream;
+    use futures_util::StreamExt;
+    use payments_engine::Amount;
+    use payments_engine::CsvDialect;
+    use payments_engine::Outcome;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::Snapshots;
+    use payments_engine::Transaction;
+    use std::fs;
+    use std::io;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn statement(payments_engine: &PaymentsEngine) -> String {
+        let mut output = Vec::new();
+        payments_engine::write_statement_to_csv(payments_engine, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    fn assert_send<T: Send>(_: &T) {}
+
+    #[tokio::test]
+    async fn processes_stream_of_transactions() {
+        let mut payments_engine = PaymentsEngine::new();
+        let transactions = stream::iter([
+            Transaction::deposit(1, 1, amount("10")),
+            Transaction::withdrawal(1, 2, amount("20")),
+            Transaction::dispute(1, 1),
+        ]);
+
+        let results: Vec<Result<(), Rejection>> =
+            payments_engine::process_stream(transactions, &mut payments_engine)
+                .map(|outcome: Outcome| outcome.result)
+                .collect()
+                .await;
+
+        assert_eq!(results, [Ok(()), Err(Rejection::InsufficientFunds), Ok(())]);
+        assert_eq!(payments_engine.account(1).unwrap().held(), amount("10"));
+    }
+
+    #[tokio::test]
+    async fn processes_csv_as_sync_reader_does() {
+        let input = fs::read("deposits-withdrawals-100k.csv").unwrap();
+        let mut sync_engine = PaymentsEngine::new().with_statement(None);
+        payments_engine::process_csv(input.as_slice(), &mut sync_engine).unwrap();
+
+        let mut async_engine = PaymentsEngine::new().with_statement(None);
+        let outcomes = payments_engine::process_csv_async(
+            input.as_slice(),
+            &mut async_engine,
+            &CsvDialect::new(),
+        );
+        assert_send(&outcomes);
+        let outcomes: Vec<_> = outcomes.collect().await;
+
+        assert_eq!(outcomes.len(), sync_engine.statement().len());
+        assert!(outcomes.iter().all(Result::is_ok));
+        assert_eq!(statement(&async_engine), statement(&sync_engine));
+    }
+
+    #[tokio::test]
+    async fn reads_quoted_fields_spanning_lines() {
+        // Enough records for chunks to end within some of them.
+        let input = (1..=10_000).fold("type,client,tx,amount\n".to_string(), |input, tx| {
+            input + &format!("deposit,1,{},\"1.5\r\n\"\r\n", tx)
+        });
+        let mut sync_engine = PaymentsEngine::new().with_statement(None);
+        payments_engine::process_csv(input.as_bytes(), &mut sync_engine).unwrap();
+
+        let mut async_engine = PaymentsEngine::new().with_statement(None);
+        let outcomes: Vec<_> = payments_engine::process_csv_async(
+            input.as_bytes(),
+            &mut async_engine,
+            &CsvDialect::new(),
+        )
+        .collect()
+        .await;
+
+        assert_eq!(outcomes.len(), 10_000);
+        assert!(outcomes.iter().all(Result::is_ok));
+        assert_eq!(statement(&async_engine), statement(&sync_engine));
+    }
+
+    #[tokio::test]
+    async fn processes_csv_in_dialect() {
+        let mut payments_engine = PaymentsEngine::new();
+        let dialect = CsvDialect::new()
+            .with_delimiter(b';')
+            .without_headers(["client", "tx", "type", "amount"]);
+
+        let outcomes: Vec<_> = payments_engine::process_csv_async(
+            "1;1;deposit;5\n1;2;withdrawal;7\n".as_bytes(),
+            &mut payments_engine,
+            &dialect,
+        )
+        .collect()
+        .await;
+
+        let results: Vec<_> = outcomes
+            .into_iter()
+            .map(|outcome| outcome.unwrap().result)
+            .collect();
+        assert_eq!(results, [Ok(()), Err(Rejection::InsufficientFunds)]);
+    }
+
+    #[tokio::test]
+    async fn ends_with_error_at_invalid_record() {
+        let mut payments_engine = PaymentsEngine::new();
+
+        let outcomes: Vec<_> = payments_engine::process_csv_async(
+            "type, client, tx, amount
+            deposit, 1, 1, 5
+            refund, 1, 2, 5
+            deposit, 1, 3, 5"
+                .as_bytes(),
+            &mut payments_engine,
+            &CsvDialect::new(),
+        )
+        .collect()
+        .await;
+
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].is_ok());
+        assert!(outcomes[1].is_err());
+        assert_eq!(payments_engine.account(1).unwrap().total(), amount("5"));
+    }
+
+    #[tokio::test]
+    async fn ends_with_error_when_snapshot_fails() {
+        let snapshots =
+            Snapshots::new(|_sequence| Err(io::Error::other("disk full"))).with_interval(1);
+        let mut payments_engine = PaymentsEngine::new().with_snapshots(snapshots);
+
+        let outcomes: Vec<_> = payments_engine::process_csv_async(
+            "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2,1\n".as_bytes(),
+            &mut payments_engine,
+            &CsvDialect::new(),
+        )
+        .collect()
+        .await;
+
+        assert_eq!(outcomes.len(), 3);
+        assert!(outcomes[..2].iter().all(Result::is_ok));
+        assert_eq!(
+            outcomes[2].as_ref().unwrap_err().to_string(),
+            "failed to write a snapshot: disk full"
+        );
+    }
+
+    #[tokio::test]
+    async fn times_processing_for_summary() {
+        let input = fs::read("deposits-withdrawals-100k.csv").unwrap();
+        let mut payments_engine = PaymentsEngine::new();
+
+        let outcomes: Vec<_> = payments_engine::process_csv_async(
+            input.as_slice(),
+            &mut payments_engine,
+            &CsvDialect::new(),
+        )
+        .collect()
+        .await;
+
+        assert!(outcomes.iter().all(Result::is_ok));
+        assert!(payments_engine.summary().elapsed_seconds > 0.0);
+    }
+}
@@ -0,0 +1,138 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::BalanceChange;
+    use payments_engine::ChangeFeed;
+    use payments_engine::ChangeFormat;
+    use payments_engine::PaymentsEngine;
+    use std::io;
+    use std::io::Write;
+    use std::sync::mpsc;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn process_changes(input: &str, format: ChangeFormat) -> String {
+        let written = Written::default();
+        let mut payments_engine =
+            PaymentsEngine::new().with_change_feed(ChangeFeed::to_writer(written.clone(), format));
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        let changes = written.0.lock().unwrap();
+        String::from_utf8(changes.clone()).unwrap()
+    }
+
+    #[test]
+    fn writes_changes_as_csv() {
+        let changes = process_changes(
+            "type,client,tx,amount
+            deposit,1,1,10
+            dispute,1,1,
+            chargeback,1,1,",
+            ChangeFormat::Csv,
+        );
+
+        assert_eq!(
+            changes,
+            "client,tx,available_before,available_after,held_before,held_after,total_before,total_after,locked_before,locked_after
+1,1,0.0000,10.0000,0.0000,0.0000,0.0000,10.0000,false,false
+1,1,10.0000,0.0000,0.0000,10.0000,10.0000,10.0000,false,false
+1,1,0.0000,0.0000,10.0000,0.0000,10.0000,0.0000,false,true
+"
+        );
+    }
+
+    #[test]
+    fn writes_changes_as_jsonl() {
+        let changes = process_changes(
+            "type,client,tx,amount
+            deposit,1,1,1.5",
+            ChangeFormat::Jsonl,
+        );
+
+        assert_eq!(
+            changes,
+            r#"{"client":1,"tx":1,"available_before":"0.0000","available_after":"1.5000","held_before":"0.0000","held_after":"0.0000","total_before":"0.0000","total_after":"1.5000","locked_before":false,"locked_after":false}
+"#
+        );
+    }
+
+    #[test]
+    fn leaves_out_rejected_transactions() {
+        let changes = process_changes(
+            "type,client,tx,amount
+            deposit,1,1,10
+            withdrawal,1,2,20
+            resolve,1,1,",
+            ChangeFormat::Csv,
+        );
+
+        assert_eq!(changes.lines().count(), 2);
+    }
+
+    #[test]
+    fn sends_change_of_each_account_to_channel() {
+        let (sender, receiver) = mpsc::channel();
+        let mut payments_engine =
+            PaymentsEngine::new().with_change_feed(ChangeFeed::to_channel(sender));
+
+        payments_engine::process_csv(
+            "type,client,tx,amount,to
+            deposit,1,1,10,
+            transfer,1,2,4,2"
+                .as_bytes(),
+            &mut payments_engine,
+        )
+        .unwrap();
+
+        let changes: Vec<BalanceChange> = receiver.try_iter().collect();
+        let changes: Vec<_> = changes
+            .iter()
+            .map(|change| {
+                (
+                    change.client_id,
+                    change.transaction_id,
+                    change.available_before.to_string(),
+                    change.available_after.to_string(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            changes,
+            [
+                (1, 1, "0.0000".to_string(), "10.0000".to_string()),
+                (1, 2, "10.0000".to_string(), "6.0000".to_string()),
+                (2, 2, "0.0000".to_string(), "4.0000".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn fails_processing_when_receiver_hangs_up() {
+        let (sender, receiver) = mpsc::channel();
+        drop(receiver);
+        let mut payments_engine =
+            PaymentsEngine::new().with_change_feed(ChangeFeed::to_channel(sender));
+
+        let result = payments_engine::process_csv(
+            "type,client,tx,amount\ndeposit,1,1,10".as_bytes(),
+            &mut payments_engine,
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "failed to emit the balance changes: the receiver of the balance changes hung up"
+        );
+    }
+}
@@ -0,0 +1,179 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Checkpoints;
+    use payments_engine::CsvDialect;
+    use payments_engine::PaymentsEngine;
+    use std::fs;
+    use std::io::Cursor;
+    use std::path::PathBuf;
+    use std::str;
+
+    const TRANSACTIONS: &str = "type,client,tx,amount
+deposit,1,1,10
+deposit,1,2,5
+dispute,1,1,
+withdrawal,1,3,2
+resolve,1,1,
+withdrawal,1,4,1
+";
+
+    fn checkpoint_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-{}-{}.checkpoint",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    fn process(
+        input: &str,
+        checkpoints: &Checkpoints,
+    ) -> Result<PaymentsEngine, Box<dyn std::error::Error>> {
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv_with_checkpoints(
+            Cursor::new(input.as_bytes()),
+            &mut payments_engine,
+            &CsvDialect::new(),
+            checkpoints,
+            None,
+        )?;
+        Ok(payments_engine)
+    }
+
+    fn account_states(payments_engine: &PaymentsEngine) -> String {
+        let mut output = Vec::new();
+        payments_engine::write_account_states_to_csv(payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    // The account states in client order, for those of several clients to be compared.
+    fn sorted_account_states(payments_engine: &PaymentsEngine) -> Vec<String> {
+        let mut account_states: Vec<String> = account_states(payments_engine)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        account_states.sort();
+        account_states
+    }
+
+    #[test]
+    fn resumes_where_run_stopped() {
+        let path = checkpoint_path("stopped");
+        // The run stops halfway, as if the rest of the input was not there yet.
+        let halfway = TRANSACTIONS.lines().take(4).collect::<Vec<_>>().join("\n") + "\n";
+        process(&halfway, &Checkpoints::new(&path, 2)).unwrap();
+
+        let payments_engine = process(TRANSACTIONS, &Checkpoints::new(&path, 2).with_resume());
+
+        let payments_engine = payments_engine.unwrap();
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,12.0000,0.0000,12.0000,false\n"
+        );
+        assert_eq!(payments_engine.summary().transactions, 6);
+    }
+
+    #[test]
+    fn applies_rows_after_last_checkpoint_once() {
+        let path = checkpoint_path("once");
+        // The run fails at the fourth row, after the third was applied but not checkpointed.
+        let broken = TRANSACTIONS.replace("withdrawal,1,3,2", "withdrawal,1,x,2");
+        assert!(process(&broken, &Checkpoints::new(&path, 2)).is_err());
+
+        let payments_engine = process(TRANSACTIONS, &Checkpoints::new(&path, 2).with_resume());
+
+        assert_eq!(
+            account_states(&payments_engine.unwrap()),
+            "client,available,held,total,locked\n1,12.0000,0.0000,12.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn resumes_from_changes_recorded_after_full_checkpoint() {
+        let path = checkpoint_path("changes");
+        let transactions =
+            TRANSACTIONS.to_string() + "deposit,2,5,3\ndispute,2,5,\nchargeback,2,5,\n";
+        let halfway = transactions.lines().take(9).collect::<Vec<_>>().join("\n") + "\n";
+        process(&halfway, &Checkpoints::new(&path, 1)).unwrap();
+        // The state in full, then the changes of the rows after it.
+        assert!(fs::read_to_string(&path).unwrap().lines().count() > 1);
+
+        let payments_engine = process(&transactions, &Checkpoints::new(&path, 1).with_resume());
+
+        let payments_engine = payments_engine.unwrap();
+        assert_eq!(
+            sorted_account_states(&payments_engine),
+            [
+                "1,12.0000,0.0000,12.0000,false",
+                "2,0.0000,0.0000,0.0000,true",
+                "client,available,held,total,locked"
+            ]
+        );
+        assert_eq!(payments_engine.summary().transactions, 9);
+    }
+
+    #[test]
+    fn leaves_out_changes_cut_short() {
+        let path = checkpoint_path("cut");
+        let halfway = TRANSACTIONS.lines().take(5).collect::<Vec<_>>().join("\n") + "\n";
+        process(&halfway, &Checkpoints::new(&path, 1)).unwrap();
+        // The run dies while writing the changes of the last row.
+        let checkpoint = fs::read(&path).unwrap();
+        fs::write(&path, &checkpoint[..checkpoint.len() - 10]).unwrap();
+
+        let payments_engine = process(TRANSACTIONS, &Checkpoints::new(&path, 1).with_resume());
+
+        let payments_engine = payments_engine.unwrap();
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,12.0000,0.0000,12.0000,false\n"
+        );
+        assert_eq!(payments_engine.summary().transactions, 6);
+    }
+
+    #[test]
+    fn refuses_to_resume_on_different_input() {
+        let path = checkpoint_path("different");
+        process(TRANSACTIONS, &Checkpoints::new(&path, 2)).unwrap();
+        let different = TRANSACTIONS.replace("deposit,1,2,5", "deposit,1,2,6");
+
+        let result = process(&different, &Checkpoints::new(&path, 2).with_resume());
+
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .ends_with("was made from a different input"));
+    }
+
+    #[test]
+    fn starts_from_scratch_without_checkpoint() {
+        let path = checkpoint_path("scratch");
+
+        let payments_engine = process(TRANSACTIONS, &Checkpoints::new(&path, 100).with_resume());
+
+        assert_eq!(
+            account_states(&payments_engine.unwrap()),
+            "client,available,held,total,locked\n1,12.0000,0.0000,12.0000,false\n"
+        );
+        // The checkpoint at the end of the input leaves nothing to apply when resumed again.
+        let payments_engine = process(TRANSACTIONS, &Checkpoints::new(&path, 100).with_resume());
+        assert_eq!(payments_engine.unwrap().summary().transactions, 6);
+    }
+
+    #[test]
+    fn fails_on_invalid_checkpoint() {
+        let path = checkpoint_path("invalid");
+        fs::write(&path, "{").unwrap();
+
+        let result = process(TRANSACTIONS, &Checkpoints::new(&path, 2).with_resume());
+
+        assert!(result
+            .err()
+            .unwrap()
+            .to_string()
+            .starts_with("invalid checkpoint"));
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Compression;
+    use payments_engine::PaymentsEngine;
+    use std::fs;
+    use std::path::PathBuf;
+
+    const TRANSACTIONS: &str = "type, client, tx, amount
+        deposit, 1, 1, 10
+        withdrawal, 1, 2, 2.5";
+
+    const ACCOUNTS: &str = "client,available,held,total,locked
+1,7.5000,0.0000,7.5000,false
+";
+
+    fn temp_file(name: &str, contents: &[u8]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("{}-{}", std::process::id(), name));
+        fs::write(&path, contents).unwrap();
+        path
+    }
+
+    fn accounts(payments_engine: &PaymentsEngine) -> String {
+        let mut output = Vec::new();
+        payments_engine::write_account_states_to_csv(payments_engine, &mut output).unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn detects_compression_by_magic_bytes() {
+        assert_eq!(
+            Compression::from_magic_bytes(&[0x1f, 0x8b, 0x08]),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_magic_bytes(&[0x28, 0xb5, 0x2f, 0xfd, 0x00]),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_magic_bytes(b"type,client,tx,amount"),
+            Compression::None
+        );
+        assert_eq!(Compression::from_magic_bytes(&[]), Compression::None);
+    }
+
+    #[test]
+    fn detects_compression_by_extension() {
+        assert_eq!(
+            Compression::from_extension("transactions.csv.gz"),
+            Compression::Gzip
+        );
+        assert_eq!(
+            Compression::from_extension("transactions.csv.zst"),
+            Compression::Zstd
+        );
+        assert_eq!(
+            Compression::from_extension("transactions.csv"),
+            Compression::None
+        );
+        assert_eq!("zstd".parse(), Ok(Compression::Zstd));
+        assert!("lz4".parse::<Compression>().is_err());
+    }
+
+    #[test]
+    fn reads_uncompressed_file() {
+        let path = temp_file("transactions.csv", TRANSACTIONS.as_bytes());
+        let (transactions_csv, compression) = payments_engine::open_csv(&path).unwrap();
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(transactions_csv, &mut payments_engine).unwrap();
+        fs::remove_file(path).unwrap();
+
+        assert_eq!(compression, Compression::None);
+        assert_eq!(accounts(&payments_engine), ACCOUNTS);
+    }
+
+    #[cfg(not(feature = "gzip"))]
+    #[test]
+    fn rejects_gzip_without_feature() {
+        let path = temp_file("transactions.csv.gz", &[0x1f, 0x8b, 0x08, 0x00]);
+        let opened = payments_engine::open_csv(&path);
+        fs::remove_file(path).unwrap();
+
+        assert!(opened.is_err());
+    }
+
+    #[cfg(all(feature = "gzip", feature = "zstd"))]
+    #[test]
+    fn round_trips_compressed_files() {
+        use payments_engine::CompressedWriter;
+        use std::io::Write;
+
+        for (compression, name) in [
+            (Compression::Gzip, "transactions-gzip.csv"),
+            (Compression::Zstd, "transactions-zstd.csv"),
+        ] {
+            let mut wtr = CompressedWriter::new(Vec::new(), compression).unwrap();
+            wtr.write_all(TRANSACTIONS.as_bytes()).unwrap();
+            // Detected by the magic bytes, as the file has no telling extension.
+            let path = temp_file(name, &wtr.finish().unwrap());
+            let (transactions_csv, detected) = payments_engine::open_csv(&path).unwrap();
+            let mut payments_engine = PaymentsEngine::new();
+            payments_engine::process_csv(transactions_csv, &mut payments_engine).unwrap();
+            fs::remove_file(path).unwrap();
+
+            assert_eq!(detected, compression);
+            assert_eq!(accounts(&payments_engine), ACCOUNTS);
+        }
+    }
+
+    #[cfg(feature = "gzip")]
+    #[test]
+    fn reads_concatenated_gzip_members() {
+        use payments_engine::CompressedWriter;
+        use std::io::Write;
+
+        let mut input = Vec::new();
+        for part in ["type,client,tx,amount\ndeposit,1,1,10\n", "deposit,1,2,5\n"] {
+            let mut wtr = CompressedWriter::new(Vec::new(), Compression::Gzip).unwrap();
+            wtr.write_all(part.as_bytes()).unwrap();
+            input.extend(wtr.finish().unwrap());
+        }
+        let transactions_csv =
+            payments_engine::decompress(input.as_slice(), Compression::Gzip).unwrap();
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(transactions_csv, &mut payments_engine).unwrap();
+
+        assert_eq!(
+            accounts(&payments_engine),
+            "client,available,held,total,locked\n1,15.0000,0.0000,15.0000,false\n"
+        );
+    }
+}
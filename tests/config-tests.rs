@@ -0,0 +1,256 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::ChangeFormat;
+    use payments_engine::DisputeRules;
+    use payments_engine::EngineConfig;
+    use payments_engine::LockedAccounts;
+    use payments_engine::NegativeAmounts;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::SummaryFormat;
+    use payments_engine::Transaction;
+    use payments_engine::Verify;
+    use std::fs;
+    use std::path::PathBuf;
+
+    fn deposit(client_id: u16, id: u32, amount: &str) -> Transaction {
+        Transaction::deposit(client_id, id, amount.parse().unwrap())
+    }
+
+    #[test]
+    fn reads_all_policies() {
+        let config = EngineConfig::from_toml(
+            r#"
+            [strictness]
+            max_amount = "1000"
+            negative_amounts = "absolute"
+            locked_accounts = "allow-disputes"
+            verify = "end-of-run"
+
+            [duplicates]
+            idempotent = true
+
+            [disputes]
+            withdrawals = false
+
+            [output]
+            summary = "json"
+            changes = "jsonl"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.strictness.max_amount, Some("1000".parse().unwrap()));
+        assert_eq!(
+            config.strictness.negative_amounts,
+            NegativeAmounts::Absolute
+        );
+        assert_eq!(
+            config.strictness.locked_accounts,
+            LockedAccounts::AllowDisputes
+        );
+        assert_eq!(config.strictness.verify, Some(Verify::EndOfRun));
+        assert!(config.duplicates.idempotent);
+        assert_eq!(
+            config.disputes,
+            DisputeRules {
+                withdrawals: false,
+                lock_on_chargeback: true,
+            }
+        );
+        assert_eq!(config.output.summary, SummaryFormat::Json);
+        assert_eq!(config.output.changes, ChangeFormat::Jsonl);
+    }
+
+    #[test]
+    fn defaults_to_engine_defaults() {
+        let config = EngineConfig::from_toml("").unwrap();
+
+        assert_eq!(config, EngineConfig::default());
+        assert_eq!(config.disputes, DisputeRules::default());
+        assert!(config.disputes.withdrawals && config.disputes.lock_on_chargeback);
+    }
+
+    #[test]
+    fn rejects_invalid_config() {
+        let errors = [
+            (
+                "strictness = { max_amount = \"0\" }",
+                "max_amount must be positive",
+            ),
+            ("strictness = { verify = \"sometimes\" }", "unknown variant"),
+            (
+                "strictness = { negative_amounts = \"ignore\" }",
+                "unknown variant",
+            ),
+            ("[disputes]\ndeposits = false", "unknown field `deposits`"),
+            (
+                "fees = \"missing-fees.toml\"",
+                "invalid fees missing-fees.toml",
+            ),
+        ];
+
+        for (config, error) in errors {
+            let e = EngineConfig::from_toml(config).unwrap_err().to_string();
+            assert!(e.contains(error), "{}: {}", config, e);
+        }
+    }
+
+    #[test]
+    fn loads_files_relative_to_config() {
+        let dir =
+            std::env::temp_dir().join(format!("payments-engine-config-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("limits.toml"), "[default]\nmax_withdrawal = \"5\"").unwrap();
+        fs::write(dir.join("engine.toml"), "limits = \"limits.toml\"").unwrap();
+
+        let config = EngineConfig::load(dir.join("engine.toml")).unwrap();
+        let mut payments_engine = config.build().unwrap();
+        payments_engine
+            .process_transaction(deposit(1, 1, "10"))
+            .unwrap();
+
+        assert_eq!(config.limits, Some(dir.join("limits.toml")));
+        assert_eq!(
+            payments_engine.process_transaction(Transaction::withdrawal(
+                1,
+                2,
+                "6".parse().unwrap()
+            )),
+            Err(Rejection::WithdrawalLimitExceeded)
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prints_as_it_reads() {
+        let config = EngineConfig {
+            fees: Some(PathBuf::from("fees.toml")),
+            ..EngineConfig::from_toml("strictness = { max_amount = \"10.5\" }").unwrap()
+        };
+
+        let printed = toml::to_string(&config).unwrap();
+
+        assert_eq!(toml::from_str::<EngineConfig>(&printed).unwrap(), config);
+    }
+
+    #[test]
+    fn applies_dispute_rules() {
+        let mut payments_engine = PaymentsEngine::new().with_dispute_rules(DisputeRules {
+            withdrawals: false,
+            lock_on_chargeback: false,
+        });
+        payments_engine
+            .process_transaction(deposit(1, 1, "10"))
+            .unwrap();
+        payments_engine
+            .process_transaction(Transaction::withdrawal(1, 2, "3".parse().unwrap()))
+            .unwrap();
+
+        assert_eq!(
+            payments_engine.process_transaction(Transaction::dispute(1, 2)),
+            Err(Rejection::UnknownTransaction)
+        );
+        payments_engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .unwrap();
+        payments_engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .unwrap();
+        payments_engine
+            .process_transaction(deposit(1, 3, "4"))
+            .unwrap();
+
+        let account = payments_engine.account(1).unwrap();
+        assert!(!account.locked());
+        assert_eq!(account.total(), "1".parse().unwrap());
+    }
+
+    #[test]
+    fn applies_negative_amount_policy() {
+        let mut rejecting = EngineConfig::from_toml("").unwrap().build().unwrap();
+        let mut absolute =
+            EngineConfig::from_toml("strictness = { negative_amounts = \"absolute\" }")
+                .unwrap()
+                .build()
+                .unwrap();
+
+        for payments_engine in [&mut rejecting, &mut absolute] {
+            payments_engine
+                .process_transaction(deposit(1, 1, "10"))
+                .unwrap();
+        }
+        let negative = [
+            deposit(1, 2, "-5"),
+            Transaction::withdrawal(1, 3, "-2".parse().unwrap()),
+            Transaction::transfer(1, 4, "-1".parse().unwrap(), 2),
+        ];
+
+        for transaction in negative.clone() {
+            assert_eq!(
+                rejecting.process_transaction(transaction),
+                Err(Rejection::NegativeAmount)
+            );
+        }
+        for transaction in negative {
+            absolute.process_transaction(transaction).unwrap();
+        }
+        assert_eq!(rejecting.account(1).unwrap().total(), "10".parse().unwrap());
+        assert_eq!(absolute.account(1).unwrap().total(), "12".parse().unwrap());
+        assert_eq!(absolute.account(2).unwrap().total(), "1".parse().unwrap());
+        assert_eq!(absolute.summary().deposited, "15".parse().unwrap());
+    }
+
+    // The most negative fixed-point amount has no positive counterpart to take instead.
+    #[test]
+    #[cfg(not(feature = "decimal"))]
+    fn rejects_negative_amount_without_absolute_value() {
+        let mut payments_engine =
+            EngineConfig::from_toml("strictness = { negative_amounts = \"absolute\" }")
+                .unwrap()
+                .build()
+                .unwrap();
+
+        assert_eq!(
+            payments_engine.process_transaction(deposit(1, 1, "-922337203685477.5808")),
+            Err(Rejection::AmountOverflow)
+        );
+        assert!(payments_engine.account(1).is_none());
+    }
+
+    #[test]
+    fn applies_locked_account_policy() {
+        let transactions = [
+            deposit(1, 1, "10"),
+            deposit(1, 2, "5"),
+            Transaction::dispute(1, 1),
+            Transaction::chargeback(1, 1),
+        ];
+        let mut rejecting = PaymentsEngine::new().with_invariant_checks();
+        let mut allowing = PaymentsEngine::new()
+            .with_locked_accounts(LockedAccounts::AllowDisputes)
+            .with_invariant_checks();
+        for payments_engine in [&mut rejecting, &mut allowing] {
+            for transaction in transactions.clone() {
+                payments_engine.process_transaction(transaction).unwrap();
+            }
+        }
+
+        assert_eq!(
+            rejecting.process_transaction(Transaction::dispute(1, 2)),
+            Err(Rejection::AccountLocked)
+        );
+        allowing
+            .process_transaction(Transaction::dispute(1, 2))
+            .unwrap();
+        assert_eq!(
+            allowing.process_transaction(deposit(1, 3, "1")),
+            Err(Rejection::AccountLocked)
+        );
+        let account = allowing.account(1).unwrap();
+        assert!(account.locked());
+        assert_eq!(account.available(), "0".parse().unwrap());
+        assert_eq!(account.held(), "5".parse().unwrap());
+        assert!(allowing.violations().is_empty());
+    }
+}
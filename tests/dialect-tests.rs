@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::CsvDialect;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    fn process_statement(input: &str, dialect: &CsvDialect) -> Result<String, String> {
+        let mut payments_engine = PaymentsEngine::new().with_statement(None);
+        payments_engine::process_csv_with_dialect(
+            input.as_bytes(),
+            &mut payments_engine,
+            dialect,
+            None,
+        )
+        .map_err(|e| e.to_string())?;
+        let mut output = Vec::new();
+        payments_engine::write_statement_to_csv(&payments_engine, &mut output).unwrap();
+        Ok(str::from_utf8(&output).unwrap().to_string())
+    }
+
+    const STATEMENT: &str = "client,tx,type,amount,fee,status,reason,available,held,total,locked
+1,1,deposit,1.5000,,applied,,1.5000,0.0000,1.5000,false
+1,2,withdrawal,0.5000,,applied,,1.0000,0.0000,1.0000,false
+";
+
+    #[test]
+    fn reads_default_dialect() {
+        let statement = process_statement(
+            "type, client, tx, amount
+            deposit, 1, 1, 1.5
+            withdrawal, 1, 2, 0.5",
+            &CsvDialect::new(),
+        );
+
+        assert_eq!(statement.unwrap(), STATEMENT);
+    }
+
+    #[test]
+    fn reads_delimiter_and_quote() {
+        let statement = process_statement(
+            "type;client;tx;amount
+            deposit;1;1;'1.5'
+            withdrawal;'1';2;0.5",
+            &CsvDialect::new().with_delimiter(b';').with_quote(b'\''),
+        );
+
+        assert_eq!(statement.unwrap(), STATEMENT);
+    }
+
+    #[test]
+    fn reads_aliased_columns() {
+        let dialect = CsvDialect::new()
+            .with_alias("client_id", "client")
+            .with_alias("transaction_id", "tx");
+        let statement = process_statement(
+            "type, client_id, transaction_id, amount
+            deposit, 1, 1, 1.5
+            withdrawal, 1, 2, 0.5",
+            &dialect,
+        );
+
+        assert_eq!(statement.unwrap(), STATEMENT);
+    }
+
+    #[test]
+    fn reads_file_without_headers() {
+        let statement = process_statement(
+            "deposit, 1, 1, 1.5
+            withdrawal, 1, 2, 0.5",
+            &CsvDialect::new().without_headers(Vec::<String>::new()),
+        );
+
+        assert_eq!(statement.unwrap(), STATEMENT);
+    }
+
+    #[test]
+    fn reads_file_without_headers_in_given_order() {
+        let dialect = CsvDialect::new()
+            .with_alias("transaction_id", "tx")
+            .without_headers(["transaction_id", "client", "amount", "type"]);
+        let statement = process_statement(
+            "1, 1, 1.5, deposit
+            2, 1, 0.5, withdrawal",
+            &dialect,
+        );
+
+        assert_eq!(statement.unwrap(), STATEMENT);
+    }
+
+    #[test]
+    fn reads_types_regardless_of_case() {
+        let input = "type, client, tx, amount
+            Deposit, 1, 1, 1.5
+            WITHDRAWAL, 1, 2, 0.5";
+
+        assert!(process_statement(input, &CsvDialect::new()).is_err());
+        assert_eq!(
+            process_statement(input, &CsvDialect::new().with_case_insensitive_types()).unwrap(),
+            STATEMENT
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_dialects() {
+        let input = "type, client, tx, amount";
+
+        assert!(process_statement(input, &CsvDialect::new().with_quote(b',')).is_err());
+        assert!(process_statement(
+            input,
+            &CsvDialect::new().with_alias("client_id", "customer")
+        )
+        .is_err());
+    }
+}
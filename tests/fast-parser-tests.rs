@@ -0,0 +1,110 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::PaymentsEngine;
+    use std::fs;
+    use std::str;
+
+    fn process_statement(input: &[u8], serde: bool) -> Result<String, String> {
+        let mut payments_engine = PaymentsEngine::new().with_statement(None);
+        let processed = if serde {
+            payments_engine::process_csv_with_serde(input, &mut payments_engine)
+        } else {
+            payments_engine::process_csv(input, &mut payments_engine)
+        };
+        processed.map_err(|e| e.to_string())?;
+        let mut output = Vec::new();
+        payments_engine::write_statement_to_csv(&payments_engine, &mut output).unwrap();
+        Ok(str::from_utf8(&output).unwrap().to_string())
+    }
+
+    // The statement lists every transaction with the amount as parsed, in order.
+    fn assert_parsed_as_by_serde(input: &[u8]) -> Result<String, String> {
+        let fast = process_statement(input, false);
+        let serde = process_statement(input, true);
+        assert_eq!(fast, serde);
+        fast
+    }
+
+    #[test]
+    fn parses_sample_file_as_serde_does() {
+        let statement =
+            assert_parsed_as_by_serde(&fs::read("deposits-withdrawals-100k.csv").unwrap());
+
+        assert!(statement.is_ok());
+    }
+
+    #[test]
+    fn parses_amounts_as_serde_does() {
+        let statement = assert_parsed_as_by_serde(
+            b"type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 1, 2, 1.23450
+            deposit, 1, 3, 0.0001
+            deposit, 1, 4, 007.50
+            deposit, 1, 5, 10.
+            deposit, 1, 6, .5
+            deposit, 1, 7, 1e3
+            deposit, 1, 8, 123456789012345.6
+            deposit, 1, 10, 0.1234567890123456789
+            deposit,1,11,\"2.5\"
+            withdrawal, 1, 12, -1.5
+            withdrawal, 1, 13, +1.5
+            withdrawal, 1, 14, 0
+            dispute, 1, 2,
+            resolve, 1, 2",
+        );
+
+        assert_eq!(statement.unwrap().lines().count(), 16);
+    }
+
+    #[test]
+    fn parses_optional_columns_in_any_order_as_serde_does() {
+        let statement = assert_parsed_as_by_serde(
+            b"timestamp, to, amount, tx, client, type, memo
+            100, , 10.0, 1, 1, deposit, first
+            , 2, 2.5, 2, 1, transfer,
+            200, 0x2, 2.5, 3, 1, transfer, hex
+            +300, , 1, 4, 1, withdrawal, last
+            400, , 1, 5, 1, withdrawal,",
+        );
+
+        assert_eq!(statement.unwrap().lines().count(), 8);
+    }
+
+    #[test]
+    fn reports_errors_as_serde_does() {
+        for input in [
+            "type, client, tx, amount\nDeposit, 1, 1, 1.0",
+            "type, client, tx, amount\ndeposit, 70000, 1, 1.0",
+            "type, client, tx, amount\ndeposit, 1, , 1.0",
+            "type, client, tx, amount\ndeposit, 1, 1, ten",
+            "type, client, tx, amount, memo\ndeposit, 1, 1, 1.0",
+            "type, client, amount\ndeposit, 1, 1.0",
+            "type, client, tx, tx, amount\ndeposit, 1, 1, 2, 1.0",
+        ] {
+            assert!(assert_parsed_as_by_serde(input.as_bytes()).is_err());
+        }
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_amounts_out_of_range_as_serde_does() {
+        let statement = assert_parsed_as_by_serde(
+            b"type, client, tx, amount\ndeposit, 1, 1, 18446744073709551616",
+        );
+
+        assert!(statement
+            .unwrap()
+            .contains("1,1,deposit,,,rejected,amount_overflow,"));
+    }
+
+    #[cfg(feature = "decimal")]
+    #[test]
+    fn reports_amounts_out_of_range_as_serde_does() {
+        let statement = assert_parsed_as_by_serde(
+            b"type, client, tx, amount\ndeposit, 1, 1, 18446744073709551616",
+        );
+
+        assert!(statement.is_err());
+    }
+}
@@ -0,0 +1,145 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::FeeSchedule;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    const FEE_SCHEDULE: &str = r#"
+        house_account = 100
+
+        [tiers.default]
+        deposit = { flat = "0.1" }
+        withdrawal = { flat = "0.5", percentage = "1", max = "1" }
+
+        [tiers.premium]
+        clients = [2]
+        withdrawal = { percentage = "0.1", min = "0.01" }
+    "#;
+
+    fn process_transactions(input: &str) -> String {
+        let payments_engine =
+            PaymentsEngine::new().with_fee_schedule(FeeSchedule::from_toml(FEE_SCHEDULE).unwrap());
+        let mut output = Vec::new();
+        payments_engine::run_with_engine(payments_engine, input.as_bytes(), &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn charges_deposit_fee_to_house_account() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,9.9000,0.0000,9.9000,false\n"));
+        assert!(output.contains("100,0.1000,0.0000,0.1000,false\n"));
+    }
+
+    #[test]
+    fn charges_percentage_withdrawal_fee_up_to_maximum() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 100.1
+            withdrawal, 1, 2, 10.0
+            withdrawal, 1, 3, 80.0",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,8.4000,0.0000,8.4000,false\n"));
+        assert!(output.contains("100,1.7000,0.0000,1.7000,false\n"));
+    }
+
+    #[test]
+    fn charges_fees_by_client_tier() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 2, 1, 10.1
+            withdrawal, 2, 2, 5.0",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("2,4.9900,0.0000,4.9900,false\n"));
+        assert!(output.contains("100,0.1100,0.0000,0.1100,false\n"));
+    }
+
+    #[test]
+    fn ignores_withdrawal_when_funds_do_not_cover_fee() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.1
+            withdrawal, 1, 2, 9.8",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
+        assert!(output.contains("100,0.1000,0.0000,0.1000,false\n"));
+    }
+
+    #[test]
+    fn refunds_deposit_fee_on_chargeback() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.1
+            deposit, 1, 2, 5.1
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,true\n"));
+        assert!(output.contains("100,0.1000,0.0000,0.1000,false\n"));
+    }
+
+    #[test]
+    fn refunds_withdrawal_fee_on_chargeback() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.1
+            withdrawal, 1, 2, 5.0
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,true\n"));
+        assert!(output.contains("100,0.1000,0.0000,0.1000,false\n"));
+    }
+
+    #[test]
+    fn rejects_client_listed_in_several_tiers() {
+        let fee_schedule = FeeSchedule::from_toml(
+            r#"
+            house_account = 100
+
+            [tiers.gold]
+            clients = [1]
+
+            [tiers.silver]
+            clients = [1]
+            "#,
+        );
+
+        assert!(fee_schedule.is_err());
+    }
+
+    #[test]
+    fn rejects_negative_fees() {
+        let fee_schedule = FeeSchedule::from_toml(
+            r#"
+            house_account = 100
+
+            [tiers.default]
+            deposit = { flat = "-0.1" }
+            "#,
+        );
+
+        assert!(fee_schedule.is_err());
+    }
+}
@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Generator;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::TransactionType;
+    use std::str;
+
+    fn generate(generator: Generator) -> String {
+        let mut output = Vec::new();
+        generator.write_csv(&mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn generates_same_transactions_for_same_seed() {
+        let transactions = generate(Generator::new(1000, 7).with_disputes(0.1));
+
+        assert_eq!(transactions.lines().count(), 1001);
+        assert!(transactions.starts_with("type,client,tx,amount\n"));
+        assert_eq!(
+            transactions,
+            generate(Generator::new(1000, 7).with_disputes(0.1))
+        );
+        assert_ne!(
+            transactions,
+            generate(Generator::new(1000, 8).with_disputes(0.1))
+        );
+    }
+
+    #[test]
+    fn generates_transactions_the_engine_applies() {
+        let transactions = generate(
+            Generator::new(10_000, 1)
+                .with_clients(10)
+                .with_disputes(0.05)
+                .with_chargebacks(0.5),
+        );
+        let mut payments_engine = PaymentsEngine::new().with_invariant_checks();
+        payments_engine::process_csv(transactions.as_bytes(), &mut payments_engine).unwrap();
+        payments_engine.check_ledger().unwrap();
+        let summary = payments_engine.summary();
+
+        assert!(payments_engine.violations().is_empty());
+        assert!(summary.by_type[&TransactionType::Dispute].applied > 0);
+        assert!(summary.by_type[&TransactionType::Resolve].applied > 0);
+        assert!(summary.by_type[&TransactionType::Chargeback].applied > 0);
+        // All but one of the clients end up charged back and locked.
+        assert_eq!(summary.locked_accounts, 9);
+        assert!(!summary.rejections.contains_key(&Rejection::MissingAmount));
+    }
+
+    #[test]
+    fn generates_errors() {
+        let transactions = generate(Generator::new(10_000, 1).with_errors(0.1));
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(transactions.as_bytes(), &mut payments_engine).unwrap();
+        let summary = payments_engine.summary();
+
+        for rejection in [
+            Rejection::MissingAmount,
+            Rejection::NegativeAmount,
+            Rejection::UnknownTransaction,
+            Rejection::NotDisputed,
+            Rejection::InsufficientFunds,
+        ] {
+            assert!(summary.rejections.contains_key(&rejection));
+        }
+        assert!(summary.rejected > 500 && summary.rejected < 1500);
+    }
+
+    #[test]
+    fn rejects_invalid_ratios() {
+        for generator in [
+            Generator::new(10, 1).with_clients(0),
+            Generator::new(10, 1).with_disputes(1.5),
+            Generator::new(10, 1).with_chargebacks(-0.1),
+            Generator::new(10, 1).with_errors(f64::NAN),
+        ] {
+            assert!(generator.write_csv(&mut Vec::new()).is_err());
+        }
+    }
+}
@@ -0,0 +1,196 @@
+#[cfg(all(test, feature = "grpc"))]
+mod tests {
+    use payments_engine::proto;
+    use payments_engine::proto::payments_engine_client::PaymentsEngineClient;
+    use payments_engine::proto::TransactionType;
+    use payments_engine::GrpcService;
+    use payments_engine::Limits;
+    use payments_engine::PaymentsEngine;
+    use tonic::transport::Channel;
+    use tonic::Code;
+
+    async fn start() -> PaymentsEngineClient<Channel> {
+        start_with(PaymentsEngine::new()).await
+    }
+
+    async fn start_with(payments_engine: PaymentsEngine) -> PaymentsEngineClient<Channel> {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(GrpcService::new(payments_engine).serve(listener));
+        PaymentsEngineClient::connect(format!("http://{}", address))
+            .await
+            .unwrap()
+    }
+
+    fn transaction(
+        tx_type: TransactionType,
+        client: u32,
+        tx: u32,
+        amount: Option<&str>,
+    ) -> proto::Transaction {
+        proto::Transaction {
+            r#type: tx_type.into(),
+            client,
+            tx,
+            amount: amount.map(str::to_string),
+            to: None,
+            timestamp: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn submits_transactions() {
+        let mut client = start().await;
+
+        let applied = client
+            .submit(transaction(TransactionType::Deposit, 1, 1, Some("10")))
+            .await
+            .unwrap()
+            .into_inner();
+        let rejected = client
+            .submit(transaction(TransactionType::Withdrawal, 1, 2, Some("20")))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(
+            applied,
+            proto::Outcome {
+                applied: true,
+                reason: String::new()
+            }
+        );
+        assert_eq!(
+            rejected,
+            proto::Outcome {
+                applied: false,
+                reason: "insufficient_funds".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_transactions() {
+        let mut client = start().await;
+
+        for transaction in [
+            transaction(TransactionType::Unspecified, 1, 1, Some("10")),
+            transaction(TransactionType::Deposit, 70_000, 1, Some("10")),
+            transaction(TransactionType::Deposit, 1, 1, Some("ten")),
+        ] {
+            let status = client.submit(transaction).await.unwrap_err();
+            assert_eq!(status.code(), Code::InvalidArgument);
+        }
+    }
+
+    #[tokio::test]
+    async fn submits_transactions_in_bulk() {
+        let mut client = start().await;
+
+        let transactions = vec![
+            transaction(TransactionType::Deposit, 1, 1, Some("10")),
+            transaction(TransactionType::Deposit, 1, 2, Some("5.5")),
+            transaction(TransactionType::Withdrawal, 1, 3, Some("100")),
+            transaction(TransactionType::Dispute, 1, 9, None),
+            transaction(TransactionType::Unspecified, 1, 4, None),
+            transaction(TransactionType::Dispute, 1, 2, None),
+        ];
+        let bulk_outcome = client
+            .submit_bulk(tokio_stream::iter(transactions))
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(bulk_outcome.applied, 3);
+        assert_eq!(bulk_outcome.rejected, 2);
+        assert_eq!(bulk_outcome.invalid, 1);
+        assert_eq!(bulk_outcome.rejections["insufficient_funds"], 1);
+        assert_eq!(bulk_outcome.rejections["unknown_transaction"], 1);
+        let account = client
+            .get_account(proto::AccountRequest { client: 1 })
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(
+            account,
+            proto::Account {
+                client: 1,
+                available: "10.0000".to_string(),
+                held: "5.5000".to_string(),
+                total: "15.5000".to_string(),
+                locked: false,
+                credit_limit: None,
+                credit_used: None,
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn looks_up_credit_of_account_with_overdraft() {
+        let limits = Limits::from_toml("[[clients]]\nclient = 1\noverdraft = \"100\"").unwrap();
+        let mut client = start_with(PaymentsEngine::new().with_limits(limits)).await;
+        for transaction in [
+            transaction(TransactionType::Deposit, 1, 1, Some("10")),
+            transaction(TransactionType::Withdrawal, 1, 2, Some("50")),
+        ] {
+            client.submit(transaction).await.unwrap();
+        }
+
+        let account = client
+            .get_account(proto::AccountRequest { client: 1 })
+            .await
+            .unwrap()
+            .into_inner();
+
+        assert_eq!(account.available, "-40.0000");
+        assert_eq!(account.credit_limit.as_deref(), Some("100.0000"));
+        assert_eq!(account.credit_used.as_deref(), Some("40.0000"));
+    }
+
+    #[tokio::test]
+    async fn looks_up_unknown_account() {
+        let mut client = start().await;
+
+        let status = client
+            .get_account(proto::AccountRequest { client: 1 })
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), Code::NotFound);
+    }
+
+    #[tokio::test]
+    #[cfg(feature = "sqlite")]
+    async fn fails_transactions_once_saving_fails() {
+        let path =
+            std::env::temp_dir().join(format!("payments-engine-grpc-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sqlite_store = payments_engine::SqliteStore::open(&path).unwrap();
+        let mut client = start_with(PaymentsEngine::new().with_sqlite_store(sqlite_store)).await;
+
+        client
+            .submit(transaction(TransactionType::Deposit, 1, 1, Some("10")))
+            .await
+            .unwrap();
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute("DROP TABLE transactions", [])
+            .unwrap();
+        let status = client
+            .submit(transaction(TransactionType::Deposit, 1, 2, Some("10")))
+            .await
+            .unwrap_err();
+        let bulk_status = client
+            .submit_bulk(tokio_stream::iter(vec![transaction(
+                TransactionType::Deposit,
+                1,
+                3,
+                Some("10"),
+            )]))
+            .await
+            .unwrap_err();
+
+        assert_eq!(status.code(), Code::Internal);
+        assert_eq!(bulk_status.code(), Code::Internal);
+    }
+}
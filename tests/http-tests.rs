@@ -0,0 +1,181 @@
+#[cfg(all(test, feature = "http"))]
+mod tests {
+    use payments_engine::HttpServer;
+    use payments_engine::PaymentsEngine;
+    use serde_json::json;
+    use serde_json::Value;
+    use std::io::Read;
+    use std::io::Write;
+    use std::net::SocketAddr;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn start() -> SocketAddr {
+        start_with(PaymentsEngine::new())
+    }
+
+    fn start_with(payments_engine: PaymentsEngine) -> SocketAddr {
+        let server = Arc::new(HttpServer::bind("127.0.0.1:0", payments_engine).unwrap());
+        let address = server.local_addr().unwrap();
+        thread::spawn(move || server.serve());
+        address
+    }
+
+    // A bare HTTP/1.1 client, returning the status and the body.
+    fn request(address: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(address).unwrap();
+        write!(
+            stream,
+            "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+            method,
+            path,
+            body.len(),
+            body
+        )
+        .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.split(' ').nth(1).unwrap().parse().unwrap();
+        (status, body.to_string())
+    }
+
+    fn post(address: SocketAddr, transaction: Value) -> (u16, Value) {
+        let (status, body) = request(address, "POST", "/transactions", &transaction.to_string());
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    fn get(address: SocketAddr, path: &str) -> (u16, Value) {
+        let (status, body) = request(address, "GET", path, "");
+        (status, serde_json::from_str(&body).unwrap())
+    }
+
+    #[test]
+    fn posts_transactions() {
+        let address = start();
+
+        assert_eq!(
+            post(
+                address,
+                json!({ "type": "deposit", "client": 1, "tx": 1, "amount": "10" })
+            ),
+            (200, json!({ "status": "applied" }))
+        );
+        assert_eq!(
+            post(
+                address,
+                json!({ "type": "withdrawal", "client": 1, "tx": 2, "amount": "20" })
+            ),
+            (
+                422,
+                json!({ "status": "rejected", "reason": "insufficient_funds" })
+            )
+        );
+        let (status, body) = post(address, json!({ "type": "refund", "client": 1, "tx": 3 }));
+        assert_eq!(status, 400);
+        assert!(body["error"].is_string());
+    }
+
+    #[test]
+    fn takes_amounts_as_numbers_or_strings() {
+        let address = start();
+        for (tx, amount) in [(1, json!(1.5)), (2, json!(10)), (3, json!("0.25"))] {
+            let deposit = json!({ "type": "deposit", "client": 1, "tx": tx, "amount": amount });
+            assert_eq!(
+                post(address, deposit),
+                (200, json!({ "status": "applied" }))
+            );
+        }
+
+        assert_eq!(get(address, "/accounts/1").1["total"], "11.7500");
+    }
+
+    #[test]
+    fn gets_account() {
+        let address = start();
+        post(
+            address,
+            json!({ "type": "deposit", "client": 1, "tx": 1, "amount": "10.5" }),
+        );
+        post(address, json!({ "type": "dispute", "client": 1, "tx": 1 }));
+
+        assert_eq!(
+            get(address, "/accounts/1"),
+            (
+                200,
+                json!({ "client": 1, "available": "0.0000", "held": "10.5000", "total": "10.5000", "locked": false })
+            )
+        );
+        assert_eq!(get(address, "/accounts/2").0, 404);
+        assert_eq!(get(address, "/accounts/x").0, 400);
+    }
+
+    #[test]
+    fn lists_accounts_by_page() {
+        let address = start();
+        for client in 1..=5 {
+            post(
+                address,
+                json!({ "type": "deposit", "client": client, "tx": client, "amount": "1" }),
+            );
+        }
+
+        let (status, page) = get(address, "/accounts?offset=1&limit=2");
+        assert_eq!(status, 200);
+        assert_eq!(page["total"], 5);
+        let clients: Vec<&Value> = page["accounts"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|account| &account["client"])
+            .collect();
+        assert_eq!(clients, [2, 3]);
+        assert_eq!(
+            get(address, "/accounts").1["accounts"]
+                .as_array()
+                .unwrap()
+                .len(),
+            5
+        );
+        assert_eq!(get(address, "/accounts?limit=0").0, 400);
+        assert_eq!(get(address, "/accounts?page=2").0, 400);
+    }
+
+    #[test]
+    fn downloads_csv_snapshot() {
+        let address = start();
+        post(
+            address,
+            json!({ "type": "deposit", "client": 1, "tx": 1, "amount": "2.5" }),
+        );
+
+        assert_eq!(
+            request(address, "GET", "/accounts.csv", ""),
+            (
+                200,
+                "client,available,held,total,locked\n1,2.5000,0.0000,2.5000,false\n".to_string()
+            )
+        );
+        assert_eq!(request(address, "GET", "/balances", "").0, 404);
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn fails_transactions_once_saving_fails() {
+        let path =
+            std::env::temp_dir().join(format!("payments-engine-http-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sqlite_store = payments_engine::SqliteStore::open(&path).unwrap();
+        let address = start_with(PaymentsEngine::new().with_sqlite_store(sqlite_store));
+        let deposit = |tx| json!({ "type": "deposit", "client": 1, "tx": tx, "amount": "10" });
+
+        assert_eq!(post(address, deposit(1)).0, 200);
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute("DROP TABLE transactions", [])
+            .unwrap();
+        assert_eq!(post(address, deposit(2)).0, 500);
+        assert_eq!(post(address, deposit(3)).0, 500);
+    }
+}
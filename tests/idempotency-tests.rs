@@ -0,0 +1,279 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Checkpoints;
+    use payments_engine::CsvDialect;
+    use payments_engine::Generator;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::Transaction;
+    use std::io::Cursor;
+    use std::str;
+
+    fn account_states(payments_engine: &PaymentsEngine) -> String {
+        let mut output = Vec::new();
+        payments_engine::write_account_states_to_csv(payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    // The account states in client order, for engines to be compared.
+    fn sorted_account_states(payments_engine: &PaymentsEngine) -> Vec<String> {
+        let mut account_states: Vec<String> = account_states(payments_engine)
+            .lines()
+            .map(str::to_string)
+            .collect();
+        account_states.sort();
+        account_states
+    }
+
+    fn deposit(client_id: u16, id: u32, amount: &str) -> Transaction {
+        Transaction::deposit(client_id, id, amount.parse().unwrap())
+    }
+
+    #[test]
+    fn skips_overlapping_file() {
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        let first = "type,client,tx,amount
+            deposit,1,1,10
+            withdrawal,1,2,3
+            dispute,1,1,";
+        let overlapping = "type,client,tx,amount
+            withdrawal,1,2,3
+            dispute,1,1,
+            resolve,1,1,
+            deposit,1,3,1";
+
+        payments_engine::process_csv(first.as_bytes(), &mut payments_engine).unwrap();
+        payments_engine::process_csv(overlapping.as_bytes(), &mut payments_engine).unwrap();
+
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,8.0000,0.0000,8.0000,false\n"
+        );
+        let summary = payments_engine.summary();
+        assert_eq!(summary.duplicates, 2);
+        assert_eq!(summary.transactions, 5);
+        assert!(payments_engine.duplicate_conflicts().is_empty());
+    }
+
+    #[test]
+    fn flags_conflicting_duplicates() {
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        payments_engine
+            .process_transaction(deposit(1, 1, "10"))
+            .unwrap();
+
+        let other_amount = payments_engine.process_transaction(deposit(1, 1, "10.5"));
+        let other_client = payments_engine.process_transaction(deposit(2, 1, "10"));
+        let other_type = payments_engine.process_transaction(Transaction::withdrawal(
+            1,
+            1,
+            "10".parse().unwrap(),
+        ));
+
+        for result in [other_amount, other_client, other_type] {
+            assert_eq!(result, Err(Rejection::ConflictingDuplicate));
+        }
+        let conflicts: Vec<String> = payments_engine
+            .duplicate_conflicts()
+            .iter()
+            .map(ToString::to_string)
+            .collect();
+        assert_eq!(
+            conflicts,
+            [
+                "tx 1: deposit of client 1 differs from the transaction applied under its id",
+                "tx 1: deposit of client 2 differs from the transaction applied under its id",
+                "tx 1: withdrawal of client 1 differs from the transaction applied under its id",
+            ]
+        );
+        assert_eq!(
+            payments_engine.account(1).unwrap().total(),
+            "10".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn answers_rejected_transactions_sent_again_with_their_rejection() {
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        let withdrawal = Transaction::withdrawal(1, 2, "5".parse().unwrap());
+
+        assert_eq!(
+            payments_engine.process_transaction(withdrawal.clone()),
+            Err(Rejection::InsufficientFunds)
+        );
+        payments_engine
+            .process_transaction(deposit(1, 1, "10"))
+            .unwrap();
+
+        assert_eq!(
+            payments_engine.process_transaction(withdrawal),
+            Err(Rejection::InsufficientFunds)
+        );
+        assert_eq!(
+            payments_engine.account(1).unwrap().total(),
+            "10".parse().unwrap()
+        );
+        assert_eq!(payments_engine.summary().duplicates, 1);
+    }
+
+    #[test]
+    fn disputes_transaction_again_after_resolve() {
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        let input = "type,client,tx,amount
+            deposit,1,1,10
+            dispute,1,1,
+            resolve,1,1,
+            dispute,1,1,";
+
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        let held_once = account_states(&payments_engine);
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+
+        assert_eq!(
+            held_once,
+            "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n"
+        );
+        assert_eq!(account_states(&payments_engine), held_once);
+        assert_eq!(payments_engine.summary().duplicates, 4);
+    }
+
+    #[test]
+    fn disputes_transaction_again_in_overlapping_file() {
+        let first = "type,client,tx,amount
+            deposit,1,1,10
+            deposit,1,2,5
+            dispute,1,1,
+            resolve,1,1,
+";
+        let overlapping = "type,client,tx,amount
+            deposit,1,2,5
+            resolve,1,1,
+            dispute,1,1,
+            chargeback,1,1,
+";
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        for input in [first, overlapping, first, overlapping] {
+            payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        }
+
+        // As if the files were one, the dispute following the resolve charged back.
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,true\n"
+        );
+        assert_eq!(payments_engine.summary().duplicates, 2 + 4 + 4);
+    }
+
+    #[test]
+    fn gives_same_output_for_file_with_errors_sent_again() {
+        let mut transactions = Vec::new();
+        Generator::new(20_000, 7)
+            .with_clients(50)
+            .with_disputes(0.05)
+            .with_chargebacks(0.2)
+            .with_errors(0.02)
+            .write_csv(&mut transactions)
+            .unwrap();
+        let prefix_len = transactions.len() / 2;
+        let prefix = &transactions[..prefix_len];
+        let prefix = &prefix[..=prefix.iter().rposition(|byte| *byte == b'\n').unwrap()];
+        let mut once = PaymentsEngine::new();
+        payments_engine::process_csv(transactions.as_slice(), &mut once).unwrap();
+
+        let mut twice = PaymentsEngine::new().with_idempotency();
+        payments_engine::process_csv(transactions.as_slice(), &mut twice).unwrap();
+        payments_engine::process_csv(transactions.as_slice(), &mut twice).unwrap();
+        let mut after_prefix = PaymentsEngine::new().with_idempotency();
+        payments_engine::process_csv(prefix, &mut after_prefix).unwrap();
+        payments_engine::process_csv(transactions.as_slice(), &mut after_prefix).unwrap();
+
+        assert!(once.summary().rejected > 0);
+        assert_eq!(sorted_account_states(&twice), sorted_account_states(&once));
+        assert_eq!(twice.summary().duplicates, 20_000);
+        assert_eq!(
+            sorted_account_states(&after_prefix),
+            sorted_account_states(&once)
+        );
+        assert!(twice.duplicate_conflicts().is_empty());
+    }
+
+    #[test]
+    fn keeps_applied_transactions_in_checkpoint() {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-idempotency-{}.checkpoint",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let input = "type,client,tx,amount\ndeposit,1,1,10\n";
+        let checkpoints = Checkpoints::new(&path, 1).with_resume();
+        let mut first_run = PaymentsEngine::new().with_idempotency();
+        payments_engine::process_csv_with_checkpoints(
+            Cursor::new(input),
+            &mut first_run,
+            &CsvDialect::new(),
+            &checkpoints,
+            None,
+        )
+        .unwrap();
+
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        let resent = "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,1,10\ndeposit,1,2,1\n";
+        payments_engine::process_csv_with_checkpoints(
+            Cursor::new(resent),
+            &mut payments_engine,
+            &CsvDialect::new(),
+            &Checkpoints::new(&path, 1).with_resume(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            payments_engine.account(1).unwrap().total(),
+            "11".parse().unwrap()
+        );
+        assert_eq!(payments_engine.summary().duplicates, 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn keeps_rejected_transactions_and_dispute_progress_in_checkpoint() {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-idempotency-rejected-{}.checkpoint",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let input = "type,client,tx,amount
+withdrawal,1,1,5
+deposit,1,2,10
+dispute,1,2,
+resolve,1,2,
+";
+        let mut first_run = PaymentsEngine::new().with_idempotency();
+        payments_engine::process_csv_with_checkpoints(
+            Cursor::new(input),
+            &mut first_run,
+            &CsvDialect::new(),
+            &Checkpoints::new(&path, 1).with_resume(),
+            None,
+        )
+        .unwrap();
+
+        let mut payments_engine = PaymentsEngine::new().with_idempotency();
+        let continued = format!("{}withdrawal,1,1,5\ndispute,1,2,\n", input);
+        payments_engine::process_csv_with_checkpoints(
+            Cursor::new(continued),
+            &mut payments_engine,
+            &CsvDialect::new(),
+            &Checkpoints::new(&path, 1).with_resume(),
+            None,
+        )
+        .unwrap();
+
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n"
+        );
+        assert_eq!(payments_engine.summary().duplicates, 1);
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,152 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::FeeSchedule;
+    use payments_engine::Invariant;
+    use payments_engine::LockedAccounts;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::Transaction;
+    use payments_engine::Violation;
+
+    const FEE_SCHEDULE: &str = r#"
+        house_account = 100
+
+        [tiers.default]
+        deposit = { flat = "0.1" }
+        withdrawal = { flat = "0.5" }
+    "#;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn engine() -> PaymentsEngine {
+        PaymentsEngine::new()
+            .with_fee_schedule(FeeSchedule::from_toml(FEE_SCHEDULE).unwrap())
+            .with_invariant_checks()
+    }
+
+    #[test]
+    fn holds_invariants_through_disputes_of_deposits_and_withdrawals() {
+        let mut engine = engine();
+
+        let transactions = [
+            Transaction::deposit(1, 1, amount("100")),
+            Transaction::deposit(1, 2, amount("20")),
+            Transaction::withdrawal(1, 3, amount("30")),
+            Transaction::dispute(1, 2),
+            Transaction::dispute(1, 3),
+            Transaction::resolve(1, 2),
+            Transaction::transfer(1, 4, amount("10"), 2),
+            Transaction::withdrawal(2, 5, amount("5")),
+            Transaction::dispute(2, 5),
+            Transaction::chargeback(1, 3),
+        ];
+        for transaction in transactions {
+            engine.process_transaction(transaction).unwrap();
+        }
+
+        assert_eq!(engine.violations(), []);
+        assert_eq!(engine.check_invariants(), []);
+        assert_eq!(engine.account(2).unwrap().held(), amount("-5.5"));
+    }
+
+    #[test]
+    fn holds_invariants_at_end_of_run_without_per_transaction_checks() {
+        let mut engine = PaymentsEngine::new();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("10")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .unwrap();
+
+        assert_eq!(engine.check_invariants(), []);
+        assert_eq!(engine.violations(), []);
+    }
+
+    #[test]
+    fn rejects_dispute_of_already_disputed_transaction() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("10")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .unwrap();
+        let result = engine.process_transaction(Transaction::dispute(1, 1));
+
+        assert_eq!(result, Err(Rejection::AlreadyDisputed));
+        assert_eq!(engine.account(1).unwrap().held(), amount("9.9"));
+        assert_eq!(engine.violations(), []);
+    }
+
+    #[test]
+    fn rejects_dispute_of_charged_back_transaction_on_locked_account() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("10")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::deposit(1, 2, amount("10")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::dispute(1, 1))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::chargeback(1, 1))
+            .unwrap();
+        let result = engine.process_transaction(Transaction::dispute(1, 1));
+
+        assert_eq!(result, Err(Rejection::AccountLocked));
+        assert_eq!(engine.account(1).unwrap().total(), amount("9.9"));
+        assert_eq!(engine.violations(), []);
+    }
+
+    #[test]
+    fn settles_disputes_opened_before_account_was_locked() {
+        let input = "type,client,tx,amount
+            deposit,1,1,10
+            deposit,1,2,5
+            dispute,1,1,
+            dispute,1,2,
+            chargeback,1,1,
+            resolve,1,2,";
+        for locked_accounts in [LockedAccounts::Reject, LockedAccounts::AllowDisputes] {
+            let mut engine = PaymentsEngine::new()
+                .with_locked_accounts(locked_accounts)
+                .with_invariant_checks();
+
+            payments_engine::process_csv(input.as_bytes(), &mut engine).unwrap();
+
+            let mut output = Vec::new();
+            payments_engine::write_account_states_to_csv(&engine, &mut output).unwrap();
+            assert_eq!(
+                String::from_utf8(output).unwrap(),
+                "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,true\n"
+            );
+            assert_eq!(engine.violations(), []);
+        }
+    }
+
+    #[test]
+    fn describes_violation_with_causing_transaction() {
+        let violation = Violation {
+            client_id: 1,
+            transaction_id: Some(7),
+            invariant: Invariant::HeldMatchesDisputes,
+        };
+
+        assert_eq!(
+            violation.to_string(),
+            "client 1: held funds do not match the disputed amounts (tx 7)"
+        );
+    }
+}
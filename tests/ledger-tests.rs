@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::FeeSchedule;
+    use payments_engine::LedgerAccount;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    fn process_transactions(payments_engine: &mut PaymentsEngine, input: &str) -> String {
+        payments_engine::process_csv(input.as_bytes(), payments_engine).unwrap();
+        payments_engine.check_ledger().unwrap();
+        let mut output = Vec::new();
+        payments_engine::write_trial_balance_to_csv(payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn settles_deposits_and_withdrawals_externally() {
+        let output = process_transactions(
+            &mut PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 4",
+        );
+
+        assert_eq!(
+            output,
+            "account,balance
+client 1 available,6.0000
+client 1 held,0.0000
+client 1 disputed withdrawals,0.0000
+external settlement,-6.0000
+chargeback loss,0.0000
+total,0.0000
+"
+        );
+    }
+
+    #[test]
+    fn posts_disputed_deposit_chargeback_back_to_settlement() {
+        let output = process_transactions(
+            &mut PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 1, 2, 5.0
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert!(output.contains("client 1 available,10.0000\n"));
+        assert!(output.contains("client 1 held,0.0000\n"));
+        assert!(output.contains("external settlement,-10.0000\n"));
+        assert!(output.contains("chargeback loss,0.0000\n"));
+    }
+
+    #[test]
+    fn holds_disputed_withdrawal_outside_of_total() {
+        let mut payments_engine = PaymentsEngine::new();
+        let output = process_transactions(
+            &mut payments_engine,
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 4.0
+            dispute, 1, 2,",
+        );
+
+        assert!(output.contains("client 1 available,6.0000\n"));
+        assert!(output.contains("client 1 held,-4.0000\n"));
+        assert!(output.contains("client 1 disputed withdrawals,4.0000\n"));
+        assert_eq!(
+            payments_engine.account(1).unwrap().total(),
+            "6".parse().unwrap()
+        );
+    }
+
+    #[test]
+    fn posts_disputed_withdrawal_chargeback_as_loss() {
+        let output = process_transactions(
+            &mut PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 4.0
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert!(output.contains("client 1 available,10.0000\n"));
+        assert!(output.contains("client 1 held,0.0000\n"));
+        assert!(output.contains("client 1 disputed withdrawals,0.0000\n"));
+        assert!(output.contains("external settlement,-6.0000\n"));
+        assert!(output.contains("chargeback loss,-4.0000\n"));
+    }
+
+    #[test]
+    fn posts_fees_between_client_and_house_accounts() {
+        let mut payments_engine = PaymentsEngine::new().with_fee_schedule(
+            FeeSchedule::from_toml(
+                r#"
+                house_account = 100
+
+                [tiers.default]
+                withdrawal = { flat = "1" }
+                "#,
+            )
+            .unwrap(),
+        );
+        let output = process_transactions(
+            &mut payments_engine,
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 4.0
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert!(output.contains("client 1 available,10.0000\n"));
+        assert!(output.contains("client 100 available,0.0000\n"));
+        assert!(output.contains("chargeback loss,-4.0000\n"));
+        assert!(output.ends_with("total,0.0000\n"));
+    }
+
+    #[test]
+    fn lists_every_ledger_account_in_trial_balance() {
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(
+            "type, client, tx, amount
+            deposit, 2, 1, 1.0
+            deposit, 1, 2, 1"
+                .as_bytes(),
+            &mut payments_engine,
+        )
+        .unwrap();
+
+        let accounts: Vec<LedgerAccount> = payments_engine
+            .trial_balance()
+            .into_iter()
+            .map(|(ledger_account, _)| ledger_account)
+            .collect();
+
+        assert_eq!(
+            accounts,
+            vec![
+                LedgerAccount::ClientAvailable(1),
+                LedgerAccount::ClientHeld(1),
+                LedgerAccount::ClientDisputedWithdrawals(1),
+                LedgerAccount::ClientAvailable(2),
+                LedgerAccount::ClientHeld(2),
+                LedgerAccount::ClientDisputedWithdrawals(2),
+                LedgerAccount::ExternalSettlement,
+                LedgerAccount::ChargebackLoss,
+            ]
+        );
+    }
+}
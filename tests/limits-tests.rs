@@ -0,0 +1,273 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::Limits;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::Transaction;
+
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    const LIMITS: &str = r#"
+        [default]
+        max_withdrawal = "100"
+        max_daily_withdrawal = "150"
+        max_balance = "1000"
+        max_transactions = 5
+        transaction_period = 3600
+
+        [[clients]]
+        client = 2
+        max_withdrawal = "500"
+        max_daily_withdrawal = "500"
+    "#;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn engine() -> PaymentsEngine {
+        PaymentsEngine::new().with_limits(Limits::from_toml(LIMITS).unwrap())
+    }
+
+    #[test]
+    fn rejects_withdrawal_above_single_withdrawal_limit() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 2, amount("100.0001"))),
+            Err(Rejection::WithdrawalLimitExceeded)
+        );
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 3, amount("100"))),
+            Ok(())
+        );
+        assert_eq!(engine.account(1).unwrap().available(), amount("400"));
+    }
+
+    #[test]
+    fn rejects_withdrawals_above_rolling_daily_limit() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")).at(0))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(1, 2, amount("100")).at(HOUR))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 3, amount("60")).at(DAY)),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 4, amount("60")).at(DAY + HOUR)),
+            Ok(())
+        );
+        assert_eq!(engine.account(1).unwrap().available(), amount("340"));
+    }
+
+    #[test]
+    fn keeps_daily_total_as_withdrawals_leave_window() {
+        let mut engine = engine();
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")).at(0))
+            .unwrap();
+        for (id, withdrawn, at) in [
+            (2, "100", HOUR),
+            (3, "50", 2 * HOUR),
+            (4, "100", DAY + HOUR),
+        ] {
+            engine
+                .process_transaction(Transaction::withdrawal(1, id, amount(withdrawn)).at(at))
+                .unwrap();
+        }
+
+        assert_eq!(
+            engine.process_transaction(
+                Transaction::withdrawal(1, 5, amount("0.0001")).at(DAY + HOUR + 1)
+            ),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+        assert_eq!(
+            engine.process_transaction(
+                Transaction::withdrawal(1, 6, amount("50")).at(DAY + 2 * HOUR)
+            ),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn treats_transactions_without_timestamp_as_happening_at_latest_timestamp() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(1, 2, amount("100")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 3, amount("60"))),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn rejects_deposit_above_maximum_balance() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("900")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::deposit(1, 2, amount("100.0001"))),
+            Err(Rejection::BalanceLimitExceeded)
+        );
+        assert_eq!(engine.account(1).unwrap().total(), amount("900"));
+    }
+
+    #[test]
+    fn rejects_transfer_above_maximum_balance_of_destination() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::deposit(2, 2, amount("900")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::transfer(1, 3, amount("101"), 2)),
+            Err(Rejection::BalanceLimitExceeded)
+        );
+        assert_eq!(engine.account(1).unwrap().total(), amount("500"));
+        assert_eq!(engine.account(2).unwrap().total(), amount("900"));
+    }
+
+    #[test]
+    fn holds_transfers_to_withdrawal_limits_of_source() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::transfer(1, 2, amount("101"), 3)),
+            Err(Rejection::WithdrawalLimitExceeded)
+        );
+        engine
+            .process_transaction(Transaction::transfer(1, 3, amount("100"), 3))
+            .unwrap();
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 4, amount("51"))),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+        assert_eq!(engine.account(1).unwrap().total(), amount("400"));
+    }
+
+    #[test]
+    fn rejects_transactions_above_count_per_period() {
+        let mut engine = engine();
+
+        for id in 1..=5 {
+            engine
+                .process_transaction(Transaction::deposit(1, id, amount("1")).at(0))
+                .unwrap();
+        }
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 6, amount("1")).at(HOUR - 1)),
+            Err(Rejection::TransactionLimitExceeded)
+        );
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 7, amount("1")).at(HOUR)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn does_not_count_rejected_transactions_towards_limits() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("100")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 2, amount("100.01"))),
+            Err(Rejection::WithdrawalLimitExceeded)
+        );
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 3, amount("100"))),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn applies_per_client_overrides() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(2, 1, amount("1000")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(2, 2, amount("400"))),
+            Ok(())
+        );
+        assert_eq!(
+            engine.process_transaction(Transaction::deposit(2, 3, amount("401"))),
+            Err(Rejection::BalanceLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn keeps_windows_at_latest_possible_timestamp() {
+        let mut engine = engine();
+
+        engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")).at(u64::MAX))
+            .unwrap();
+        engine
+            .process_transaction(Transaction::withdrawal(1, 2, amount("100")))
+            .unwrap();
+
+        assert_eq!(
+            engine.process_transaction(Transaction::withdrawal(1, 3, amount("100"))),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn rejects_unknown_limits() {
+        for limits in [
+            "[default]\nmax_withdrawl = \"5000\"",
+            "[[clients]]\nclient = 1\nmax_withdrawl = \"5000\"",
+        ] {
+            let e = Limits::from_toml(limits).err().unwrap().to_string();
+            assert!(e.contains("unknown field `max_withdrawl`"), "{}", e);
+        }
+    }
+
+    #[test]
+    fn rejects_negative_limits() {
+        let limits = Limits::from_toml(
+            r#"
+            [default]
+            max_balance = "-1"
+            "#,
+        );
+
+        assert!(limits.is_err());
+    }
+}
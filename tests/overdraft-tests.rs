@@ -0,0 +1,104 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Limits;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    const LIMITS: &str = r#"
+        [[clients]]
+        client = 1
+        overdraft = "100"
+    "#;
+
+    fn process_transactions(input: &str) -> String {
+        let payments_engine = PaymentsEngine::new().with_limits(Limits::from_toml(LIMITS).unwrap());
+        let mut output = Vec::new();
+        payments_engine::run_with_engine(payments_engine, input.as_bytes(), &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn withdraws_into_overdraft() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 50.0",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("client,available,held,total,locked,credit_limit,credit_used\n"));
+        assert!(output.contains("1,-40.0000,0.0000,-40.0000,false,100.0000,40.0000\n"));
+    }
+
+    #[test]
+    fn ignores_withdrawal_beyond_credit_limit() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 110.0
+            withdrawal, 1, 3, 110.0001",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("1,-100.0000,0.0000,-100.0000,false,100.0000,100.0000\n"));
+    }
+
+    #[test]
+    fn does_not_allow_overdraft_without_credit_line() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 10.0
+            withdrawal, 2, 3, 10.1",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("2,10.0000,0.0000,10.0000,false,0.0000,0.0000\n"));
+    }
+
+    #[test]
+    fn disputes_deposit_beyond_credit_limit() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 50.0
+            withdrawal, 1, 2, 120.0
+            dispute, 1, 1,
+            withdrawal, 1, 3, 1.0",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("1,-120.0000,50.0000,-70.0000,false,100.0000,120.0000\n"));
+    }
+
+    #[test]
+    fn chargeback_of_disputed_withdrawal_repays_used_credit() {
+        let output = process_transactions(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 50.0
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("1,10.0000,0.0000,10.0000,true,100.0000,0.0000\n"));
+    }
+
+    #[test]
+    fn omits_credit_columns_without_any_credit_lines() {
+        let mut output = Vec::new();
+        payments_engine::run_with_engine(
+            PaymentsEngine::new().with_limits(Limits::from_toml("").unwrap()),
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0"
+                .as_bytes(),
+            &mut output,
+        )
+        .unwrap();
+
+        assert_eq!(
+            str::from_utf8(&output).unwrap(),
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n"
+        );
+    }
+}
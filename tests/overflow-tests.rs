@@ -0,0 +1,151 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn statement(payments_engine: PaymentsEngine, input: &str) -> String {
+        let mut payments_engine = payments_engine.with_invariant_checks().with_statement(None);
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        payments_engine.check_ledger().unwrap();
+        assert!(payments_engine.violations().is_empty());
+        let mut output = Vec::new();
+        payments_engine::write_statement_to_csv(&payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn rejects_amounts_over_maximum() {
+        let output = statement(
+            PaymentsEngine::new().with_max_amount(amount("1000")),
+            "type, client, tx, amount, to
+            deposit, 1, 1, 1000
+            deposit, 1, 2, 1000.0001
+            withdrawal, 1, 3, 1500
+            transfer, 1, 4, 2000, 2
+            dispute, 1, 1,",
+        );
+
+        assert_eq!(
+            output,
+            "client,tx,type,amount,fee,status,reason,available,held,total,locked
+1,1,deposit,1000.0000,,applied,,1000.0000,0.0000,1000.0000,false
+1,2,deposit,1000.0001,,rejected,amount_too_large,1000.0000,0.0000,1000.0000,false
+1,3,withdrawal,1500.0000,,rejected,amount_too_large,1000.0000,0.0000,1000.0000,false
+1,4,transfer,2000.0000,,rejected,amount_too_large,1000.0000,0.0000,1000.0000,false
+1,1,dispute,,,applied,,0.0000,1000.0000,1000.0000,false
+"
+        );
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_deposit_overflowing_balance() {
+        let output = statement(
+            PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 500000000000000
+            withdrawal, 1, 2, 500000000000000
+            deposit, 1, 3, 500000000000000
+            deposit, 1, 4, 500000000000000
+            deposit, 1, 5, 1",
+        );
+
+        assert!(output.contains("1,4,deposit,500000000000000.0000,,rejected,amount_overflow,"));
+        assert!(output.contains(
+            "1,5,deposit,1.0000,,applied,,500000000000001.0000,0.0000,500000000000001.0000,false\n"
+        ));
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_deposits_overflowing_settlement_account() {
+        let output = statement(
+            PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 500000000000000
+            deposit, 2, 2, 500000000000000",
+        );
+
+        assert!(output.contains(
+            "2,2,deposit,500000000000000.0000,,rejected,amount_overflow,0.0000,0.0000,0.0000,false\n"
+        ));
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_withdrawal_with_fee_out_of_range() {
+        let fee_schedule = payments_engine::FeeSchedule::from_toml(
+            r#"
+            house_account = 100
+
+            [tiers.default]
+            withdrawal = { percentage = "200" }
+            "#,
+        )
+        .unwrap();
+        let output = statement(
+            PaymentsEngine::new().with_fee_schedule(fee_schedule),
+            "type, client, tx, amount
+            deposit, 1, 1, 500000000000000
+            withdrawal, 1, 2, 500000000000000",
+        );
+
+        assert!(output.contains("1,2,withdrawal,500000000000000.0000,,rejected,amount_overflow,"));
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_amounts_out_of_range() {
+        let output = statement(
+            PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 1000000000000000
+            deposit, 1, 2, 1.5",
+        );
+
+        assert_eq!(
+            output,
+            "client,tx,type,amount,fee,status,reason,available,held,total,locked
+1,1,deposit,,,rejected,amount_overflow,,,,
+1,2,deposit,1.5000,,applied,,1.5000,0.0000,1.5000,false
+"
+        );
+    }
+
+    #[cfg(not(feature = "decimal"))]
+    #[test]
+    fn rejects_amounts_with_more_than_four_decimal_places() {
+        let output = statement(
+            PaymentsEngine::new(),
+            "type, client, tx, amount
+            deposit, 1, 1, 1.5
+            withdrawal, 1, 2, 0.00001
+            deposit, 1, 3, 1.23456e-1",
+        );
+
+        assert_eq!(
+            output,
+            "client,tx,type,amount,fee,status,reason,available,held,total,locked
+1,1,deposit,1.5000,,applied,,1.5000,0.0000,1.5000,false
+1,2,withdrawal,,,rejected,amount_too_precise,1.5000,0.0000,1.5000,false
+1,3,deposit,,,rejected,amount_too_precise,1.5000,0.0000,1.5000,false
+"
+        );
+    }
+
+    #[test]
+    fn reports_invalid_amount_as_error() {
+        let mut payments_engine = PaymentsEngine::new();
+        let processed = payments_engine::process_csv(
+            "type, client, tx, amount\ndeposit, 1, 1, 1.5.0".as_bytes(),
+            &mut payments_engine,
+        );
+
+        assert!(processed.is_err());
+    }
+}
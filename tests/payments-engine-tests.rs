@@ -29,8 +29,8 @@ mod tests {
 
         assert_eq!(output.lines().count(), 3);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("2,2,0.0000,2,false\n"));
-        assert!(output.contains("1,1.5,0.0000,1.5,false\n"));
+        assert!(output.contains("2,2.0000,0.0000,2.0000,false\n"));
+        assert!(output.contains("1,1.5000,0.0000,1.5000,false\n"));
     }
 
     #[test]
@@ -44,8 +44,8 @@ mod tests {
 
         assert_eq!(output.lines().count(), 3);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("2,2,0.0000,2,false\n"));
-        assert!(output.contains("1,3,0.0000,3,false\n"));
+        assert!(output.contains("2,2.0000,0.0000,2.0000,false\n"));
+        assert!(output.contains("1,3.0000,0.0000,3.0000,false\n"));
     }
 
     #[test]
@@ -59,7 +59,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,3,0.0000,3,false\n"));
+        assert!(output.contains("1,3.0000,0.0000,3.0000,false\n"));
     }
 
     #[test]
@@ -73,7 +73,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,0,0.0000,0,false\n"));
+        assert!(output.contains("1,0.0000,0.0000,0.0000,false\n"));
     }
 
     #[test]
@@ -87,7 +87,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,2,0.0000,2,false\n"));
+        assert!(output.contains("1,2.0000,0.0000,2.0000,false\n"));
     }
 
     #[test]
@@ -101,7 +101,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,7,0.0000,7,false\n"));
+        assert!(output.contains("1,7.0000,0.0000,7.0000,false\n"));
     }
 
     #[test]
@@ -127,7 +127,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,1,0.0000,1,false\n"));
+        assert!(output.contains("1,1.0000,0.0000,1.0000,false\n"));
     }
 
     #[test]
@@ -140,7 +140,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,1,0.0000,1,false\n"));
+        assert!(output.contains("1,1.0000,0.0000,1.0000,false\n"));
     }
 
     #[test]
@@ -153,7 +153,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,5,0.0000,5,false\n"));
+        assert!(output.contains("1,5.0000,0.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -166,7 +166,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,0,10,10,false\n"));
+        assert!(output.contains("1,0.0000,10.0000,10.0000,false\n"));
     }
 
     #[test]
@@ -179,7 +179,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,0,10,10,false\n"));
+        assert!(output.contains("1,0.0000,10.0000,10.0000,false\n"));
     }
 
     #[test]
@@ -193,7 +193,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,5,-5,5,false\n"));
+        assert!(output.contains("1,5.0000,-5.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -208,7 +208,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,5,-5,5,false\n"));
+        assert!(output.contains("1,5.0000,-5.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -223,7 +223,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,12,5,17,false\n"));
+        assert!(output.contains("1,12.0000,5.0000,17.0000,false\n"));
     }
 
     #[test]
@@ -237,7 +237,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,-5,10,5,false\n"));
+        assert!(output.contains("1,-5.0000,10.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -252,7 +252,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,-5,10,5,false\n"));
+        assert!(output.contains("1,-5.0000,10.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -265,7 +265,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,10,0.0000,10,false\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
     }
 
     #[test]
@@ -282,7 +282,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,17.0001,0,17.0001,false\n"));
+        assert!(output.contains("1,17.0001,0.0000,17.0001,false\n"));
     }
 
     #[test]
@@ -297,7 +297,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,5,0,5,false\n"));
+        assert!(output.contains("1,5.0000,0.0000,5.0000,false\n"));
     }
 
     #[test]
@@ -330,7 +330,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,12.0001,5,17.0001,false\n"));
+        assert!(output.contains("1,12.0001,5.0000,17.0001,false\n"));
     }
 
     #[test]
@@ -347,7 +347,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,12.0001,0,12.0001,true\n"));
+        assert!(output.contains("1,12.0001,0.0000,12.0001,true\n"));
     }
 
     #[test]
@@ -363,7 +363,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,5,0,5,true\n"));
+        assert!(output.contains("1,5.0000,0.0000,5.0000,true\n"));
     }
 
     #[test]
@@ -396,7 +396,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,12.0001,5,17.0001,false\n"));
+        assert!(output.contains("1,12.0001,5.0000,17.0001,false\n"));
     }
 
     #[test]
@@ -411,7 +411,7 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,0,0,0,true\n"));
+        assert!(output.contains("1,0.0000,0.0000,0.0000,true\n"));
     }
 
     #[test]
@@ -427,6 +427,137 @@ mod tests {
 
         assert_eq!(output.lines().count(), 2);
         assert!(output.contains("client,available,held,total,locked\n"));
-        assert!(output.contains("1,1,0,1,true\n"));
+        assert!(output.contains("1,1.0000,0.0000,1.0000,true\n"));
+    }
+
+    #[test]
+    fn transfers_funds_between_clients() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 1.0
+            transfer, 1, 3, 4.0, 2",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,6.0000,0.0000,6.0000,false\n"));
+        assert!(output.contains("2,5.0000,0.0000,5.0000,false\n"));
+    }
+
+    #[test]
+    fn transfers_funds_to_new_client() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            transfer, 1, 2, 10.0, 2",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,0.0000,0.0000,0.0000,false\n"));
+        assert!(output.contains("2,10.0000,0.0000,10.0000,false\n"));
+    }
+
+    #[test]
+    fn ignores_transfer_when_not_enough_funds_available() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            transfer, 1, 2, 10.1, 2",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
+    }
+
+    #[test]
+    fn does_not_open_destination_account_of_rejected_transfer() {
+        let mut payments_engine = payments_engine::PaymentsEngine::new();
+        let input = "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            transfer, 1, 2, 10.1, 2";
+
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+
+        assert!(payments_engine.account(2).is_none());
+    }
+
+    #[test]
+    fn ignores_transfer_without_destination() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            transfer, 1, 2, 5.0",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
+    }
+
+    #[test]
+    fn ignores_transfer_of_negative_amount() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 10.0
+            transfer, 1, 3, -5.0, 2",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
+        assert!(output.contains("2,10.0000,0.0000,10.0000,false\n"));
+    }
+
+    #[test]
+    fn ignores_transfer_from_locked_account() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            deposit, 1, 2, 5.0
+            dispute, 1, 1,
+            chargeback, 1, 1,
+            transfer, 1, 3, 5.0, 2",
+        );
+
+        assert_eq!(output.lines().count(), 2);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,5.0000,0.0000,5.0000,true\n"));
+    }
+
+    #[test]
+    fn ignores_transfer_to_locked_account() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 5.0
+            dispute, 2, 2,
+            chargeback, 2, 2,
+            transfer, 1, 3, 5.0, 2",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,10.0000,0.0000,10.0000,false\n"));
+        assert!(output.contains("2,0.0000,0.0000,0.0000,true\n"));
+    }
+
+    #[test]
+    fn ignores_dispute_of_transfer() {
+        let output = process_transactions(
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0
+            transfer, 1, 2, 4.0, 2
+            dispute, 1, 2,
+            dispute, 2, 2,",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("client,available,held,total,locked\n"));
+        assert!(output.contains("1,6.0000,0.0000,6.0000,false\n"));
+        assert!(output.contains("2,4.0000,0.0000,4.0000,false\n"));
     }
 }
@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Progress;
+    use payments_engine::ProgressReporter;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    fn process_with_progress(input: &str, interval: Duration) -> Vec<Progress> {
+        let reports = Rc::new(RefCell::new(Vec::new()));
+        let reported = Rc::clone(&reports);
+        let mut progress = ProgressReporter::new(interval, move |progress: &Progress| {
+            reported.borrow_mut().push(*progress)
+        })
+        .with_total_bytes(input.len() as u64);
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv_with_progress(
+            input.as_bytes(),
+            &mut payments_engine,
+            &mut progress,
+        )
+        .unwrap();
+        let reports = reports.borrow().clone();
+        reports
+    }
+
+    #[test]
+    fn reports_final_progress() {
+        let input = "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 20.0";
+
+        let reports = process_with_progress(input, Duration::from_secs(3600));
+
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].rows, 2);
+        assert_eq!(reports[0].bytes, input.len() as u64);
+        assert_eq!(reports[0].total_bytes, Some(input.len() as u64));
+        assert_eq!(reports[0].eta(), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn reports_progress_along_the_way() {
+        let mut input = String::from("type,client,tx,amount\n");
+        for id in 1..=5000 {
+            input.push_str(&format!("deposit,1,{},1.0\n", id));
+        }
+
+        let reports = process_with_progress(&input, Duration::ZERO);
+
+        assert_eq!(reports.len(), 5);
+        assert_eq!(reports[0].rows, 1024);
+        assert!(reports[0].bytes < reports[1].bytes);
+        assert_eq!(reports[4].rows, 5000);
+    }
+
+    #[test]
+    fn estimates_time_left_by_bytes_read() {
+        let progress = Progress {
+            rows: 100,
+            bytes: 250,
+            total_bytes: Some(1000),
+            elapsed: Duration::from_secs(10),
+        };
+
+        assert_eq!(progress.eta(), Some(Duration::from_secs(30)));
+        assert_eq!(progress.rows_per_second(), 10.0);
+        assert_eq!(
+            progress.to_string(),
+            "100 rows, 0.0 MB of 0.0 MB (25.0%), 10 rows/s, ETA 30s"
+        );
+    }
+}
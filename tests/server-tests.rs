@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Server;
+    use std::io::BufRead;
+    use std::io::BufReader;
+    use std::io::Write;
+    use std::net::SocketAddr;
+    use std::net::TcpListener;
+    use std::net::TcpStream;
+    use std::sync::Arc;
+    use std::thread;
+
+    fn start(payments_engine: PaymentsEngine) -> (Arc<Server>, SocketAddr) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let server = Arc::new(Server::new(payments_engine));
+        let serving = Arc::clone(&server);
+        thread::spawn(move || serving.serve(listener));
+        (server, address)
+    }
+
+    // Sends the lines one by one, returning the response to each.
+    fn send(address: SocketAddr, lines: &[&str]) -> Vec<String> {
+        let mut stream = TcpStream::connect(address).unwrap();
+        stream.set_nodelay(true).unwrap();
+        let mut responses = BufReader::new(stream.try_clone().unwrap()).lines();
+        lines
+            .iter()
+            .map(|line| {
+                writeln!(stream, "{}", line).unwrap();
+                responses.next().unwrap().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn acknowledges_transactions_with_outcome() {
+        let (_, address) = start(PaymentsEngine::new());
+
+        let responses = send(
+            address,
+            &[
+                "deposit, 1, 1, 10",
+                "withdrawal, 1, 2, 20",
+                "dispute, 1, 1",
+                "transfer, 1, 3, 1, 2",
+            ],
+        );
+
+        assert_eq!(
+            responses,
+            [
+                "applied",
+                "rejected insufficient_funds",
+                "applied",
+                "rejected insufficient_funds"
+            ]
+        );
+    }
+
+    #[test]
+    fn answers_balance_queries() {
+        let (_, address) = start(PaymentsEngine::new());
+
+        let responses = send(
+            address,
+            &[
+                "deposit,1,1,10.5",
+                "withdrawal,1,2,0.5",
+                "balance 1",
+                "balance 2",
+            ],
+        );
+
+        assert_eq!(responses[2], "balance 1,10.0000,0.0000,10.0000,false");
+        assert_eq!(responses[3], "error unknown client 2");
+    }
+
+    #[test]
+    fn reports_invalid_lines() {
+        let (_, address) = start(PaymentsEngine::new());
+
+        let responses = send(
+            address,
+            &[
+                "refund, 1, 1, 10",
+                "deposit, 1",
+                "balance x",
+                "deposit, 1, 1, 1",
+            ],
+        );
+
+        assert!(responses[..3]
+            .iter()
+            .all(|response| response.starts_with("error ")));
+        assert_eq!(responses[3], "applied");
+    }
+
+    #[test]
+    fn applies_transactions_of_concurrent_connections() {
+        let (server, address) = start(PaymentsEngine::new());
+
+        let connections: Vec<_> = (0..8u32)
+            .map(|connection| {
+                thread::spawn(move || {
+                    let lines: Vec<String> = (0..100u32)
+                        .map(|i| format!("deposit, 1, {}, 1", connection * 100 + i + 1))
+                        .collect();
+                    let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+                    send(address, &lines)
+                })
+            })
+            .collect();
+        for connection in connections {
+            assert!(connection
+                .join()
+                .unwrap()
+                .iter()
+                .all(|response| response == "applied"));
+        }
+
+        assert_eq!(
+            send(address, &["balance 1"]),
+            ["balance 1,800.0000,0.0000,800.0000,false"]
+        );
+        let payments_engine = server.payments_engine();
+        let payments_engine = payments_engine.lock().unwrap();
+        assert_eq!(payments_engine.summary().applied, 800);
+    }
+
+    #[test]
+    #[cfg(feature = "sqlite")]
+    fn fails_transactions_once_saving_fails() {
+        let path =
+            std::env::temp_dir().join(format!("payments-engine-server-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let sqlite_store = payments_engine::SqliteStore::open(&path).unwrap();
+        let (_, address) = start(PaymentsEngine::new().with_sqlite_store(sqlite_store));
+
+        let applied = send(address, &["deposit, 1, 1, 10"]);
+        rusqlite::Connection::open(&path)
+            .unwrap()
+            .execute("DROP TABLE transactions", [])
+            .unwrap();
+        let failed = send(address, &["deposit, 1, 2, 10", "deposit, 1, 3, 10"]);
+
+        assert_eq!(applied, ["applied"]);
+        assert!(failed
+            .iter()
+            .all(|response| response.starts_with("error failed to save a transaction: ")));
+    }
+}
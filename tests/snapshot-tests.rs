@@ -0,0 +1,237 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::ClientId;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Server;
+    use payments_engine::Snapshots;
+    use payments_engine::Transaction;
+    use payments_engine::TransactionType;
+    use std::io;
+    use std::io::Write;
+    use std::net::TcpListener;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use std::thread;
+    use std::time::Duration;
+    use std::time::Instant;
+
+    // Writes each snapshot to a buffer of its own.
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<Vec<u8>>>>);
+
+    impl Written {
+        fn snapshots(&self) -> Snapshots {
+            let written = self.clone();
+            Snapshots::new(move |_sequence| {
+                written.0.lock().unwrap().push(Vec::new());
+                Ok(Box::new(written.clone()))
+            })
+        }
+
+        fn snapshots_written(&self) -> Vec<String> {
+            let snapshots = self.0.lock().unwrap();
+            snapshots
+                .iter()
+                .map(|snapshot| String::from_utf8(snapshot.clone()).unwrap())
+                .collect()
+        }
+    }
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            let mut snapshots = self.0.lock().unwrap();
+            snapshots.last_mut().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn deposit(id: u32, client_id: ClientId, amount: &str) -> Transaction {
+        Transaction {
+            id,
+            tx_type: TransactionType::Deposit,
+            client_id,
+            amount: Some(amount.parse().unwrap()),
+            destination_client_id: None,
+            timestamp: None,
+        }
+    }
+
+    const HEADER: &str = "client,available,held,total,locked\n";
+
+    #[test]
+    fn writes_snapshot_every_interval() {
+        let written = Written::default();
+        let mut payments_engine =
+            PaymentsEngine::new().with_snapshots(written.snapshots().with_interval(2));
+
+        for id in 1..=5 {
+            payments_engine
+                .process_transaction(deposit(id, 1, "1"))
+                .unwrap();
+        }
+
+        assert_eq!(
+            written.snapshots_written(),
+            [
+                format!("{}1,2.0000,0.0000,2.0000,false\n", HEADER),
+                format!("{}1,4.0000,0.0000,4.0000,false\n", HEADER),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_snapshot_once_triggered() {
+        let written = Written::default();
+        let trigger = Arc::new(AtomicBool::new(false));
+        let mut payments_engine = PaymentsEngine::new()
+            .with_snapshots(written.snapshots().with_trigger(Arc::clone(&trigger)));
+
+        payments_engine
+            .process_transaction(deposit(1, 1, "1"))
+            .unwrap();
+        trigger.store(true, Ordering::Relaxed);
+        payments_engine
+            .process_transaction(deposit(2, 1, "1"))
+            .unwrap();
+        payments_engine
+            .process_transaction(deposit(3, 1, "1"))
+            .unwrap();
+
+        assert_eq!(
+            written.snapshots_written(),
+            [format!("{}1,2.0000,0.0000,2.0000,false\n", HEADER)]
+        );
+        assert!(!trigger.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn writes_snapshot_of_idle_server_once_triggered() {
+        let written = Written::default();
+        let trigger = Arc::new(AtomicBool::new(false));
+        let server = Server::new(
+            PaymentsEngine::new()
+                .with_snapshots(written.snapshots().with_trigger(Arc::clone(&trigger))),
+        );
+        let payments_engine = server.payments_engine();
+        payments_engine
+            .lock()
+            .unwrap()
+            .process_transaction(deposit(1, 1, "1"))
+            .unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        thread::spawn(move || server.serve(listener));
+        payments_engine::watch_snapshot_trigger(&payments_engine, Duration::from_millis(10))
+            .unwrap();
+
+        // No transaction comes in after the trigger is set.
+        trigger.store(true, Ordering::Relaxed);
+        let started = Instant::now();
+        while written.snapshots_written().is_empty() && started.elapsed() < Duration::from_secs(5) {
+            thread::sleep(Duration::from_millis(10));
+        }
+
+        assert_eq!(
+            written.snapshots_written(),
+            [format!("{}1,1.0000,0.0000,1.0000,false\n", HEADER)]
+        );
+        assert!(!trigger.load(Ordering::Relaxed));
+    }
+
+    #[test]
+    fn stops_watching_trigger_once_engine_dropped() {
+        let written = Written::default();
+        let trigger = Arc::new(AtomicBool::new(false));
+        let payments_engine = Arc::new(Mutex::new(
+            PaymentsEngine::new().with_snapshots(written.snapshots().with_trigger(trigger)),
+        ));
+        let watcher =
+            payments_engine::watch_snapshot_trigger(&payments_engine, Duration::from_millis(10));
+
+        drop(payments_engine);
+
+        watcher.unwrap().join().unwrap();
+        let without_trigger = Arc::new(Mutex::new(
+            PaymentsEngine::new().with_snapshots(written.snapshots()),
+        ));
+        assert!(payments_engine::watch_snapshot_trigger(
+            &without_trigger,
+            Duration::from_millis(10)
+        )
+        .is_none());
+    }
+
+    #[test]
+    fn writes_only_changed_accounts_in_delta() {
+        let written = Written::default();
+        let mut payments_engine =
+            PaymentsEngine::new().with_snapshots(written.snapshots().with_interval(2).with_delta());
+
+        payments_engine
+            .process_transaction(deposit(1, 1, "1"))
+            .unwrap();
+        payments_engine
+            .process_transaction(deposit(2, 1, "2"))
+            .unwrap();
+        payments_engine
+            .process_transaction(deposit(3, 2, "5"))
+            .unwrap();
+        // Rejected, so not changing the account of client 1.
+        let withdrawal = Transaction {
+            tx_type: TransactionType::Withdrawal,
+            ..deposit(4, 1, "5")
+        };
+        payments_engine.process_transaction(withdrawal).unwrap_err();
+
+        assert_eq!(
+            written.snapshots_written(),
+            [
+                format!("{}1,3.0000,0.0000,3.0000,false\n", HEADER),
+                format!("{}2,5.0000,0.0000,5.0000,false\n", HEADER),
+            ]
+        );
+    }
+
+    #[test]
+    fn writes_snapshot_on_demand() {
+        let written = Written::default();
+        let mut payments_engine = PaymentsEngine::new().with_snapshots(written.snapshots());
+
+        payments_engine
+            .process_transaction(deposit(1, 1, "1"))
+            .unwrap();
+        payments_engine.write_snapshot().unwrap();
+
+        assert_eq!(
+            written.snapshots_written(),
+            [format!("{}1,1.0000,0.0000,1.0000,false\n", HEADER)]
+        );
+        assert!(PaymentsEngine::new().write_snapshot().is_err());
+    }
+
+    #[test]
+    fn fails_processing_when_snapshot_fails() {
+        let snapshots =
+            Snapshots::new(|_sequence| Err(io::Error::other("disk full"))).with_interval(1);
+        let mut payments_engine = PaymentsEngine::new().with_snapshots(snapshots);
+
+        let result = payments_engine::process_csv(
+            "type,client,tx,amount\ndeposit,1,1,1\ndeposit,1,2,1".as_bytes(),
+            &mut payments_engine,
+        );
+
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "failed to write a snapshot: disk full"
+        );
+        assert_eq!(
+            payments_engine.account(1).unwrap().total(),
+            "2".parse().unwrap()
+        );
+    }
+}
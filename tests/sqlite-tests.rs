@@ -0,0 +1,387 @@
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::FeeSchedule;
+    use payments_engine::Invariant;
+    use payments_engine::Limits;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::SqliteStore;
+    use payments_engine::Transaction;
+    use rusqlite::Connection;
+    use std::path::PathBuf;
+    use std::str;
+
+    const HOUR: u64 = 60 * 60;
+    const DAY: u64 = 24 * HOUR;
+
+    fn db_path(name: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!(
+            "payments-engine-{}-{}.db",
+            name,
+            std::process::id()
+        ));
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{}", path.display(), suffix));
+        }
+        path
+    }
+
+    fn process(path: &PathBuf, input: &str) -> PaymentsEngine {
+        let mut payments_engine =
+            PaymentsEngine::new().with_sqlite_store(SqliteStore::open(path).unwrap());
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        payments_engine
+    }
+
+    fn with_daily_limit(path: &PathBuf) -> PaymentsEngine {
+        let limits = Limits::from_toml("[default]\nmax_daily_withdrawal = \"150\"").unwrap();
+        PaymentsEngine::new()
+            .with_limits(limits)
+            .with_sqlite_store(SqliteStore::open(path).unwrap())
+    }
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn account_states(payments_engine: &PaymentsEngine) -> String {
+        let mut output = Vec::new();
+        payments_engine::write_account_states_to_csv(payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn keeps_accounts_and_transactions() {
+        let path = db_path("keeps");
+        process(
+            &path,
+            "type,client,tx,amount
+            deposit,1,1,10
+            deposit,1,2,5
+            withdrawal,1,3,2.5
+            dispute,1,1,
+            dispute,1,2,
+            resolve,1,2,",
+        );
+
+        let connection = Connection::open(&path).unwrap();
+        let account: (String, String, String, bool) = connection
+            .query_row(
+                "SELECT available, held, total, locked FROM accounts WHERE client = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(
+            account,
+            (
+                "2.5000".to_string(),
+                "10.0000".to_string(),
+                "12.5000".to_string(),
+                false
+            )
+        );
+        let mut statement = connection
+            .prepare("SELECT tx, type, amount, state FROM transactions ORDER BY tx")
+            .unwrap();
+        let transactions: Vec<(u32, String, String, String)> = statement
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            transactions,
+            [
+                (1, "deposit".into(), "10.0000".into(), "disputed".into()),
+                (2, "deposit".into(), "5.0000".into(), "resolved".into()),
+                (3, "withdrawal".into(), "-2.5000".into(), "applied".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn resumes_from_kept_state() {
+        let path = db_path("resumes");
+        let first_run = process(
+            &path,
+            "type,client,tx,amount
+            deposit,1,1,10
+            deposit,1,2,5
+            dispute,1,1,",
+        );
+        drop(first_run);
+
+        let payments_engine = process(
+            &path,
+            "type,client,tx,amount
+            chargeback,1,1,
+            deposit,1,3,1",
+        );
+
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,true\n"
+        );
+        payments_engine.check_ledger().unwrap();
+        let reopened = PaymentsEngine::new().with_sqlite_store(SqliteStore::open(&path).unwrap());
+        assert_eq!(account_states(&reopened), account_states(&payments_engine));
+    }
+
+    #[test]
+    fn keeps_decimal_places_of_balances() {
+        let path = db_path("places");
+        drop(process(
+            &path,
+            "type,client,tx,amount
+            deposit,1,1,10",
+        ));
+
+        let reopened = PaymentsEngine::new().with_sqlite_store(SqliteStore::open(&path).unwrap());
+
+        assert_eq!(
+            account_states(&reopened),
+            "client,available,held,total,locked\n1,10.0000,0.0000,10.0000,false\n"
+        );
+    }
+
+    #[test]
+    fn skips_transactions_applied_before_restart() {
+        let path = db_path("idempotent");
+        let process_idempotent = |input: &str| {
+            let mut payments_engine = PaymentsEngine::new()
+                .with_sqlite_store(SqliteStore::open(&path).unwrap())
+                .with_idempotency();
+            payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+            payments_engine
+        };
+        drop(process_idempotent(
+            "type,client,tx,amount
+            deposit,1,1,10
+            dispute,1,1,",
+        ));
+
+        let payments_engine = process_idempotent(
+            "type,client,tx,amount
+            deposit,1,1,10
+            dispute,1,1,
+            resolve,1,1,
+            deposit,1,2,1",
+        );
+
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,11.0000,0.0000,11.0000,false\n"
+        );
+        assert_eq!(payments_engine.summary().duplicates, 2);
+    }
+
+    #[test]
+    fn answers_transactions_sent_again_after_restart_with_their_outcome() {
+        let path = db_path("outcomes");
+        let process_idempotent = |input: &str| {
+            let mut payments_engine = PaymentsEngine::new()
+                .with_idempotency()
+                .with_sqlite_store(SqliteStore::open(&path).unwrap());
+            payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+            payments_engine
+        };
+        let input = "type,client,tx,amount
+            withdrawal,1,1,5
+            deposit,1,2,10
+            dispute,1,2,
+            resolve,1,2,
+            dispute,1,2,";
+        let once = account_states(&process_idempotent(input));
+
+        let payments_engine = process_idempotent(input);
+
+        assert_eq!(
+            once,
+            "client,available,held,total,locked\n1,0.0000,10.0000,10.0000,false\n"
+        );
+        assert_eq!(account_states(&payments_engine), once);
+        assert_eq!(payments_engine.summary().duplicates, 5);
+        let connection = Connection::open(&path).unwrap();
+        let outcome: String = connection
+            .query_row("SELECT outcome FROM applied WHERE tx = 1", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(outcome, "insufficient_funds");
+    }
+
+    #[test]
+    fn disputes_transaction_again_in_overlapping_file_after_restart() {
+        let path = db_path("overlapping");
+        let process_idempotent = |input: &str| {
+            let mut payments_engine = PaymentsEngine::new()
+                .with_idempotency()
+                .with_sqlite_store(SqliteStore::open(&path).unwrap());
+            payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+            payments_engine
+        };
+        drop(process_idempotent(
+            "type,client,tx,amount
+            deposit,1,1,10
+            deposit,1,2,5
+            dispute,1,1,
+            resolve,1,1,",
+        ));
+
+        let payments_engine = process_idempotent(
+            "type,client,tx,amount
+            deposit,1,2,5
+            resolve,1,1,
+            dispute,1,1,
+            chargeback,1,1,",
+        );
+
+        assert_eq!(
+            account_states(&payments_engine),
+            "client,available,held,total,locked\n1,5.0000,0.0000,5.0000,true\n"
+        );
+        assert_eq!(payments_engine.summary().duplicates, 2);
+    }
+
+    #[test]
+    fn keeps_fees_on_house_account() {
+        let path = db_path("fees");
+        let fee_schedule = FeeSchedule::from_toml(
+            r#"
+            house_account = 9
+
+            [tiers.default]
+            deposit = { flat = "1" }
+            "#,
+        )
+        .unwrap();
+        let mut payments_engine = PaymentsEngine::new()
+            .with_fee_schedule(fee_schedule)
+            .with_sqlite_store(SqliteStore::open(&path).unwrap());
+        payments_engine::process_csv(
+            "type,client,tx,amount\ndeposit,1,1,10".as_bytes(),
+            &mut payments_engine,
+        )
+        .unwrap();
+
+        let connection = Connection::open(&path).unwrap();
+        let house: (String, String) = connection
+            .query_row(
+                "SELECT a.total, t.type FROM accounts a JOIN transactions t USING (client)
+                WHERE client = 9",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(house, ("1.0000".to_string(), "fee".to_string()));
+    }
+
+    #[test]
+    fn keeps_limit_usage_across_restart() {
+        let path = db_path("limit-usage");
+        let mut payments_engine = with_daily_limit(&path);
+        payments_engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")).at(0))
+            .unwrap();
+        payments_engine
+            .process_transaction(Transaction::withdrawal(1, 2, amount("100")).at(HOUR))
+            .unwrap();
+        drop(payments_engine);
+
+        let mut reopened = with_daily_limit(&path);
+
+        assert_eq!(
+            reopened.process_transaction(Transaction::withdrawal(1, 3, amount("60")).at(DAY)),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+        assert_eq!(
+            reopened
+                .process_transaction(Transaction::withdrawal(1, 4, amount("60")).at(DAY + HOUR)),
+            Ok(())
+        );
+    }
+
+    #[test]
+    fn keeps_latest_timestamp_across_restart() {
+        let path = db_path("clock");
+        let mut payments_engine = with_daily_limit(&path);
+        payments_engine
+            .process_transaction(Transaction::deposit(1, 1, amount("500")).at(0))
+            .unwrap();
+        payments_engine
+            .process_transaction(Transaction::withdrawal(1, 2, amount("100")).at(HOUR))
+            .unwrap();
+        payments_engine
+            .process_transaction(Transaction::deposit(1, 3, amount("1")).at(3 * DAY))
+            .unwrap();
+        drop(payments_engine);
+
+        let mut reopened = with_daily_limit(&path);
+
+        // Without a timestamp, at the latest one kept - a day after the first withdrawal.
+        assert_eq!(
+            reopened.process_transaction(Transaction::withdrawal(1, 4, amount("100"))),
+            Ok(())
+        );
+        assert_eq!(
+            reopened.process_transaction(Transaction::withdrawal(1, 5, amount("60"))),
+            Err(Rejection::DailyWithdrawalLimitExceeded)
+        );
+    }
+
+    #[test]
+    fn keeps_what_invariants_are_checked_by_across_restart() {
+        let path = db_path("invariants");
+        process(
+            &path,
+            "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\n",
+        );
+        // Balances changed behind the engine's back, after the account was locked.
+        let connection = Connection::open(&path).unwrap();
+        connection
+            .execute_batch(
+                "UPDATE accounts SET total = '20.0000', locked = 1, changed_while_locked = 1",
+            )
+            .unwrap();
+
+        let reopened = PaymentsEngine::new().with_sqlite_store(SqliteStore::open(&path).unwrap());
+
+        let violations: Vec<_> = reopened
+            .check_invariants()
+            .into_iter()
+            .map(|violation| (violation.transaction_id, violation.invariant))
+            .collect();
+        assert_eq!(
+            violations,
+            [
+                (Some(2), Invariant::TotalMatchesBalances),
+                (Some(2), Invariant::LockedAccountUnchanged)
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_batch_run_at_first_transaction_failing_to_be_saved() {
+        let path = db_path("failed");
+        let mut payments_engine =
+            PaymentsEngine::new().with_sqlite_store(SqliteStore::open(&path).unwrap());
+        Connection::open(&path)
+            .unwrap()
+            .execute_batch("DROP TABLE clock")
+            .unwrap();
+
+        let result = payments_engine::process_csv(
+            "type,client,tx,amount\ndeposit,1,1,10\ndeposit,1,2,5\n".as_bytes(),
+            &mut payments_engine,
+        );
+
+        assert!(result
+            .unwrap_err()
+            .to_string()
+            .starts_with("failed to save a transaction"));
+        assert_eq!(payments_engine.account(1).unwrap().total(), amount("10"));
+    }
+}
@@ -0,0 +1,100 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::ClientId;
+    use payments_engine::FeeSchedule;
+    use payments_engine::PaymentsEngine;
+    use std::str;
+
+    const FEE_SCHEDULE: &str = r#"
+        house_account = 100
+
+        [tiers.default]
+        withdrawal = { flat = "0.5" }
+    "#;
+
+    fn statement(client_id: Option<ClientId>, input: &str) -> String {
+        let mut payments_engine = PaymentsEngine::new()
+            .with_fee_schedule(FeeSchedule::from_toml(FEE_SCHEDULE).unwrap())
+            .with_statement(client_id);
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        let mut output = Vec::new();
+        payments_engine::write_statement_to_csv(&payments_engine, &mut output).unwrap();
+        str::from_utf8(&output).unwrap().to_string()
+    }
+
+    #[test]
+    fn lists_transactions_with_running_balances() {
+        let output = statement(
+            None,
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 1, 2, 5.0
+            dispute, 1, 2,
+            resolve, 1, 2,",
+        );
+
+        assert_eq!(
+            output,
+            "client,tx,type,amount,fee,status,reason,available,held,total,locked
+1,1,deposit,10.0000,,applied,,10.0000,0.0000,10.0000,false
+1,2,deposit,5.0000,,applied,,15.0000,0.0000,15.0000,false
+1,2,dispute,,,applied,,10.0000,5.0000,15.0000,false
+1,2,resolve,,,applied,,15.0000,0.0000,15.0000,false
+"
+        );
+    }
+
+    #[test]
+    fn flags_rejected_transactions() {
+        let output = statement(
+            None,
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 20.0
+            dispute, 1, 3,",
+        );
+
+        assert_eq!(output.lines().count(), 4);
+        assert!(output.contains(
+            "1,2,withdrawal,20.0000,,rejected,insufficient_funds,10.0000,0.0000,10.0000,false\n"
+        ));
+        assert!(output
+            .contains("1,3,dispute,,,rejected,unknown_transaction,10.0000,0.0000,10.0000,false\n"));
+    }
+
+    #[test]
+    fn lists_transfers_for_both_clients_and_fees_for_house_account() {
+        let output = statement(
+            None,
+            "type, client, tx, amount, to
+            deposit, 1, 1, 10.0,
+            transfer, 1, 2, 4.0, 2
+            withdrawal, 2, 3, 1.0,",
+        );
+
+        assert_eq!(output.lines().count(), 6);
+        assert!(output.contains("1,2,transfer,4.0000,,applied,,6.0000,0.0000,6.0000,false\n"));
+        assert!(output.contains("2,2,transfer,4.0000,,applied,,4.0000,0.0000,4.0000,false\n"));
+        assert!(
+            output.contains("2,3,withdrawal,1.0000,0.5000,applied,,2.5000,0.0000,2.5000,false\n")
+        );
+        assert!(output.contains("100,3,fee,0.5000,,applied,,0.5000,0.0000,0.5000,false\n"));
+    }
+
+    #[test]
+    fn lists_only_given_client() {
+        let output = statement(
+            Some(2),
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 5.0
+            withdrawal, 2, 3, 6.0",
+        );
+
+        assert_eq!(output.lines().count(), 3);
+        assert!(output.contains("2,2,deposit,5.0000,,applied,,5.0000,0.0000,5.0000,false\n"));
+        assert!(output.contains(
+            "2,3,withdrawal,6.0000,,rejected,insufficient_funds,5.0000,0.0000,5.0000,false\n"
+        ));
+    }
+}
@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use payments_engine::Amount;
+    use payments_engine::PaymentsEngine;
+    use payments_engine::Rejection;
+    use payments_engine::Summary;
+    use payments_engine::TransactionType;
+    use payments_engine::TypeSummary;
+
+    fn amount(amount: &str) -> Amount {
+        amount.parse().unwrap()
+    }
+
+    fn summarize(input: &str) -> Summary {
+        let mut payments_engine = PaymentsEngine::new();
+        payments_engine::process_csv(input.as_bytes(), &mut payments_engine).unwrap();
+        payments_engine.summary()
+    }
+
+    #[test]
+    fn counts_applied_and_rejected_transactions_per_type() {
+        let summary = summarize(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 2, 2, 5.0
+            withdrawal, 1, 3, 20.0
+            withdrawal, 1, 4, 2.0
+            dispute, 1, 5,",
+        );
+
+        assert_eq!(summary.transactions, 5);
+        assert_eq!(summary.applied, 3);
+        assert_eq!(summary.rejected, 2);
+        assert_eq!(
+            summary.by_type[&TransactionType::Withdrawal],
+            TypeSummary {
+                applied: 1,
+                rejected: 1
+            }
+        );
+        assert!(!summary.by_type.contains_key(&TransactionType::Transfer));
+        assert_eq!(summary.rejections[&Rejection::InsufficientFunds], 1);
+        assert_eq!(summary.rejections[&Rejection::UnknownTransaction], 1);
+        assert!(summary.elapsed_seconds > 0.0);
+    }
+
+    #[test]
+    fn totals_moved_amounts() {
+        let summary = summarize(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            deposit, 1, 2, 5.5
+            withdrawal, 1, 3, 2.0
+            deposit, 2, 4, 3.0
+            dispute, 2, 4,
+            dispute, 1, 2,
+            chargeback, 1, 2,",
+        );
+
+        assert_eq!(summary.deposited, amount("18.5"));
+        assert_eq!(summary.withdrawn, amount("2"));
+        assert_eq!(summary.held, amount("3"));
+        assert_eq!(summary.charged_back, amount("5.5"));
+        assert_eq!(summary.locked_accounts, 1);
+    }
+
+    #[test]
+    fn describes_run() {
+        let summary = summarize(
+            "type, client, tx, amount
+            deposit, 1, 1, 10.0
+            withdrawal, 1, 2, 20.0",
+        );
+
+        let text = summary.to_string();
+        assert!(text.starts_with("transactions: 2 (1 applied, 1 rejected) in "));
+        assert!(text.contains("\n  deposit: 1 applied, 0 rejected\n"));
+        assert!(text.contains("\nrejections:\n  insufficient_funds: 1\n"));
+        assert!(text.ends_with("\nlocked accounts: 0\n"));
+    }
+}